@@ -0,0 +1,290 @@
+//! Run N in-process validators connected by an in-memory broadcast transport.
+//!
+//! ```text
+//! cargo run --features devnet --example devnet -- --validators 4 --heights 100
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use bytes::Bytes;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+
+use tendermint_state::smr::smr_types::{
+    CommitProof, SMREvent, SMRStatus, SMRTrigger, TriggerSource, TriggerType,
+};
+use tendermint_state::smr::{state_machine::StateMachine, Event};
+use tendermint_state::types::{Hash, Height, Round, VoteType, INIT_HEIGHT, INIT_ROUND};
+
+const DEFAULT_VALIDATORS: usize = 4;
+const DEFAULT_HEIGHTS: u64 = 100;
+
+/// Consensus messages broadcast between validators.
+#[derive(Clone, Debug)]
+enum Message {
+    Proposal {
+        height: Height,
+        round: Round,
+        hash: Hash,
+    },
+    Vote {
+        vote_type: VoteType,
+        height: Height,
+        round: Round,
+        hash: Hash,
+        voter: usize,
+    },
+}
+
+impl Message {
+    fn height(&self) -> Height {
+        match self {
+            Message::Proposal { height, .. } | Message::Vote { height, .. } => *height,
+        }
+    }
+}
+
+/// A validator drives its own SMR, broadcasts its proposals and votes and aggregates the votes
+/// of others into QCs.
+#[rustfmt::skip]
+struct Validator {
+    id:         usize,
+    smr:        StateMachine,
+    height:     Height,
+    max_height: Height,
+    rx_state:   Event,
+    rx_timer:   Event,
+    inbox:      UnboundedReceiver<Message>,
+    peers:      Vec<UnboundedSender<Message>>,
+    votes:      HashMap<(Height, Round, VoteType, Hash), HashSet<usize>>,
+    future:     Vec<Message>,
+    commits:    UnboundedSender<(usize, CommitProof)>,
+}
+
+impl Validator {
+    async fn run(mut self) {
+        self.goto_height(INIT_HEIGHT + 1);
+
+        loop {
+            tokio::select! {
+                Some(event) = self.rx_state.next() => {
+                    if !self.handle_event(event) {
+                        return;
+                    }
+                }
+                Some(msg) = self.inbox.next() => self.handle_message(msg),
+                // Every validator is honest and online, so nothing times out in the devnet.
+                Some(_) = self.rx_timer.next() => (),
+                else => return,
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: SMREvent) -> bool {
+        match event {
+            SMREvent::NewRoundInfo { height, round, .. }
+                if proposer(height, round, self.peers.len()) == self.id =>
+            {
+                let hash = Bytes::from(format!("block {} round {}", height, round));
+                self.broadcast(Message::Proposal {
+                    height,
+                    round,
+                    hash,
+                });
+            }
+            SMREvent::PrevoteVote {
+                height,
+                round,
+                block_hash,
+                ..
+            } => self.broadcast(Message::Vote {
+                vote_type: VoteType::Prevote,
+                height,
+                round,
+                hash: block_hash,
+                voter: self.id,
+            }),
+            SMREvent::PrecommitVote {
+                height,
+                round,
+                block_hash,
+                ..
+            } => self.broadcast(Message::Vote {
+                vote_type: VoteType::Precommit,
+                height,
+                round,
+                hash: block_hash,
+                voter: self.id,
+            }),
+            SMREvent::Commit {
+                height, block_hash, ..
+            } => {
+                // The dummy executor does nothing but report the commit.
+                let proof = CommitProof { height, block_hash };
+                if self.commits.unbounded_send((self.id, proof)).is_err()
+                    || self.height >= self.max_height
+                {
+                    return false;
+                }
+                self.goto_height(self.height.next());
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn handle_message(&mut self, msg: Message) {
+        if msg.height() > self.height {
+            self.future.push(msg);
+            return;
+        } else if msg.height() < self.height {
+            return;
+        }
+
+        let trigger = match msg {
+            Message::Proposal {
+                height,
+                round,
+                hash,
+            } => trigger(TriggerType::Proposal, hash, round, height),
+            Message::Vote {
+                vote_type,
+                height,
+                round,
+                hash,
+                voter,
+            } => {
+                let voters = self
+                    .votes
+                    .entry((height, round, vote_type.clone(), hash.clone()))
+                    .or_default();
+                // Only the vote reaching the quorum triggers the QC.
+                if !voters.insert(voter) || voters.len() != quorum(self.peers.len()) {
+                    return;
+                }
+                trigger(vote_type.into(), hash, round, height)
+            }
+        };
+
+        if let Err(e) = self.smr.process(trigger) {
+            log::warn!("devnet: validator {} process error {}", self.id, e);
+        }
+    }
+
+    fn goto_height(&mut self, height: Height) {
+        let status = SMRStatus::new(height);
+        let trigger_type = TriggerType::NewHeight(Box::new(status));
+        let new_height = trigger(trigger_type, Hash::new(), INIT_ROUND, self.height);
+        self.smr.process(new_height).expect("new height");
+        self.height = height;
+        self.votes.retain(|(h, ..), _| *h >= height);
+
+        for msg in std::mem::take(&mut self.future) {
+            self.handle_message(msg);
+        }
+    }
+
+    fn broadcast(&self, msg: Message) {
+        for peer in self.peers.iter() {
+            let _ = peer.unbounded_send(msg.clone());
+        }
+    }
+}
+
+fn proposer(height: Height, round: Round, validators: usize) -> usize {
+    ((height.0 + round.0) % validators as u64) as usize
+}
+
+fn quorum(validators: usize) -> usize {
+    validators * 2 / 3 + 1
+}
+
+fn trigger(trigger_type: TriggerType, hash: Hash, round: Round, height: Height) -> SMRTrigger {
+    SMRTrigger {
+        trigger_type,
+        source: TriggerSource::State,
+        hash,
+        lock_round: None,
+        round,
+        height,
+    }
+}
+
+fn parse_args() -> (usize, u64) {
+    let mut validators = DEFAULT_VALIDATORS;
+    let mut heights = DEFAULT_HEIGHTS;
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("missing value of {}", arg));
+        match arg.as_str() {
+            "--validators" => validators = value.parse().expect("invalid validators"),
+            "--heights" => heights = value.parse().expect("invalid heights"),
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    assert!(validators > 0, "at least one validator is required");
+    (validators, heights)
+}
+
+#[tokio::main]
+async fn main() {
+    let (validators, heights) = parse_args();
+    let (senders, inboxes): (Vec<_>, Vec<_>) = (0..validators).map(|_| unbounded()).unzip();
+    let (tx_commit, mut rx_commit) = unbounded();
+
+    for (id, inbox) in inboxes.into_iter().enumerate() {
+        let (smr, rx_state, rx_timer) = StateMachine::new();
+        let validator = Validator {
+            id,
+            smr,
+            height: INIT_HEIGHT,
+            max_height: Height(heights),
+            rx_state,
+            rx_timer,
+            inbox,
+            peers: senders.clone(),
+            votes: HashMap::new(),
+            future: Vec::new(),
+            commits: tx_commit.clone(),
+        };
+        tokio::spawn(validator.run());
+    }
+    drop(tx_commit);
+
+    let start = Instant::now();
+    let mut committed: HashMap<Height, (Hash, usize)> = HashMap::new();
+    let mut finalized = 0u64;
+
+    while let Some((id, proof)) = rx_commit.next().await {
+        let (hash, count) = committed
+            .entry(proof.height)
+            .or_insert_with(|| (proof.block_hash.clone(), 0));
+        assert_eq!(
+            hash, &proof.block_hash,
+            "fork at height {} from validator {}",
+            proof.height, id
+        );
+        *count += 1;
+
+        if *count == validators {
+            finalized += 1;
+            committed.remove(&proof.height);
+            if finalized == heights {
+                break;
+            }
+        }
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "{} validators committed {} heights in {:?}, {:.2} heights/s",
+        validators,
+        finalized,
+        elapsed,
+        finalized as f64 / elapsed.as_secs_f64()
+    );
+}
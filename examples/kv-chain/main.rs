@@ -0,0 +1,311 @@
+//! Run a chain of in-process validators executing a trivial key-value application.
+//!
+//! ```text
+//! cargo run --example kv-chain -- --validators 4 put a 1 put b 2 del a get b
+//! ```
+//!
+//! Every `put` and `del` command is a transaction committed in its own height, and every `get`
+//! reads the state after all the transactions are committed. The validators are connected by an
+//! in-memory transport delivering the messages in order, and each of them records its triggers in
+//! a WAL, which is replayed into a fresh state machine at the end to check recovery.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use bytes::Bytes;
+
+use tendermint_state::smr::smr_types::{
+    SMREvent, SMRStatus, SMRTrigger, TriggerSource, TriggerType,
+};
+use tendermint_state::smr::wal::{ReplayProgress, WalReplayer};
+use tendermint_state::smr::{state_machine::StateMachine, Event};
+use tendermint_state::types::{Hash, Height, Round, VoteType, INIT_HEIGHT};
+
+const DEFAULT_VALIDATORS: usize = 4;
+
+/// A key-value transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tx {
+    Put(String, String),
+    Del(String),
+}
+
+impl Tx {
+    /// The block of a single transaction is identified by its encoding.
+    fn hash(&self) -> Hash {
+        match self {
+            Tx::Put(key, value) => Bytes::from(format!("put {} {}", key, value)),
+            Tx::Del(key) => Bytes::from(format!("del {}", key)),
+        }
+    }
+}
+
+/// Consensus messages delivered by the in-memory transport.
+#[derive(Clone, Debug)]
+enum Message {
+    Proposal {
+        height: Height,
+        round: Round,
+        tx: Tx,
+    },
+    Vote {
+        vote_type: VoteType,
+        height: Height,
+        round: Round,
+        hash: Hash,
+        voter: usize,
+    },
+}
+
+/// The in-memory transport broadcasting every message to all validators, including the sender.
+#[derive(Default)]
+struct Transport {
+    queue: VecDeque<(usize, Message)>,
+}
+
+impl Transport {
+    fn broadcast(&mut self, validators: usize, msg: Message) {
+        for to in 0..validators {
+            self.queue.push_back((to, msg.clone()));
+        }
+    }
+}
+
+/// A validator drives its SMR, executes the committed transactions on its store and records its
+/// triggers in the WAL.
+#[rustfmt::skip]
+struct Validator {
+    id:         usize,
+    smr:        StateMachine,
+    rx_state:   Event,
+    rx_timer:   Event,
+    height:     Height,
+    blocks:     HashMap<Hash, Tx>,
+    votes:      HashMap<(Height, Round, VoteType, Hash), HashSet<usize>>,
+    store:      BTreeMap<String, String>,
+    wal:        Vec<SMRTrigger>,
+}
+
+impl Validator {
+    fn new(id: usize) -> Self {
+        let (smr, rx_state, rx_timer) = StateMachine::new();
+        Validator {
+            id,
+            smr,
+            rx_state,
+            rx_timer,
+            height: INIT_HEIGHT,
+            blocks: HashMap::new(),
+            votes: HashMap::new(),
+            store: BTreeMap::new(),
+            wal: Vec::new(),
+        }
+    }
+
+    fn process(&mut self, trigger: SMRTrigger) {
+        self.wal.push(trigger.clone());
+        if let Err(e) = self.smr.process(trigger) {
+            log::warn!("kv-chain: validator {} process error {}", self.id, e);
+        }
+    }
+
+    fn goto_height(&mut self, height: Height) {
+        self.process(SMRStatus::new(height).into());
+        self.height = height;
+        self.votes.retain(|(h, ..), _| *h >= height);
+    }
+
+    /// Handle the events thrown by the SMR. Return the height of a commit, if any.
+    fn handle_events(
+        &mut self,
+        validators: usize,
+        txs: &[Tx],
+        transport: &mut Transport,
+    ) -> Option<Height> {
+        // Every validator is honest and online, so nothing times out.
+        while self.rx_timer.try_next().is_some() {}
+
+        let mut committed = None;
+        while let Some(event) = self.rx_state.try_next() {
+            match event {
+                SMREvent::NewRoundInfo { height, round, .. }
+                    if proposer(height, round, validators) == self.id =>
+                {
+                    let tx = txs[(height.0 - 1) as usize].clone();
+                    transport.broadcast(validators, Message::Proposal { height, round, tx });
+                }
+                SMREvent::PrevoteVote {
+                    height,
+                    round,
+                    block_hash,
+                    ..
+                } => transport.broadcast(
+                    validators,
+                    Message::Vote {
+                        vote_type: VoteType::Prevote,
+                        height,
+                        round,
+                        hash: block_hash,
+                        voter: self.id,
+                    },
+                ),
+                SMREvent::PrecommitVote {
+                    height,
+                    round,
+                    block_hash,
+                    ..
+                } => transport.broadcast(
+                    validators,
+                    Message::Vote {
+                        vote_type: VoteType::Precommit,
+                        height,
+                        round,
+                        hash: block_hash,
+                        voter: self.id,
+                    },
+                ),
+                SMREvent::Commit {
+                    height, block_hash, ..
+                } => {
+                    let tx = self
+                        .blocks
+                        .remove(&block_hash)
+                        .expect("committed unknown block");
+                    execute(&mut self.store, tx);
+                    committed = Some(height);
+                }
+                _ => (),
+            }
+        }
+        committed
+    }
+
+    fn handle_message(&mut self, validators: usize, msg: Message) {
+        let trigger = match msg {
+            Message::Proposal { height, round, tx } => {
+                if height != self.height {
+                    return;
+                }
+                let hash = tx.hash();
+                self.blocks.insert(hash.clone(), tx);
+                trigger(TriggerType::Proposal, hash, round, height)
+            }
+            Message::Vote {
+                vote_type,
+                height,
+                round,
+                hash,
+                voter,
+            } => {
+                if height != self.height {
+                    return;
+                }
+                let voters = self
+                    .votes
+                    .entry((height, round, vote_type.clone(), hash.clone()))
+                    .or_default();
+                // Only the vote reaching the quorum triggers the QC.
+                if !voters.insert(voter) || voters.len() != validators * 2 / 3 + 1 {
+                    return;
+                }
+                trigger(vote_type.into(), hash, round, height)
+            }
+        };
+        self.process(trigger);
+    }
+}
+
+fn execute(store: &mut BTreeMap<String, String>, tx: Tx) {
+    match tx {
+        Tx::Put(key, value) => {
+            store.insert(key, value);
+        }
+        Tx::Del(key) => {
+            store.remove(&key);
+        }
+    }
+}
+
+fn proposer(height: Height, round: Round, validators: usize) -> usize {
+    ((height.0 + round.0) % validators as u64) as usize
+}
+
+fn trigger(trigger_type: TriggerType, hash: Hash, round: Round, height: Height) -> SMRTrigger {
+    SMRTrigger {
+        trigger_type,
+        source: TriggerSource::State,
+        hash,
+        lock_round: None,
+        round,
+        height,
+    }
+}
+
+/// Parse the validator count, the transactions and the keys to read from the client commands.
+fn parse_args() -> (usize, Vec<Tx>, Vec<String>) {
+    let mut validators = DEFAULT_VALIDATORS;
+    let (mut txs, mut gets) = (Vec::new(), Vec::new());
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut next = |name: &str| args.next().unwrap_or_else(|| panic!("missing {}", name));
+        match arg.as_str() {
+            "--validators" => validators = next("validators").parse().expect("invalid validators"),
+            "put" => txs.push(Tx::Put(next("key"), next("value"))),
+            "del" => txs.push(Tx::Del(next("key"))),
+            "get" => gets.push(next("key")),
+            _ => panic!("unknown command {}", arg),
+        }
+    }
+    assert!(validators > 0, "at least one validator is required");
+    (validators, txs, gets)
+}
+
+fn main() {
+    let (validators, txs, gets) = parse_args();
+    let mut nodes = (0..validators).map(Validator::new).collect::<Vec<_>>();
+    let mut transport = Transport::default();
+    let last_height = Height(txs.len() as u64);
+
+    if !txs.is_empty() {
+        nodes
+            .iter_mut()
+            .for_each(|node| node.goto_height(INIT_HEIGHT + 1));
+    }
+    loop {
+        for node in nodes.iter_mut() {
+            if let Some(height) = node.handle_events(validators, &txs, &mut transport) {
+                if height < last_height {
+                    node.goto_height(height.next());
+                }
+            }
+        }
+        match transport.queue.pop_front() {
+            Some((to, msg)) => nodes[to].handle_message(validators, msg),
+            None => break,
+        }
+    }
+
+    for node in nodes.iter() {
+        assert_eq!(node.store, nodes[0].store, "validator {} diverges", node.id);
+    }
+    for key in gets.iter() {
+        match nodes[0].store.get(key) {
+            Some(value) => println!("{} = {}", key, value),
+            None => println!("{} not found", key),
+        }
+    }
+
+    // Recover a validator from its WAL.
+    let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+    let mut replayer = WalReplayer::new(64, |_: &ReplayProgress| ());
+    let progress = replayer.replay(&mut smr, nodes[0].wal.clone());
+    assert_eq!(
+        progress.height,
+        nodes[0].smr.height(),
+        "WAL replay diverges"
+    );
+    println!(
+        "{} validators committed {} heights, WAL replayed {} triggers",
+        validators, last_height, progress.processed
+    );
+}
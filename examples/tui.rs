@@ -0,0 +1,174 @@
+//! Step through a WAL replay of the SMR in the terminal.
+//!
+//! ```text
+//! cargo run --features tui --example tui -- [wal.json]
+//! ```
+//!
+//! The WAL is a JSON array of triggers. Without a WAL, a demo scenario of a few heights is
+//! replayed. Press enter to apply the next trigger, `c` to run to the end and `q` to quit.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+use bytes::Bytes;
+
+use tendermint_state::smr::smr_types::{SMREvent, SMRTrigger};
+use tendermint_state::smr::{state_machine::StateMachine, Event};
+use tendermint_state::testing::scenario::scenario;
+use tendermint_state::types::VoteType;
+
+const RECENT_EVENTS: usize = 10;
+
+/// The replay session, which keeps the last events and the pending timer of the SMR.
+#[rustfmt::skip]
+struct Session {
+    smr:      StateMachine,
+    rx_state: Event,
+    rx_timer: Event,
+    entries:  VecDeque<SMRTrigger>,
+    applied:  usize,
+    events:   VecDeque<String>,
+    timer:    Option<String>,
+    last:     Option<String>,
+}
+
+impl Session {
+    fn step(&mut self) -> bool {
+        let trigger = match self.entries.pop_front() {
+            Some(trigger) => trigger,
+            None => return false,
+        };
+
+        let summary = format!(
+            "{} from {} height {} round {}",
+            trigger.trigger_type, trigger.source, trigger.height, trigger.round
+        );
+        self.last = Some(match self.smr.process(trigger) {
+            Ok(()) => summary,
+            Err(e) => format!("{} rejected: {}", summary, e),
+        });
+        self.applied += 1;
+
+        while let Some(event) = self.rx_state.try_next() {
+            if self.events.len() == RECENT_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(event.to_string());
+        }
+        while let Some(event) = self.rx_timer.try_next() {
+            if let Some(timer) = pending_timer(&event) {
+                self.timer = Some(timer);
+            }
+        }
+        true
+    }
+
+    fn render(&self) {
+        let view = self.smr.view_handle().load();
+        let mut out = String::from("\x1b[2J\x1b[H");
+        out += &format!(
+            "applied {}, remaining {}\n\n",
+            self.applied,
+            self.entries.len()
+        );
+        out += &format!(
+            "height {}  round {}  step {}\n",
+            view.height, view.round, view.step
+        );
+        out += &match &view.lock {
+            Some(lock) => format!(
+                "lock   round {} hash 0x{}\n",
+                lock.round,
+                hummer::coding::hex_encode(&lock.hash)
+            ),
+            None => "lock   none\n".to_string(),
+        };
+        out += &format!("timer  {}\n", self.timer.as_deref().unwrap_or("none"));
+        out += &format!(
+            "last   {}\n\nrecent events:\n",
+            self.last.as_deref().unwrap_or("none")
+        );
+        for event in self.events.iter() {
+            out += &format!("  {}\n", event);
+        }
+        out += "\n[enter] step  [c] continue  [q] quit > ";
+
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+/// Return the timer armed by the timer event, if any.
+fn pending_timer(event: &SMREvent) -> Option<String> {
+    match event {
+        SMREvent::NewRoundInfo { height, round, .. } => {
+            Some(format!("propose of height {} round {}", height, round))
+        }
+        SMREvent::PrevoteVote { height, round, .. } => {
+            Some(format!("prevote of height {} round {}", height, round))
+        }
+        SMREvent::PrecommitVote { height, round, .. } => {
+            Some(format!("precommit of height {} round {}", height, round))
+        }
+        SMREvent::Commit { .. } => Some("none".to_string()),
+        _ => None,
+    }
+}
+
+fn demo() -> Vec<SMRTrigger> {
+    let hash = Bytes::from(vec![1u8]);
+    let mut wal = scenario();
+    for height in 1..=3 {
+        wal = wal
+            .new_height(height)
+            .timer_propose()
+            .qc(VoteType::Prevote, Bytes::new())
+            .qc(VoteType::Precommit, Bytes::new())
+            .proposal(hash.clone())
+            .qc(VoteType::Prevote, hash.clone())
+            .qc(VoteType::Precommit, hash.clone());
+    }
+    wal.build()
+}
+
+fn main() {
+    let entries = match std::env::args().nth(1) {
+        Some(path) => {
+            let file = std::fs::File::open(&path).expect("open wal");
+            serde_json::from_reader(file).expect("invalid wal")
+        }
+        None => demo(),
+    };
+
+    let (smr, rx_state, rx_timer) = StateMachine::new();
+    let mut session = Session {
+        smr,
+        rx_state,
+        rx_timer,
+        entries: entries.into(),
+        applied: 0,
+        events: VecDeque::new(),
+        timer: None,
+        last: None,
+    };
+
+    let mut lines = io::stdin().lock().lines();
+    let mut stepping = true;
+    loop {
+        session.render();
+        if stepping {
+            match lines.next() {
+                Some(Ok(line)) if line.trim() == "q" => return,
+                Some(Ok(line)) if line.trim() == "c" => stepping = false,
+                Some(Ok(_)) => (),
+                _ => return,
+            }
+        }
+        if !session.step() {
+            session.render();
+            println!();
+            return;
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::error::ConsensusError;
+use crate::identity::{check_address, ADDRESS_LEN};
+use crate::types::{Address, ConsensusResult, Height, HexFmt, Round};
+
+/// A fixed set of equally weighted authorities for permissioned networks. The membership never
+/// changes, so there is no weight math or set update to handle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticAuthoritySet {
+    authorities: Vec<Address>,
+}
+
+impl StaticAuthoritySet {
+    /// Create the set from the configured addresses, which must be unique and well-formed.
+    pub fn new(authorities: Vec<Address>) -> ConsensusResult<Self> {
+        if authorities.is_empty() {
+            return Err(ConsensusError::Other("Empty authority set".to_string()));
+        }
+
+        let mut seen = HashSet::new();
+        for address in authorities.iter() {
+            check_address(address, ADDRESS_LEN)?;
+            if !seen.insert(address) {
+                return Err(ConsensusError::Other(format!(
+                    "Duplicate authority {:?}",
+                    HexFmt(address)
+                )));
+            }
+        }
+        Ok(StaticAuthoritySet { authorities })
+    }
+
+    /// Return the number of authorities.
+    pub fn len(&self) -> usize {
+        self.authorities.len()
+    }
+
+    /// Return whether the set is empty, which is never true for a created set.
+    pub fn is_empty(&self) -> bool {
+        self.authorities.is_empty()
+    }
+
+    /// Return whether the address is an authority.
+    pub fn contains(&self, address: &Address) -> bool {
+        self.authorities.contains(address)
+    }
+
+    /// Return the number of votes of a quorum, which is more than two thirds of the set.
+    pub fn quorum(&self) -> usize {
+        self.authorities.len() * 2 / 3 + 1
+    }
+
+    /// Return whether the voters reach a quorum. Non-authorities and duplicates are not counted.
+    pub fn has_quorum<'a, I>(&self, voters: I) -> bool
+    where
+        I: IntoIterator<Item = &'a Address>,
+    {
+        let voters = voters
+            .into_iter()
+            .filter(|address| self.contains(address))
+            .collect::<HashSet<_>>();
+        voters.len() >= self.quorum()
+    }
+
+    /// Return the proposer of the round, chosen round robin from the height.
+    pub fn proposer(&self, height: Height, round: Round) -> &Address {
+        let index = height.0.wrapping_add(round.0) % self.authorities.len() as u64;
+        &self.authorities[index as usize]
+    }
+}
+
+/// Detect that more than one third of the voting power of a static authority set has not voted
+/// for a number of consecutive rounds. The caller observes the votes of each round and reports
+/// the result through `StateMachine::report_quorum_unavailable`.
+#[rustfmt::skip]
+#[derive(Clone, Debug)]
+pub struct QuorumMonitor {
+    set:      StaticAuthoritySet,
+    rounds:   u64,
+    voted:    HashSet<Address>,
+    silent:   HashMap<Address, u64>,
+    reported: bool,
+}
+
+impl QuorumMonitor {
+    /// Create a monitor treating an authority silent for `rounds` consecutive rounds as offline.
+    pub fn new(set: StaticAuthoritySet, rounds: u64) -> ConsensusResult<Self> {
+        if rounds == 0 {
+            return Err(ConsensusError::Other(
+                "Zero quorum monitor rounds".to_string(),
+            ));
+        }
+
+        Ok(QuorumMonitor {
+            set,
+            rounds,
+            voted: HashSet::new(),
+            silent: HashMap::new(),
+            reported: false,
+        })
+    }
+
+    /// Observe a vote of the current round. Votes of non-authorities are ignored.
+    pub fn observe(&mut self, voter: &Address) {
+        if self.set.contains(voter) {
+            self.voted.insert(voter.clone());
+        }
+    }
+
+    /// End the current round. Return the missing power and the offline authorities once the
+    /// quorum becomes unavailable. It is reported again only after the quorum recovers.
+    pub fn end_round(&mut self) -> Option<(u64, Vec<Address>)> {
+        let voted = std::mem::take(&mut self.voted);
+        let mut offline = Vec::new();
+        for address in self.set.authorities.iter() {
+            let silent = self.silent.entry(address.clone()).or_insert(0);
+            *silent = if voted.contains(address) {
+                0
+            } else {
+                *silent + 1
+            };
+            if *silent >= self.rounds {
+                offline.push(address.clone());
+            }
+        }
+
+        // Every authority has one vote, so the power is the number of authorities.
+        let unavailable = offline.len() * 3 > self.set.len();
+        if !unavailable {
+            self.reported = false;
+            return None;
+        } else if self.reported {
+            return None;
+        }
+        self.reported = true;
+        Some((offline.len() as u64, offline))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::error::ConsensusError;
+    use crate::identity::ADDRESS_LEN;
+    use crate::types::{Address, Height, Round};
+
+    use super::{QuorumMonitor, StaticAuthoritySet};
+
+    fn address(byte: u8) -> Address {
+        Bytes::from(vec![byte; ADDRESS_LEN])
+    }
+
+    #[test]
+    fn test_static_authority_set() {
+        assert!(StaticAuthoritySet::new(Vec::new()).is_err());
+        assert!(StaticAuthoritySet::new(vec![address(1), address(1)]).is_err());
+        assert_eq!(
+            StaticAuthoritySet::new(vec![Bytes::from(vec![1u8])]),
+            Err(ConsensusError::InvalidAddress)
+        );
+
+        let set = StaticAuthoritySet::new((1..=4).map(address).collect()).unwrap();
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.quorum(), 3);
+        assert!(set.contains(&address(2)));
+        assert!(!set.contains(&address(5)));
+
+        let voters = [address(1), address(2), address(2), address(5)];
+        assert!(!set.has_quorum(voters.iter()));
+        let voters = [address(1), address(2), address(3)];
+        assert!(set.has_quorum(voters.iter()));
+
+        assert_eq!(set.proposer(Height(1), Round(0)), &address(2));
+        assert_eq!(set.proposer(Height(1), Round(3)), &address(1));
+        assert_eq!(set.proposer(Height(u64::MAX), Round(1)), &address(1));
+    }
+
+    #[test]
+    fn test_quorum_monitor() {
+        let set = StaticAuthoritySet::new((1..=4).map(address).collect()).unwrap();
+        assert!(QuorumMonitor::new(set.clone(), 0).is_err());
+        let mut monitor = QuorumMonitor::new(set, 2).unwrap();
+
+        // One offline authority is tolerated.
+        for _ in 0..3 {
+            (1..=3).for_each(|byte| monitor.observe(&address(byte)));
+            assert!(monitor.end_round().is_none());
+        }
+
+        // Two offline authorities for two rounds make the quorum unavailable.
+        (1..=2).for_each(|byte| monitor.observe(&address(byte)));
+        assert!(monitor.end_round().is_none());
+        (1..=2).for_each(|byte| monitor.observe(&address(byte)));
+        monitor.observe(&address(9));
+        assert_eq!(monitor.end_round(), Some((2, vec![address(3), address(4)])));
+        assert!(monitor.end_round().is_none());
+
+        // Reported again after recovering.
+        (1..=4).for_each(|byte| monitor.observe(&address(byte)));
+        assert!(monitor.end_round().is_none());
+        monitor.end_round();
+        assert_eq!(monitor.end_round().map(|(power, _)| power), Some(4));
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::ConsensusError;
+use crate::smr::smr_types::CommitProof;
+use crate::types::{ConsensusResult, Hash, Hasher, Height};
+
+/// A bundle of the block hashes committed in consecutive heights. The digest chains all of them,
+/// so a light client only needs to trust the digest to verify any of the bundled commits.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The first height of the checkpoint.
+    pub start_height: Height,
+    /// The block hashes from the first height.
+    pub block_hashes: Vec<Hash>,
+    /// The chained digest of the block hashes.
+    pub digest: Hash,
+}
+
+impl Checkpoint {
+    /// Return the last height of the checkpoint.
+    pub fn end_height(&self) -> Height {
+        self.start_height + (self.block_hashes.len() as u64).saturating_sub(1)
+    }
+
+    /// Verify that the digest chains the block hashes.
+    pub fn verify<H: Hasher>(&self, hasher: &H) -> ConsensusResult<()> {
+        if chain_digest(hasher, self.start_height, &self.block_hashes) != self.digest {
+            return Err(ConsensusError::CommitProofErr(format!(
+                "Checkpoint digest mismatch from height {}",
+                self.start_height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Return whether the commit proof is bundled in the checkpoint. The checkpoint itself must be
+    /// verified before.
+    pub fn contains(&self, proof: &CommitProof) -> bool {
+        proof
+            .height
+            .0
+            .checked_sub(self.start_height.0)
+            .and_then(|index| self.block_hashes.get(index as usize))
+            .is_some_and(|hash| *hash == proof.block_hash)
+    }
+}
+
+/// Collect the commit proofs of consecutive heights into a checkpoint every `interval` heights.
+#[rustfmt::skip]
+pub struct CheckpointBuilder<H> {
+    hasher:      H,
+    interval:    u64,
+    proofs:      Vec<CommitProof>,
+    last_height: Option<Height>,
+}
+
+impl<H: Hasher> CheckpointBuilder<H> {
+    /// Create a builder bundling `interval` heights into a checkpoint.
+    pub fn new(hasher: H, interval: u64) -> ConsensusResult<Self> {
+        if interval == 0 {
+            return Err(ConsensusError::Other(
+                "Zero checkpoint interval".to_string(),
+            ));
+        }
+
+        Ok(CheckpointBuilder {
+            hasher,
+            interval,
+            proofs: Vec::new(),
+            last_height: None,
+        })
+    }
+
+    /// Add the commit proof of the next height. Return the checkpoint once `interval` proofs are
+    /// collected.
+    pub fn push(&mut self, proof: CommitProof) -> ConsensusResult<Option<Checkpoint>> {
+        if let Some(last_height) = self.last_height {
+            if proof.height != last_height.next() {
+                return Err(ConsensusError::CommitProofErr(format!(
+                    "Height {} does not follow {}",
+                    proof.height, last_height
+                )));
+            }
+        }
+
+        self.last_height = Some(proof.height);
+        self.proofs.push(proof);
+        if (self.proofs.len() as u64) < self.interval {
+            return Ok(None);
+        }
+
+        let start_height = self.proofs[0].height;
+        let block_hashes = self
+            .proofs
+            .drain(..)
+            .map(|proof| proof.block_hash)
+            .collect::<Vec<_>>();
+        let digest = chain_digest(&self.hasher, start_height, &block_hashes);
+        Ok(Some(Checkpoint {
+            start_height,
+            block_hashes,
+            digest,
+        }))
+    }
+}
+
+/// Chain the block hashes as `digest = hash(digest || height || block hash)` from an empty
+/// digest.
+fn chain_digest<H: Hasher>(hasher: &H, start_height: Height, block_hashes: &[Hash]) -> Hash {
+    block_hashes
+        .iter()
+        .enumerate()
+        .fold(Hash::new(), |digest, (index, hash)| {
+            let height = start_height + index as u64;
+            let mut bytes = digest.to_vec();
+            bytes.extend_from_slice(&height.0.to_be_bytes());
+            bytes.extend_from_slice(hash);
+            hasher.hash(&bytes)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::smr::smr_types::CommitProof;
+    use crate::testing::mocks::MockHasher;
+    use crate::types::Height;
+
+    use super::CheckpointBuilder;
+
+    fn proof(height: u64) -> CommitProof {
+        CommitProof {
+            height: Height(height),
+            block_hash: Bytes::from(vec![height as u8]),
+        }
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        assert!(CheckpointBuilder::new(MockHasher::new(), 0).is_err());
+
+        let mut builder = CheckpointBuilder::new(MockHasher::new(), 3).unwrap();
+        assert!(builder.push(proof(1)).unwrap().is_none());
+        assert!(builder.push(proof(3)).is_err());
+        assert!(builder.push(proof(2)).unwrap().is_none());
+        let checkpoint = builder.push(proof(3)).unwrap().unwrap();
+
+        assert_eq!(checkpoint.start_height, Height(1));
+        assert_eq!(checkpoint.end_height(), Height(3));
+        assert!(checkpoint.verify(&MockHasher::new()).is_ok());
+        assert!((1..=3).all(|height| checkpoint.contains(&proof(height))));
+        assert!(!checkpoint.contains(&proof(4)));
+        assert!(!checkpoint.contains(&proof(0)));
+
+        let mut forged = checkpoint.clone();
+        forged.block_hashes[1] = Bytes::from(vec![9u8]);
+        assert!(forged.verify(&MockHasher::new()).is_err());
+
+        // The next checkpoint starts from the following height.
+        assert!(builder.push(proof(5)).is_err());
+        assert!(builder.push(proof(4)).unwrap().is_none());
+    }
+}
@@ -2,81 +2,111 @@
 use std::cmp::{Eq, PartialEq};
 use std::error::Error;
 
+use bytes::Bytes;
 use derive_more::Display;
 
+use crate::types::HexFmt;
+
 /// Consensus error.
 #[derive(Clone, Debug, Display)]
 pub enum ConsensusError {
-    ///
+    /// Malformed peer address.
     #[display(fmt = "Invalid address")]
     InvalidAddress,
-    ///
+    /// Event channel error.
     #[display(fmt = "Channel error {:?}", _0)]
     ChannelErr(String),
-    ///
+    /// Trigger SMR error.
     #[display(fmt = "Trigger {} SMR error", _0)]
     TriggerSMRErr(String),
-    ///
+    /// Monitor event error.
     #[display(fmt = "Monitor {} event error", _0)]
     MonitorEventErr(String),
-    ///
+    /// Throw event error.
     #[display(fmt = "Throw {} event error", _0)]
     ThrowEventErr(String),
-    ///
+    /// Proposal error.
     #[display(fmt = "Proposal error {}", _0)]
     ProposalErr(String),
-    ///
+    /// Prevote error.
     #[display(fmt = "Prevote error {}", _0)]
     PrevoteErr(String),
-    ///
+    /// Precommit error.
     #[display(fmt = "Precommit error {}", _0)]
     PrecommitErr(String),
-    ///
+    /// Brake error.
     #[display(fmt = "Brake error {}", _0)]
     BrakeErr(String),
-    ///
+    /// The round of a vote differs from self round.
     #[display(fmt = "Self round is {}, vote round is {}", local, vote)]
     RoundDiff {
-        ///
+        /// Self round.
         local: u64,
-        ///
+        /// Vote round.
         vote: u64,
     },
-    ///
+    /// Two different blocks are committed or locked in the same round.
+    #[display(
+        fmt = "Fork detected in round {}, local hash {}, remote hash {}",
+        round,
+        "HexFmt(local_hash)",
+        "HexFmt(remote_hash)"
+    )]
+    ForkDetected {
+        /// The round of the fork.
+        round: u64,
+        /// The local block hash.
+        local_hash: Bytes,
+        /// The remote block hash.
+        remote_hash: Bytes,
+    },
+    /// Self check error.
     #[display(fmt = "Self check not pass {}", _0)]
     SelfCheckErr(String),
-    ///
+    /// Correctness error.
     #[display(fmt = "Correctness error {}", _0)]
     CorrectnessErr(String),
-    ///
+    /// Timer error.
     #[display(fmt = "Timer error {}", _0)]
     TimerErr(String),
-    ///
+    /// State error.
     #[display(fmt = "State error {}", _0)]
     StateErr(String),
-    ///
+    /// Multiple proposals in the same height and round.
     #[display(fmt = "Multiple proposal in height {}, round {}", _0, _1)]
     MultiProposal(u64, u64),
-    ///
+    /// Storage error.
     #[display(fmt = "Storage error {}", _0)]
     StorageErr(String),
-    ///
+    /// Save WAL error.
     #[display(fmt = "Save Wal error {}, {}, {} step", height, round, step)]
     SaveWalErr {
-        ///
+        /// WAL height.
         height: u64,
-        ///
+        /// WAL round.
         round: u64,
-        ///
+        /// WAL step.
         step: String,
     },
-    ///
+    /// Load WAL error.
     #[display(fmt = "Load Wal error {}", _0)]
     LoadWalErr(String),
-    ///
+    /// Commit proof error.
+    #[display(fmt = "Commit proof error {}", _0)]
+    CommitProofErr(String),
+    /// Arithmetic overflow.
+    #[display(fmt = "Arithmetic overflow {}", _0)]
+    OverflowErr(String),
+    /// Parameter change error.
+    #[display(fmt = "Parameter change error {}", _0)]
+    ParamChangeErr(String),
+    /// Duration config error.
+    #[display(fmt = "Duration config error {}", _0)]
+    DurationConfigErr(String),
+    /// Crypto error.
     #[display(fmt = "Crypto error {}", _0)]
     CryptoErr(String),
-    ///
+    /// Aggregated signature error.
     #[display(fmt = "Aggregated signature error {}", _0)]
     AggregatedSignatureErr(String),
     /// Other error.
@@ -90,8 +120,8 @@ impl Error for ConsensusError {}
 impl PartialEq for ConsensusError {
     fn eq(&self, other: &Self) -> bool {
         use self::ConsensusError::{
-            CorrectnessErr, InvalidAddress, MonitorEventErr, Other, PrecommitErr, PrevoteErr,
-            ProposalErr, RoundDiff, SelfCheckErr, ThrowEventErr, TriggerSMRErr,
+            CorrectnessErr, ForkDetected, InvalidAddress, MonitorEventErr, Other, PrecommitErr,
+            PrevoteErr, ProposalErr, RoundDiff, SelfCheckErr, ThrowEventErr, TriggerSMRErr,
         };
         match (self, other) {
             // If compare objects are the following types of error, as long as the error type need
@@ -107,6 +137,18 @@ impl PartialEq for ConsensusError {
             // If it is the following two types of errors, in the judgment, the error type need the
             // same, and the error information need the same.
             (RoundDiff { local: m, vote: n }, RoundDiff { local: p, vote: q }) => m == p && n == q,
+            (
+                ForkDetected {
+                    round: m,
+                    local_hash: a,
+                    remote_hash: b,
+                },
+                ForkDetected {
+                    round: n,
+                    local_hash: c,
+                    remote_hash: d,
+                },
+            ) => m == n && a == c && b == d,
             (Other(x), Other(y)) | (CorrectnessErr(x), CorrectnessErr(y)) => x == y,
             _ => false,
         }
@@ -0,0 +1,191 @@
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::error::ConsensusError;
+use crate::types::{Address, ConsensusResult, Hasher, HexFmt};
+
+/// The length of an address in bytes.
+pub const ADDRESS_LEN: usize = 20;
+
+/// Derive the address of a public key, which is the last `ADDRESS_LEN` bytes of its hash.
+pub fn derive_address<H: Hasher>(hasher: &H, pub_key: &[u8]) -> ConsensusResult<Address> {
+    let hash = hasher.hash(pub_key);
+    if hash.len() < ADDRESS_LEN {
+        return Err(ConsensusError::CryptoErr(format!(
+            "Hash length {} is shorter than address length {}",
+            hash.len(),
+            ADDRESS_LEN
+        )));
+    }
+    Ok(hash.slice(hash.len() - ADDRESS_LEN..))
+}
+
+/// Check that the address has the expected length.
+pub fn check_address(address: &[u8], len: usize) -> ConsensusResult<()> {
+    if address.len() != len {
+        return Err(ConsensusError::InvalidAddress);
+    }
+    Ok(())
+}
+
+/// Display an address as the checksummed hex. A letter of the hex is uppercase if the
+/// corresponding nibble of the hash of the lowercase hex is at least 8.
+#[rustfmt::skip]
+pub struct Checksummed<'a, H> {
+    hasher:  &'a H,
+    address: &'a Address,
+}
+
+impl<'a, H: Hasher> Checksummed<'a, H> {
+    /// Wrap the address to display.
+    pub fn new(hasher: &'a H, address: &'a Address) -> Self {
+        Checksummed { hasher, address }
+    }
+}
+
+impl<H: Hasher> fmt::Display for Checksummed<'_, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = HexFmt(self.address).to_string();
+        write!(f, "0x{}", checksum(self.hasher, &hex))
+    }
+}
+
+/// Parse an address from the hex with an optional `0x` prefix. The checksum is verified if the
+/// hex is in mixed case.
+pub fn parse_address<H: Hasher>(hasher: &H, s: &str) -> ConsensusResult<Address> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    check_address(hex.as_bytes(), ADDRESS_LEN * 2)?;
+
+    let lower = hex.to_ascii_lowercase();
+    let address = hex_decode(&lower).ok_or(ConsensusError::InvalidAddress)?;
+    let mixed_case = hex != lower && hex != hex.to_ascii_uppercase();
+    if mixed_case && checksum(hasher, &lower) != hex {
+        return Err(ConsensusError::InvalidAddress);
+    }
+    Ok(Bytes::from(address))
+}
+
+/// Decode the lowercase hex of an even length.
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    let nibble = |c: u8| match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    };
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| Some((nibble(pair[0])? << 4) | nibble(*pair.get(1)?)?))
+        .collect()
+}
+
+fn checksum<H: Hasher>(hasher: &H, lower_hex: &str) -> String {
+    let hash = hasher.hash(lower_hex.as_bytes());
+    lower_hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble =
+                hash.get(i / 2)
+                    .map_or(0, |byte| if i % 2 == 0 { byte >> 4 } else { byte & 0x0f });
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::error::ConsensusError;
+    use crate::types::{Hash, Hasher};
+
+    use super::{check_address, derive_address, parse_address, Checksummed, ADDRESS_LEN};
+
+    /// A toy hasher repeating the xor of the bytes into 32 bytes.
+    struct XorHasher;
+
+    impl Hasher for XorHasher {
+        fn hash(&self, bytes: &[u8]) -> Hash {
+            let xor = bytes.iter().fold(0u8, |acc, b| acc ^ b);
+            Bytes::from(
+                (0..32u8)
+                    .map(|i| xor.wrapping_add(i.wrapping_mul(17)))
+                    .collect::<Vec<_>>(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_derive_address() {
+        let address = derive_address(&XorHasher, &[1, 2, 3]).unwrap();
+        assert_eq!(address.len(), ADDRESS_LEN);
+        assert_eq!(address, XorHasher.hash(&[1, 2, 3]).slice(12..));
+
+        struct ShortHasher;
+        impl Hasher for ShortHasher {
+            fn hash(&self, _bytes: &[u8]) -> Hash {
+                Bytes::from(vec![0u8; 8])
+            }
+        }
+        assert!(matches!(
+            derive_address(&ShortHasher, &[1, 2, 3]),
+            Err(ConsensusError::CryptoErr(_))
+        ));
+    }
+
+    #[test]
+    fn test_checksum_round_trip() {
+        let address = derive_address(&XorHasher, &[1, 2, 3]).unwrap();
+        let hex = Checksummed::new(&XorHasher, &address).to_string();
+        assert!(hex.starts_with("0x"));
+        assert_eq!(hex.len(), 2 + ADDRESS_LEN * 2);
+        assert_eq!(parse_address(&XorHasher, &hex).unwrap(), address);
+
+        // Single case hex skips the checksum.
+        let lower = hex.to_ascii_lowercase();
+        assert_eq!(parse_address(&XorHasher, &lower).unwrap(), address);
+        assert_eq!(parse_address(&XorHasher, &lower[2..]).unwrap(), address);
+
+        // Flip the case of one letter to break the checksum.
+        let index = hex[2..].find(|c: char| c.is_ascii_alphabetic()).unwrap() + 2;
+        let mut broken = hex.clone().into_bytes();
+        broken[index] ^= 0x20;
+        let broken = String::from_utf8(broken).unwrap();
+        assert_eq!(
+            parse_address(&XorHasher, &broken),
+            Err(ConsensusError::InvalidAddress)
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(
+            parse_address(&XorHasher, "0x1234"),
+            Err(ConsensusError::InvalidAddress)
+        );
+        let not_hex = "zz".repeat(ADDRESS_LEN);
+        assert_eq!(
+            parse_address(&XorHasher, &not_hex),
+            Err(ConsensusError::InvalidAddress)
+        );
+    }
+
+    #[test]
+    fn test_check_address() {
+        assert!(check_address(&[0u8; ADDRESS_LEN], ADDRESS_LEN).is_ok());
+        assert!(check_address(&[0u8; 32], 32).is_ok());
+        assert_eq!(
+            check_address(&[], ADDRESS_LEN),
+            Err(ConsensusError::InvalidAddress)
+        );
+        assert_eq!(
+            check_address(&[0u8; ADDRESS_LEN + 1], ADDRESS_LEN),
+            Err(ConsensusError::InvalidAddress)
+        );
+    }
+}
@@ -1,6 +1,80 @@
+//! The API is split into two tiers. The core tier, which is the SMR, its types, the errors and
+//! the identity helpers, is stable across minor releases. The unstable tier, which is the runners
+//! and the experimental modules, is behind the default `unstable` feature and may change in any
+//! release. Only the signatures pinned by `test_core_api` are checked automatically. There is no
+//! snapshot of the whole public API, so the type fields, the trait impls and the feature-gated
+//! items are kept stable by review.
+
+/// Static authority set module for permissioned networks. Unstable.
+#[cfg(feature = "unstable")]
+pub mod authority;
+/// Checkpoint module to bundle the commits of consecutive heights. Unstable.
+#[cfg(feature = "unstable")]
+pub mod checkpoint;
+/// Error module.
+pub mod error;
+/// Validator identity module to derive, display and parse addresses.
+pub mod identity;
 /// State machine replicas module to do state changes.
 pub mod smr;
+/// Testing utilities for downstream crates.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
 /// Message types using in the overlord consensus protocol.
 pub mod types;
-/// Error module.
-pub mod error;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::error::ConsensusError;
+    use crate::identity::{check_address, ADDRESS_LEN};
+    use crate::smr::smr_types::{
+        ChannelKind, ErrorEvent, Lock, QcValue, SMREvent, SMRStatus, SMRTrigger, SmrView, Step,
+        TriggerSource, TriggerType,
+    };
+    use crate::smr::{state_machine::StateMachine, Event, Receiver, SmrViewHandle};
+    use crate::types::{
+        Address, ConsensusResult, DurationConfig, GenesisConfig, Hash, Height, Round, VoteType,
+    };
+
+    /// Pin the signatures of the most used core API. This is not a snapshot of the whole public
+    /// API, and the fields, the trait impls and the feature-gated items are not checked. A change
+    /// that fails to compile here breaks the downstream chains and must wait for a major release.
+    #[test]
+    fn test_core_api() {
+        let _: fn() -> (StateMachine, Event, Event) = StateMachine::new;
+        let _: fn(GenesisConfig) -> (StateMachine, Event, Event) = StateMachine::with_genesis;
+        let _: fn(GenesisConfig, ChannelKind, ChannelKind) -> (StateMachine, Event, Event) =
+            StateMachine::with_channels;
+        let _: fn(&mut StateMachine, SMRTrigger) -> ConsensusResult<()> = StateMachine::process;
+        let _: fn(&mut StateMachine, SMRTrigger, Option<Address>) -> ConsensusResult<()> =
+            StateMachine::process_from;
+        let _: fn(&mut StateMachine, Round, Hash, Hash) -> ConsensusResult<()> =
+            StateMachine::adopt_lock;
+        let _: fn(&mut StateMachine, Height, Round, Hash, Hash) -> ConsensusResult<()> =
+            StateMachine::set_lock;
+        let _: fn(&mut StateMachine, bool) = StateMachine::set_strict_commit;
+        let _: fn(&mut StateMachine, Option<Duration>) = StateMachine::set_watchdog;
+        let _: fn(&mut StateMachine) -> Receiver<ErrorEvent> = StateMachine::subscribe_errors;
+        let _: fn(&StateMachine) -> SmrViewHandle = StateMachine::view_handle;
+        let _: fn(&StateMachine) -> Height = StateMachine::height;
+        let _: fn(&StateMachine) -> Round = StateMachine::round;
+        let _: fn(&StateMachine) -> &Step = StateMachine::step;
+        let _: fn(&StateMachine) -> &Hash = StateMachine::block_hash;
+        let _: fn(&StateMachine) -> Option<&Lock> = StateMachine::lock;
+        let _: fn(&mut Event) -> Option<SMREvent> = Event::try_next;
+
+        let _: fn(Height) -> SMRStatus = SMRStatus::new;
+        let _: fn(VoteType, QcValue, Round, Height) -> SMRTrigger = SMRTrigger::qc;
+        let _: fn(u64, u64, u64, u64) -> DurationConfig = DurationConfig::new;
+        let _: fn(&[u8], usize) -> ConsensusResult<()> = check_address;
+        let _: usize = ADDRESS_LEN;
+
+        let view = SmrView::default();
+        let _: (Height, Round, Step, Option<Lock>) =
+            (view.height, view.round, view.step, view.lock);
+        let _ = (TriggerType::ContinueRound, TriggerSource::Timer);
+        let _ = ConsensusError::InvalidAddress;
+    }
+}
@@ -0,0 +1,265 @@
+use crate::smr::smr_types::{Lock, LockCause, SMREvent, SMRState, Step};
+use crate::types::{Hash, Height, Round};
+
+/// An append-only archive of the state events thrown by the SMR. The SMR state at any past
+/// height and round is rebuilt by folding the events, for time-travel debugging and audits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Archive {
+    events: Vec<SMREvent>,
+}
+
+impl Archive {
+    /// Create an empty archive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an event received from the state event channel, in the order of receiving.
+    pub fn record(&mut self, event: SMREvent) {
+        self.events.push(event);
+    }
+
+    /// Return the archived events.
+    pub fn events(&self) -> &[SMREvent] {
+        &self.events
+    }
+
+    /// Return the number of archived events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Return whether the archive is empty.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Rebuild the SMR state at the end of the given round of the given height, which is the state
+    /// after the last archived event of the round. Return `None` if the SMR never entered the
+    /// round according to the archive.
+    pub fn reconstruct_state_at(&self, height: Height, round: Round) -> Option<SMRState> {
+        let mut state: Option<SMRState> = None;
+        for event in self.events.iter() {
+            if let Some(view) = event_view(event) {
+                if view > (height, round) {
+                    break;
+                }
+            }
+            apply(&mut state, event);
+        }
+        state.filter(|state| (state.height, state.round) == (height, round))
+    }
+}
+
+impl From<Vec<SMREvent>> for Archive {
+    fn from(events: Vec<SMREvent>) -> Self {
+        Archive { events }
+    }
+}
+
+/// Return the height and round of the event, or `None` if it does not carry them.
+fn event_view(event: &SMREvent) -> Option<(Height, Round)> {
+    match event {
+        SMREvent::NewRoundInfo { height, round, .. }
+        | SMREvent::PrevoteVote { height, round, .. }
+        | SMREvent::PrecommitVote { height, round, .. }
+        | SMREvent::Brake { height, round, .. }
+        | SMREvent::LockChanged { height, round, .. } => Some((*height, *round)),
+        // The activation is thrown before the new round info of the height, so it belongs to the
+        // height rather than the end of the previous one.
+        SMREvent::ParamChangeActivated { height, .. } => Some((*height, Round(0))),
+        _ => None,
+    }
+}
+
+/// Fold an event into the state. The events before the first new round info are ignored, since
+/// the state is unknown until then.
+fn apply(state: &mut Option<SMRState>, event: &SMREvent) {
+    if let SMREvent::NewRoundInfo {
+        height,
+        round,
+        lock_round,
+        lock_proposal,
+        ..
+    } = event
+    {
+        let lock = lock_round
+            .zip(lock_proposal.clone())
+            .map(|(round, hash)| Lock { round, hash });
+        let (commit_seq, params_version, max_round) =
+            state.as_ref().map_or((0, 0, None), |state| {
+                (state.commit_seq, state.params_version, state.max_round)
+            });
+        *state = Some(SMRState {
+            height: *height,
+            round: *round,
+            step: Step::Propose,
+            block_hash: lock
+                .as_ref()
+                .map_or_else(Hash::new, |lock| lock.hash.clone()),
+            lock,
+            commit_seq,
+            params_version,
+            max_round,
+        });
+        return;
+    }
+
+    let state = match state {
+        Some(state) => state,
+        None => return,
+    };
+    match event {
+        SMREvent::PrevoteVote { block_hash, .. } => {
+            state.step = Step::Prevote;
+            state.block_hash = block_hash.clone();
+        }
+        SMREvent::PrecommitVote { block_hash, .. } => {
+            state.step = Step::Precommit;
+            // A locked SMR keeps the lock as the proposal while precommitting nil.
+            if state.lock.is_none() {
+                state.block_hash = block_hash.clone();
+            }
+        }
+        SMREvent::Brake { .. } => state.step = Step::Brake,
+        // The lock removed by a new height is still held at the end of the previous height.
+        SMREvent::LockChanged {
+            cause: LockCause::NewHeight,
+            ..
+        } => (),
+        SMREvent::LockChanged { new, .. } => {
+            if let Some(lock) = new {
+                state.block_hash = lock.hash.clone();
+            }
+            state.lock = new.clone();
+        }
+        SMREvent::Commit {
+            block_hash,
+            commit_seq,
+            ..
+        } => {
+            state.step = Step::Commit;
+            state.block_hash = block_hash.clone();
+            state.commit_seq = *commit_seq;
+        }
+        SMREvent::ParamChangeActivated {
+            version, max_round, ..
+        } => {
+            state.params_version = *version;
+            if max_round.is_some() {
+                state.max_round = *max_round;
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use bytes::Bytes;
+
+    use crate::smr::smr_types::{SMRState, SMRStatus, SMRTrigger, Step};
+    use crate::smr::state_machine::StateMachine;
+    use crate::testing::scenario::scenario;
+    use crate::types::{Height, ParamChange, Round, VoteType};
+
+    use super::Archive;
+
+    #[test]
+    fn test_reconstruct_state() {
+        let hash = Bytes::from(vec![1u8]);
+        let other_hash = Bytes::from(vec![2u8]);
+        let triggers = scenario()
+            .new_height(1)
+            .proposal(hash.clone())
+            .qc(VoteType::Prevote, hash.clone())
+            .qc(VoteType::Precommit, Bytes::new())
+            .proposal_with_lock(other_hash.clone(), 0)
+            .timer_prevote()
+            .timer_precommit()
+            .timer_propose()
+            .qc(VoteType::Prevote, Bytes::new())
+            .qc(VoteType::Precommit, Bytes::new())
+            .proposal(other_hash.clone())
+            .qc(VoteType::Prevote, other_hash.clone())
+            .qc(VoteType::Precommit, other_hash)
+            .new_height(2)
+            .proposal(hash.clone())
+            .build();
+
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let (archive, expected) = record(&mut smr, triggers);
+        assert!(expected.len() > 4);
+        for ((height, round), state) in expected {
+            assert_eq!(archive.reconstruct_state_at(height, round), Some(state));
+        }
+        assert_eq!(archive.reconstruct_state_at(Height(1), Round(9)), None);
+        assert_eq!(
+            Archive::new().reconstruct_state_at(Height(1), Round(0)),
+            None
+        );
+
+        // A round ending in the brake step.
+        let mut triggers = scenario()
+            .new_height(1)
+            .proposal(hash.clone())
+            .qc(VoteType::Prevote, hash)
+            .timer_precommit()
+            .build();
+        triggers.push(SMRTrigger::timeout(Step::Brake, Round(0), Height(1)).unwrap());
+        triggers.push(SMRTrigger::continue_round(Round(1), Height(1)));
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        smr.set_brake(true);
+        let (archive, expected) = record(&mut smr, triggers);
+        let state = archive.reconstruct_state_at(Height(1), Round(0)).unwrap();
+        assert_eq!(state.step, Step::Brake);
+        assert_eq!(Some(state), expected.get(&(Height(1), Round(0))).cloned());
+
+        // A parameter change belongs to its activation height.
+        let hash = Bytes::from(vec![3u8]);
+        let mut triggers = scenario()
+            .new_height(1)
+            .proposal(hash.clone())
+            .qc(VoteType::Prevote, hash.clone())
+            .qc(VoteType::Precommit, hash)
+            .new_height(2)
+            .build();
+        let mut status = SMRStatus::new(Height(1));
+        status.param_change = Some(ParamChange {
+            version: 1,
+            activation_height: Height(2),
+            new_interval: None,
+            new_config: None,
+            new_timeouts: None,
+            max_round: Some(Round(5)),
+        });
+        triggers[0] = SMRTrigger::from(status);
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let (archive, expected) = record(&mut smr, triggers);
+        let before = archive.reconstruct_state_at(Height(1), Round(0)).unwrap();
+        let after = archive.reconstruct_state_at(Height(2), Round(0)).unwrap();
+        assert_eq!((before.params_version, before.max_round), (0, None));
+        assert_eq!((after.params_version, after.max_round), (1, Some(Round(5))));
+        assert_eq!(Some(before), expected.get(&(Height(1), Round(0))).cloned());
+        assert_eq!(Some(after), expected.get(&(Height(2), Round(0))).cloned());
+    }
+
+    /// Record the state at the end of every round as the expectation.
+    fn record(
+        smr: &mut StateMachine,
+        triggers: Vec<SMRTrigger>,
+    ) -> (Archive, BTreeMap<(Height, Round), SMRState>) {
+        let mut archive = Archive::new();
+        let mut expected = BTreeMap::new();
+        for trigger in triggers {
+            if let Ok(events) = smr.process_sync(trigger) {
+                events.into_iter().for_each(|event| archive.record(event));
+            }
+            let state = smr.export_state();
+            expected.insert((state.height, state.round), state);
+        }
+        (archive, expected)
+    }
+}
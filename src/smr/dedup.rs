@@ -0,0 +1,241 @@
+use std::collections::btree_map::{BTreeMap, Entry};
+
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::smr::smr_types::SMREvent;
+use crate::types::{Address, Hash, Height, HexFmt, Round};
+
+/// The evidence of a proposer proposing two different blocks in the same round.
+#[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq)]
+#[display(
+    fmt = "Equivocation of proposer {:?} in height {}, round {}, first {:?}, second {:?}",
+    "HexFmt(proposer)",
+    height,
+    round,
+    "HexFmt(first)",
+    "HexFmt(second)"
+)]
+pub struct EquivocationEvidence {
+    /// The height of the proposals.
+    pub height: Height,
+    /// The round of the proposals.
+    pub round: Round,
+    /// The proposer of the proposals.
+    pub proposer: Address,
+    /// The hash of the first proposal.
+    pub first: Hash,
+    /// The hash of the conflicting proposal.
+    pub second: Hash,
+}
+
+/// The result of checking a proposal against the proposals seen before.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProposalCheck {
+    /// The first proposal of the proposer in the round, which is fed to the SMR.
+    New,
+    /// The same proposal as the first one, which is dropped.
+    Duplicate,
+    /// A proposal different from the first one, which is rejected and reported as the evidence.
+    Equivocation(EquivocationEvidence),
+}
+
+/// Remember the first valid proposal of each proposer per height and round, so that the state
+/// layer feeds only that one to the SMR. A later different proposal from the same proposer is
+/// turned into equivocation evidence instead, which closes spoofing a proposal of a round.
+#[derive(Clone, Debug, Default)]
+pub struct ProposalDedup {
+    seen: BTreeMap<(Height, Round, Address), Hash>,
+}
+
+impl ProposalDedup {
+    /// Create an empty deduplicator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check a verified proposal and remember it if it is the first one of the proposer in the
+    /// round.
+    pub fn check(
+        &mut self,
+        height: Height,
+        round: Round,
+        proposer: &Address,
+        hash: &Hash,
+    ) -> ProposalCheck {
+        let first = match self.seen.entry((height, round, proposer.clone())) {
+            Entry::Vacant(entry) => {
+                entry.insert(hash.clone());
+                return ProposalCheck::New;
+            }
+            Entry::Occupied(entry) if entry.get() == hash => return ProposalCheck::Duplicate,
+            Entry::Occupied(entry) => entry.get().clone(),
+        };
+
+        let evidence = EquivocationEvidence {
+            height,
+            round,
+            proposer: proposer.clone(),
+            first,
+            second: hash.clone(),
+        };
+        log::warn!("Tendermint: {}", evidence);
+        ProposalCheck::Equivocation(evidence)
+    }
+
+    /// Forget the proposals of the heights lower than the given one, which is the current height.
+    pub fn prune(&mut self, height: Height) {
+        self.seen = self.seen.split_off(&(height, Round(0), Address::new()));
+    }
+
+    /// Return the number of remembered proposals.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Return whether no proposal is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+/// Filter the commit events by their sequence number, so that the executor executes each commit
+/// exactly once although a restarted driver replaying the WAL throws a commit again. The executor
+/// persists the sequence number of the last executed commit along with its state, and restores the
+/// filter from it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommitDedup {
+    last_seq: u64,
+}
+
+impl CommitDedup {
+    /// Create a filter having executed the commits up to the given sequence number, which is zero
+    /// if none is executed.
+    pub fn new(last_seq: u64) -> Self {
+        CommitDedup { last_seq }
+    }
+
+    /// Return the sequence number of the last executed commit.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Return whether the event is a commit to execute, and remember it as executed if so. The
+    /// commits not later than the last executed one and the other events return `false`.
+    pub fn accept(&mut self, event: &SMREvent) -> bool {
+        match event {
+            SMREvent::Commit { commit_seq, .. } if *commit_seq > self.last_seq => {
+                self.last_seq = *commit_seq;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::smr::smr_types::{SMREvent, SMRStatus, SMRTrigger};
+    use crate::smr::state_machine::StateMachine;
+    use crate::types::{Height, Round, VoteType};
+
+    use super::{CommitDedup, EquivocationEvidence, ProposalCheck, ProposalDedup};
+
+    #[test]
+    fn test_proposal_dedup() {
+        let (alice, bob) = (Bytes::from(vec![1u8; 20]), Bytes::from(vec![2u8; 20]));
+        let (block, other) = (Bytes::from(vec![1u8]), Bytes::from(vec![2u8]));
+        let mut dedup = ProposalDedup::new();
+
+        let check = |dedup: &mut ProposalDedup, height, round, proposer, hash| {
+            dedup.check(Height(height), Round(round), proposer, hash)
+        };
+        assert_eq!(check(&mut dedup, 1, 0, &alice, &block), ProposalCheck::New);
+        assert_eq!(
+            check(&mut dedup, 1, 0, &alice, &block),
+            ProposalCheck::Duplicate
+        );
+        assert_eq!(
+            check(&mut dedup, 1, 0, &alice, &other),
+            ProposalCheck::Equivocation(EquivocationEvidence {
+                height: Height(1),
+                round: Round(0),
+                proposer: alice.clone(),
+                first: block.clone(),
+                second: other.clone(),
+            })
+        );
+        // The first proposal stays the one fed to the SMR.
+        assert_eq!(
+            check(&mut dedup, 1, 0, &alice, &block),
+            ProposalCheck::Duplicate
+        );
+
+        // Another proposer, round or height is independent.
+        assert_eq!(check(&mut dedup, 1, 0, &bob, &other), ProposalCheck::New);
+        assert_eq!(check(&mut dedup, 1, 1, &alice, &other), ProposalCheck::New);
+        assert_eq!(check(&mut dedup, 2, 0, &alice, &other), ProposalCheck::New);
+
+        dedup.prune(Height(2));
+        assert_eq!(dedup.len(), 1);
+        assert!(matches!(
+            check(&mut dedup, 2, 0, &alice, &block),
+            ProposalCheck::Equivocation(_)
+        ));
+        assert_eq!(check(&mut dedup, 1, 0, &alice, &other), ProposalCheck::New);
+    }
+
+    #[test]
+    fn test_commit_dedup() {
+        let commit = |commit_seq: u64| SMREvent::Commit {
+            height: Height(commit_seq),
+            round: Round(0),
+            block_hash: Bytes::from(vec![1u8]),
+            polc_round: Some(Round(0)),
+            commit_seq,
+        };
+        let mut dedup = CommitDedup::default();
+        assert!(dedup.accept(&commit(1)));
+        assert!(!dedup.accept(&commit(1)));
+        assert!(!dedup.accept(&SMREvent::Stop));
+        assert!(dedup.accept(&commit(2)));
+        assert_eq!(dedup.last_seq(), 2);
+
+        // A restarted executor replays the commits executed before the restart.
+        let mut dedup = CommitDedup::new(dedup.last_seq());
+        assert!(!dedup.accept(&commit(1)));
+        assert!(!dedup.accept(&commit(2)));
+        assert!(dedup.accept(&commit(3)));
+    }
+
+    #[test]
+    fn test_resume_commit_seq() {
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::builder()
+            .height(Height(4))
+            .commit_seq(3)
+            .build()
+            .unwrap();
+        let mut dedup = CommitDedup::new(3);
+
+        // The commit after resuming goes on from the last sequence number, so it is executed.
+        smr.process(SMRStatus::new(Height(5)).into()).unwrap();
+        let hash = Bytes::from(vec![1u8]);
+        smr.process(SMRTrigger::proposal(
+            hash.clone(),
+            None,
+            Round(0),
+            Height(5),
+        ))
+        .unwrap();
+        for vote_type in [VoteType::Prevote, VoteType::Precommit] {
+            let qc = SMRTrigger::qc(vote_type, hash.clone().into(), Round(0), Height(5));
+            smr.process(qc).unwrap();
+        }
+        let commits = std::iter::from_fn(|| rx_state.try_next())
+            .filter(|event| dedup.accept(event))
+            .count();
+        assert_eq!((commits, dedup.last_seq()), (1, 4));
+    }
+}
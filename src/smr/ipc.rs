@@ -0,0 +1,269 @@
+use std::io::{ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ConsensusError;
+use crate::smr::smr_types::{EventChannel, SMREvent, SMRTrigger};
+use crate::smr::state_machine::StateMachine;
+use crate::smr::Event;
+use crate::types::{ConsensusResult, Height, Round};
+
+/// The maximum length of a frame body. A longer frame is rejected before it is read, so a
+/// corrupted length prefix can not make the reader allocate unbounded memory.
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+/// A message between the driver and the SMR running in another process.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// A trigger from the driver to the SMR.
+    Trigger(SMRTrigger),
+    /// An event thrown by the SMR to the given channel.
+    Event(EventChannel, SMREvent),
+    /// A trigger rejected by the SMR.
+    Rejected {
+        /// The height of the trigger.
+        height: Height,
+        /// The round of the trigger.
+        round: Round,
+        /// The display of the consensus error.
+        error: String,
+    },
+}
+
+/// Write frames to a byte stream, such as a unix socket or the stdout. Each frame is a big endian
+/// `u32` length followed by the JSON encoding of the frame.
+#[derive(Debug)]
+pub struct FrameWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Create a writer over the byte stream.
+    pub fn new(inner: W) -> Self {
+        FrameWriter { inner }
+    }
+
+    /// Write and flush a frame.
+    pub fn write_frame(&mut self, frame: &Frame) -> ConsensusResult<()> {
+        let body = serde_json::to_vec(frame)
+            .map_err(|e| ConsensusError::ChannelErr(format!("Encode frame {}", e)))?;
+        let len = u32::try_from(body.len())
+            .ok()
+            .filter(|len| *len <= MAX_FRAME_LEN)
+            .ok_or_else(|| ConsensusError::ChannelErr(format!("Frame of {} bytes", body.len())))?;
+
+        self.inner
+            .write_all(&len.to_be_bytes())
+            .and_then(|_| self.inner.write_all(&body))
+            .and_then(|_| self.inner.flush())
+            .map_err(|e| ConsensusError::ChannelErr(format!("Write frame {}", e)))
+    }
+
+    /// Return the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Read the frames written by a `FrameWriter` from a byte stream.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner: R,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Create a reader over the byte stream.
+    pub fn new(inner: R) -> Self {
+        FrameReader { inner }
+    }
+
+    /// Read the next frame. Return `None` if the stream ends between frames, and an error if it
+    /// ends inside a frame.
+    pub fn read_frame(&mut self) -> ConsensusResult<Option<Frame>> {
+        let mut len = [0u8; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(ConsensusError::ChannelErr(format!("Read frame {}", e))),
+        }
+
+        let len = u32::from_be_bytes(len);
+        if len > MAX_FRAME_LEN {
+            return Err(ConsensusError::ChannelErr(format!(
+                "Frame of {} bytes",
+                len
+            )));
+        }
+        let mut body = vec![0u8; len as usize];
+        self.inner
+            .read_exact(&mut body)
+            .map_err(|e| ConsensusError::ChannelErr(format!("Read frame {}", e)))?;
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|e| ConsensusError::ChannelErr(format!("Decode frame {}", e)))
+    }
+
+    /// Return the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Run the SMR as the consensus process, serving the trigger frames read from `reader` until the
+/// stream ends. After each trigger, the thrown events, or the rejection, are written to `writer`.
+/// A frame other than a trigger from the driver is a protocol error.
+pub fn serve<R: Read, W: Write>(
+    smr: &mut StateMachine,
+    rx_state: &mut Event,
+    rx_timer: &mut Event,
+    reader: &mut FrameReader<R>,
+    writer: &mut FrameWriter<W>,
+) -> ConsensusResult<()> {
+    while let Some(frame) = reader.read_frame()? {
+        let trigger = match frame {
+            Frame::Trigger(trigger) => trigger,
+            frame => {
+                return Err(ConsensusError::ChannelErr(format!(
+                    "Unexpected frame {:?}",
+                    frame
+                )))
+            }
+        };
+
+        let (height, round) = (trigger.height, trigger.round);
+        if let Err(e) = smr.process(trigger) {
+            writer.write_frame(&Frame::Rejected {
+                height,
+                round,
+                error: e.to_string(),
+            })?;
+        }
+        while let Some(event) = rx_state.try_next() {
+            writer.write_frame(&Frame::Event(EventChannel::State, event))?;
+        }
+        while let Some(event) = rx_timer.try_next() {
+            writer.write_frame(&Frame::Event(EventChannel::Timer, event))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+
+    use crate::smr::smr_types::{EventChannel, SMREvent, SMRStatus, SMRTrigger};
+    use crate::smr::smr_types::{TriggerSource, TriggerType};
+    use crate::smr::state_machine::StateMachine;
+    use crate::types::{Height, Round};
+
+    use super::{serve, Frame, FrameReader, FrameWriter, MAX_FRAME_LEN};
+
+    fn trigger(trigger_type: TriggerType, height: u64) -> SMRTrigger {
+        SMRTrigger {
+            trigger_type,
+            source: TriggerSource::State,
+            hash: Bytes::from(vec![1u8]),
+            lock_round: None,
+            round: Round(0),
+            height: Height(height),
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let frames = [
+            Frame::Trigger(trigger(TriggerType::Proposal, 1)),
+            Frame::Event(EventChannel::Timer, SMREvent::Stop),
+        ];
+        let mut writer = FrameWriter::new(Vec::new());
+        frames
+            .iter()
+            .for_each(|frame| writer.write_frame(frame).unwrap());
+        let bytes = writer.into_inner();
+
+        let mut reader = FrameReader::new(Cursor::new(bytes.clone()));
+        assert_eq!(reader.read_frame().unwrap(), Some(frames[0].clone()));
+        assert_eq!(reader.read_frame().unwrap(), Some(frames[1].clone()));
+        assert_eq!(reader.read_frame().unwrap(), None);
+
+        // A stream ending inside a frame or an oversized frame is an error.
+        let mut reader = FrameReader::new(Cursor::new(bytes[..bytes.len() - 1].to_vec()));
+        reader.read_frame().unwrap();
+        assert!(reader.read_frame().is_err());
+        let oversized = (MAX_FRAME_LEN + 1).to_be_bytes().to_vec();
+        assert!(FrameReader::new(Cursor::new(oversized))
+            .read_frame()
+            .is_err());
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut writer = FrameWriter::new(Vec::new());
+        let status = SMRStatus::new(Height(1));
+        writer
+            .write_frame(&Frame::Trigger(trigger(
+                TriggerType::NewHeight(Box::new(status)),
+                0,
+            )))
+            .unwrap();
+        let mut timeout = trigger(
+            TriggerType::NewHeight(Box::new(SMRStatus::new(Height(2)))),
+            1,
+        );
+        timeout.source = TriggerSource::Timer;
+        writer.write_frame(&Frame::Trigger(timeout)).unwrap();
+        let mut reader = FrameReader::new(Cursor::new(writer.into_inner()));
+
+        let (mut smr, mut rx_state, mut rx_timer) = StateMachine::new();
+        let mut writer = FrameWriter::new(Vec::new());
+        serve(
+            &mut smr,
+            &mut rx_state,
+            &mut rx_timer,
+            &mut reader,
+            &mut writer,
+        )
+        .unwrap();
+        assert_eq!(smr.height(), Height(1));
+
+        let mut reader = FrameReader::new(Cursor::new(writer.into_inner()));
+        let mut frames = Vec::new();
+        while let Some(frame) = reader.read_frame().unwrap() {
+            frames.push(frame);
+        }
+        assert!(matches!(
+            frames[0],
+            Frame::Event(EventChannel::State, SMREvent::NewRoundInfo { .. })
+        ));
+        assert!(frames.iter().any(|frame| matches!(
+            frame,
+            Frame::Event(EventChannel::Timer, SMREvent::NewRoundInfo { .. })
+        )));
+        assert!(matches!(
+            frames.last(),
+            Some(Frame::Rejected {
+                height: Height(1),
+                ..
+            })
+        ));
+
+        // The SMR process only accepts triggers.
+        let mut writer = FrameWriter::new(Vec::new());
+        writer
+            .write_frame(&Frame::Event(EventChannel::State, SMREvent::Stop))
+            .unwrap();
+        let mut reader = FrameReader::new(Cursor::new(writer.into_inner()));
+        let mut writer = FrameWriter::new(Vec::new());
+        assert!(serve(
+            &mut smr,
+            &mut rx_state,
+            &mut rx_timer,
+            &mut reader,
+            &mut writer
+        )
+        .is_err());
+    }
+}
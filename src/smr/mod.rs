@@ -1,50 +1,553 @@
-///
+/// Log at debug level on the hot path of the SMR. The arguments are only evaluated if the debug
+/// level is enabled for the logger, and the whole log is compiled out with the `minimal-logging`
+/// feature.
+macro_rules! smr_debug {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "minimal-logging")) && log::log_enabled!(log::Level::Debug) {
+            log::debug!($($arg)+);
+        }
+    };
+}
+
+/// Archive of the state events to rebuild the past states of the SMR. Unstable.
+#[cfg(feature = "unstable")]
+pub mod archive;
+/// Deduplication of the proposals per round and of the commits replayed after a restart, and
+/// detection of equivocating proposers. Unstable.
+#[cfg(feature = "unstable")]
+pub mod dedup;
+/// Framed transport to run the SMR in a separate process from the driver. Unstable.
+#[cfg(feature = "ipc")]
+pub mod ipc;
+/// Deterministic simulation of validators over a network with random delays.
+#[cfg(test)]
+mod sim;
+/// SMR triggers, events, states and the other SMR types.
 pub mod smr_types;
-///
-mod state_machine;
+/// Single validator driver for local development chains. Unstable.
+#[cfg(feature = "runner")]
+pub mod solo;
+/// The state machine replica.
+pub mod state_machine;
+/// Timer setting the step timeouts from the timer events and feeding the timeout triggers back.
+/// Unstable.
+#[cfg(feature = "timer")]
+pub mod timer;
+/// Chunked and cancellable replay of the triggers recorded in the WAL. Unstable.
+#[cfg(feature = "wal")]
+pub mod wal;
 
+use std::collections::VecDeque;
+use std::future::poll_fn;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use futures_core::stream::{FusedStream, Stream};
+
+use crate::error::ConsensusError;
+use crate::smr::smr_types::{
+    ChannelKind, EventTime, GenericSMREvent, GenericSmrView, OnChannelClosed, TimestampMode,
+};
+use crate::types::{ConsensusHash, ConsensusResult, Hash};
+
+type TimedEvent<H> = (GenericSMREvent<H>, Option<EventTime>);
+
+/// Lock the mutex. No lock of the crate is held across a call which may panic and leave the value
+/// half updated, so a poisoned lock is taken as is.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Create an unbounded channel of the auxiliary SMR streams, such as the errors and the audit.
+pub(crate) fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared::default());
+    let sender = Sender(Arc::clone(&shared));
+    (sender, Receiver(shared))
+}
+
+/// Create an event channel of the given kind whose depth is measurable. The events are stamped by
+/// the given clock.
+pub(crate) fn event_channel<H>(
+    kind: ChannelKind,
+    clock: Clock,
+) -> (EventSender<H>, GenericEvent<H>) {
+    let shared = Arc::new(Shared::default());
+    let depth = ChannelDepth::default();
+    let sender = EventSender {
+        shared: Arc::clone(&shared),
+        kind,
+        on_closed: OnChannelClosed::default(),
+        depth: depth.clone(),
+    };
+    (sender, GenericEvent::new(shared, depth, clock))
+}
+
+/// The state shared by the sender and the receiver of a channel.
+#[rustfmt::skip]
+#[derive(Debug)]
+struct Shared<T> {
+    queue:           Mutex<VecDeque<T>>,
+    waker:           Mutex<Option<Waker>>,
+    sender_closed:   AtomicBool,
+    receiver_closed: AtomicBool,
+    dropped:         AtomicU64,
+}
+
+impl<T> Default for Shared<T> {
+    fn default() -> Self {
+        Shared {
+            queue: Mutex::new(VecDeque::new()),
+            waker: Mutex::new(None),
+            sender_closed: AtomicBool::new(false),
+            receiver_closed: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    fn pop(&self) -> Option<T> {
+        lock(&self.queue).pop_front()
+    }
+
+    fn push(&self, item: T) {
+        lock(&self.queue).push_back(item);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = lock(&self.waker).take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        self.sender_closed.store(true, Ordering::Release);
+        self.wake();
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.sender_closed.load(Ordering::Acquire) && lock(&self.queue).is_empty()
+    }
+
+    fn poll_pop(&self, cx: &mut Context) -> Poll<Option<T>> {
+        if let Some(item) = self.pop() {
+            return Poll::Ready(Some(item));
+        }
+
+        // Register before checking again, so that an item sent in between wakes the task.
+        *lock(&self.waker) = Some(cx.waker().clone());
+        if let Some(item) = self.pop() {
+            Poll::Ready(Some(item))
+        } else if self.sender_closed.load(Ordering::Acquire) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<H> Shared<TimedEvent<H>> {
+    fn pop_event(&self, depth: &ChannelDepth) -> Option<TimedEvent<H>> {
+        let item = self.pop();
+        if item.is_some() {
+            depth.decr();
+        }
+        item
+    }
+}
+
+/// The sender of an auxiliary SMR stream.
+#[derive(Debug)]
+pub(crate) struct Sender<T>(Arc<Shared<T>>);
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+impl<T> Sender<T> {
+    /// Send the item, or return it if the receiver is dropped.
+    pub(crate) fn send(&self, item: T) -> Result<(), T> {
+        if self.0.receiver_closed.load(Ordering::Acquire) {
+            return Err(item);
+        }
+        self.0.push(item);
+        Ok(())
+    }
+}
+
+/// The receiver of an auxiliary SMR stream, such as the errors, the diagnostics and the audit
+/// records. It is a stream which ends after the SMR drops the sender.
+#[derive(Debug)]
+pub struct Receiver<T>(Arc<Shared<T>>);
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.0.receiver_closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.0.poll_pop(cx)
+    }
+}
+
+impl<T> FusedStream for Receiver<T> {
+    fn is_terminated(&self) -> bool {
+        self.0.is_terminated()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next item, or `None` after the sender is dropped.
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.0.poll_pop(cx)).await
+    }
+
+    /// Receive an item if there is one in the channel without waiting.
+    pub fn try_next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+}
+
+/// A handle to read the latest SMR view from any thread. A read only clones an `Arc` under an
+/// uncontended read lock, so it never waits for the SMR to process a trigger.
+#[derive(Clone, Debug)]
+pub struct GenericSmrViewHandle<H>(Arc<RwLock<Arc<GenericSmrView<H>>>>);
+
+/// The view handle of the default hash type.
+pub type SmrViewHandle = GenericSmrViewHandle<Hash>;
+
+impl<H> Default for GenericSmrViewHandle<H> {
+    fn default() -> Self {
+        GenericSmrViewHandle(Arc::new(RwLock::new(Arc::new(GenericSmrView::default()))))
+    }
+}
+
+impl<H: ConsensusHash> GenericSmrViewHandle<H> {
+    /// Return the latest view.
+    pub fn load(&self) -> Arc<GenericSmrView<H>> {
+        Arc::clone(&self.0.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Publish the view if it changes.
+    pub(crate) fn publish(&self, view: GenericSmrView<H>) {
+        if *self.load() != view {
+            *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(view);
+        }
+    }
+}
+
+/// The monotonic clock shared by the SMR and its event channels.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Clock(Instant);
+
+impl Clock {
+    pub(crate) fn new() -> Self {
+        Clock(Instant::now())
+    }
+
+    /// Return the monotonic nanoseconds since the clock was created.
+    pub(crate) fn now(&self) -> u64 {
+        self.0.elapsed().as_nanos() as u64
+    }
+
+    pub(crate) fn stamp(&self, mode: TimestampMode) -> Option<EventTime> {
+        let wall_clock = match mode {
+            TimestampMode::Off => return None,
+            TimestampMode::Monotonic => None,
+            TimestampMode::WallClock => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|time| time.as_millis() as u64),
+        };
+
+        Some(EventTime {
+            emitted_at: self.now(),
+            wall_clock,
+        })
+    }
+}
+
+/// The upper bounds in microseconds of the latency histogram buckets. The last bucket counts the
+/// rest.
+pub const LATENCY_BUCKETS_US: [u64; 6] = [1, 10, 100, 1_000, 10_000, 100_000];
+
+/// A histogram of the latencies from the receipt of a trigger to the step transition it causes.
+#[rustfmt::skip]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS_US.len() + 1],
+    sum_ns:  u64,
+}
+
+impl LatencyHistogram {
+    pub(crate) fn record(&mut self, nanos: u64) {
+        let index = LATENCY_BUCKETS_US
+            .iter()
+            .position(|bound| nanos <= bound * 1_000)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        self.buckets[index] += 1;
+        self.sum_ns = self.sum_ns.saturating_add(nanos);
+    }
+
+    /// Return the counts of the buckets bounded by `LATENCY_BUCKETS_US`.
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    /// Return the number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Return the sum of the recorded latencies in nanoseconds.
+    pub fn sum_ns(&self) -> u64 {
+        self.sum_ns
+    }
+}
+
+/// A gauge of the number of events which are sent but not received yet in an event channel. A
+/// growing depth means a slow consumer.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelDepth(Arc<AtomicUsize>);
+
+impl ChannelDepth {
+    /// Return the current depth.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn incr(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn decr(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The sender of an event channel which counts the sent events.
+#[derive(Debug)]
+pub(crate) struct EventSender<H> {
+    shared: Arc<Shared<TimedEvent<H>>>,
+    kind: ChannelKind,
+    on_closed: OnChannelClosed,
+    depth: ChannelDepth,
+}
+
+impl<H> Drop for EventSender<H> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl<H: ConsensusHash> EventSender<H> {
+    /// Send the event. A full bounded channel rejects the event, while a full ring channel drops
+    /// the oldest one. A dropped receiver is handled by the `OnChannelClosed` policy.
+    pub(crate) fn send(
+        &self,
+        event: GenericSMREvent<H>,
+        time: Option<EventTime>,
+    ) -> ConsensusResult<()> {
+        if self.is_closed() {
+            return Err(ConsensusError::ThrowEventErr(format!(
+                "event: {}, error: channel closed",
+                event
+            )));
+        }
+
+        if self.shared.receiver_closed.load(Ordering::Acquire) {
+            match self.on_closed {
+                OnChannelClosed::Halt => {
+                    return Err(ConsensusError::ThrowEventErr(format!(
+                        "event: {}, error: receiver dropped",
+                        event
+                    )));
+                }
+                OnChannelClosed::DropEvents => {
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                OnChannelClosed::Reconnect => (),
+            }
+        }
+
+        let mut queue = lock(&self.shared.queue);
+        match self.kind {
+            ChannelKind::Bounded(capacity) if queue.len() >= capacity.max(1) => {
+                return Err(ConsensusError::ThrowEventErr(format!(
+                    "event: {}, error: channel full",
+                    event
+                )));
+            }
+            ChannelKind::Ring(capacity) if queue.len() >= capacity.max(1) => {
+                queue.pop_front();
+                self.depth.decr();
+                self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => (),
+        }
+        queue.push_back((event, time));
+        self.depth.incr();
+        drop(queue);
+
+        self.shared.wake();
+        Ok(())
+    }
+
+    pub(crate) fn depth(&self) -> ChannelDepth {
+        self.depth.clone()
+    }
+
+    /// Close the channel. The receiver gets the events in the channel and then the end of the
+    /// stream.
+    pub(crate) fn close(&self) {
+        self.shared.close();
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.sender_closed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_on_closed(&mut self, on_closed: OnChannelClosed) {
+        self.on_closed = on_closed;
+    }
 
-use futures::channel::mpsc::{UnboundedReceiver};
-use futures::stream::{FusedStream, Stream, StreamExt};
+    /// Attach a new receiver to the channel whose receiver is dropped. The events kept for the
+    /// dropped receiver are delivered to the new one only with the `Reconnect` policy.
+    pub(crate) fn reattach(&self, clock: Clock) -> ConsensusResult<GenericEvent<H>> {
+        if !self.shared.receiver_closed.load(Ordering::Acquire) {
+            return Err(ConsensusError::ChannelErr(
+                "receiver is still attached".to_string(),
+            ));
+        }
 
-use crate::smr::smr_types::{SMREvent};
+        if self.on_closed != OnChannelClosed::Reconnect {
+            while self.shared.pop_event(&self.depth).is_some() {}
+        }
+        self.shared.receiver_closed.store(false, Ordering::Release);
+        Ok(GenericEvent::new(
+            Arc::clone(&self.shared),
+            self.depth.clone(),
+            clock,
+        ))
+    }
+}
 
-///
+/// The receiver of an SMR event channel, which is a stream of the events.
 #[derive(Debug)]
-pub struct Event {
-    rx: UnboundedReceiver<SMREvent>,
+pub struct GenericEvent<H> {
+    shared: Arc<Shared<TimedEvent<H>>>,
+    depth: ChannelDepth,
+    clock: Clock,
 }
 
-impl Stream for Event {
-    type Item = SMREvent;
+/// The event receiver of the default hash type.
+pub type Event = GenericEvent<Hash>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        self.rx.poll_next_unpin(cx)
+impl<H> Drop for GenericEvent<H> {
+    fn drop(&mut self) {
+        self.shared.receiver_closed.store(true, Ordering::Release);
     }
 }
 
-impl FusedStream for Event {
+impl<H> Stream for GenericEvent<H> {
+    type Item = GenericSMREvent<H>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .poll_next_timed(cx)
+            .map(|item| item.map(|(event, _)| event))
+    }
+}
+
+impl<H> FusedStream for GenericEvent<H> {
     fn is_terminated(&self) -> bool {
-        self.rx.is_terminated()
+        self.shared.is_terminated()
     }
 }
 
-impl Event {
-    pub fn new(receiver: UnboundedReceiver<SMREvent>) -> Self {
-        Event { rx: receiver }
+impl<H> GenericEvent<H> {
+    fn new(shared: Arc<Shared<TimedEvent<H>>>, depth: ChannelDepth, clock: Clock) -> Self {
+        GenericEvent {
+            shared,
+            depth,
+            clock,
+        }
+    }
+
+    fn poll_next_timed(&mut self, cx: &mut Context) -> Poll<Option<TimedEvent<H>>> {
+        let poll = self.shared.poll_pop(cx);
+        if let Poll::Ready(Some(_)) = poll {
+            self.depth.decr();
+        }
+        poll
+    }
+
+    /// Receive the next event with its emission time. The time is `None` unless the SMR stamps
+    /// the events.
+    pub async fn next_timed(&mut self) -> Option<TimedEvent<H>> {
+        poll_fn(|cx| self.poll_next_timed(cx)).await
+    }
+
+    /// Receive an event if there is one in the channel without waiting.
+    pub fn try_next(&mut self) -> Option<GenericSMREvent<H>> {
+        self.try_next_timed().map(|(event, _)| event)
+    }
+
+    /// Receive an event with its emission time if there is one in the channel without waiting.
+    pub fn try_next_timed(&mut self) -> Option<TimedEvent<H>> {
+        self.shared.pop_event(&self.depth)
+    }
+
+    /// Return the number of events dropped by a full ring channel or while no receiver is attached.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Return the monotonic nanoseconds on the clock of the SMR, which is comparable with
+    /// `EventTime::emitted_at`.
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Return the number of events waiting to be received.
+    pub fn depth(&self) -> usize {
+        self.depth.get()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use futures::StreamExt;
+    use futures::stream::{FusedStream, StreamExt};
 
-    use crate::smr::smr_types::{SMRStatus, SMRTrigger, TriggerSource, TriggerType};
-    use crate::types::{Hash, INIT_HEIGHT, INIT_ROUND};
+    use crate::identity::ADDRESS_LEN;
+    use crate::smr::smr_types::{
+        ChannelKind, EventChannel, OnChannelClosed, SMREvent, SMRStatus, SMRTrigger, TimestampMode,
+        TriggerSource, TriggerType,
+    };
+    use crate::types::{Hash, Height, INIT_HEIGHT, INIT_ROUND};
 
-    use super::{state_machine::StateMachine};
+    use super::{event_channel, state_machine::StateMachine, Clock, LatencyHistogram};
+
+    fn commit() -> SMREvent {
+        SMREvent::Commit {
+            height: INIT_HEIGHT,
+            round: INIT_ROUND,
+            block_hash: Hash::new(),
+            polc_round: None,
+            commit_seq: 0,
+        }
+    }
 
     #[tokio::test]
     async fn test_smr() {
@@ -52,7 +555,7 @@ mod test {
 
         let status = SMRStatus::new(INIT_HEIGHT + 1);
         let msg = SMRTrigger {
-            trigger_type: TriggerType::NewHeight(status),
+            trigger_type: TriggerType::NewHeight(Box::new(status)),
             source: TriggerSource::State,
             hash: Hash::new(),
             lock_round: None,
@@ -66,10 +569,237 @@ mod test {
         match rx_state.next().await {
             Some(event) => {
                 println!("{:?}", event);
-
             }
             None => println!("none"),
         }
-        
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (mut smr, mut rx_state, rx_timer) = StateMachine::new();
+        let status = SMRStatus::new(INIT_HEIGHT + 1);
+        let msg = SMRTrigger {
+            trigger_type: TriggerType::NewHeight(Box::new(status)),
+            source: TriggerSource::State,
+            hash: Hash::new(),
+            lock_round: None,
+            round: INIT_ROUND,
+            height: INIT_HEIGHT,
+        };
+        smr.process(msg.clone()).unwrap();
+        let consumer = tokio::spawn(async move { rx_timer.collect::<Vec<_>>().await });
+
+        smr.shutdown().unwrap();
+        smr.shutdown().unwrap();
+        assert!(smr.process(msg).is_err());
+
+        // A stale trigger, which is ignored without an error before the shutdown, is rejected by
+        // every entry point.
+        let stale = SMRTrigger::proposal(Hash::from(vec![1u8]), None, INIT_ROUND, INIT_HEIGHT);
+        assert!(smr.process(stale.clone()).is_err());
+        assert!(smr
+            .process_from(stale.clone(), Some(Hash::from(vec![1u8; ADDRESS_LEN])))
+            .is_err());
+        assert!(smr.process_with_outcome(stale.clone()).is_err());
+        assert!(smr.process_sync(stale.clone()).is_err());
+        let report = smr.replay(vec![stale]).unwrap();
+        assert_eq!(report.rejected.len(), 1);
+        assert!(report.events.is_empty());
+
+        // The events in the channel are flushed before the end of the stream.
+        assert!(matches!(
+            rx_state.next().await,
+            Some(SMREvent::NewRoundInfo { .. })
+        ));
+        assert_eq!(rx_state.next().await, Some(SMREvent::Stop));
+        assert_eq!(rx_state.next().await, None);
+        assert!(rx_state.is_terminated());
+        let events = consumer.await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], SMREvent::Stop);
+    }
+
+    #[test]
+    fn test_reattach_receiver() {
+        let new_height = |height| SMRTrigger {
+            trigger_type: TriggerType::NewHeight(Box::new(SMRStatus::new(height))),
+            source: TriggerSource::State,
+            hash: Hash::new(),
+            lock_round: None,
+            round: INIT_ROUND,
+            height: INIT_HEIGHT,
+        };
+
+        // Halt by default.
+        let (mut smr, rx_state, _rx_timer) = StateMachine::new();
+        assert!(smr.reattach_receiver(EventChannel::State).is_err());
+        drop(rx_state);
+        assert!(smr.process(new_height(Height(1))).is_err());
+
+        // Drop the events while detached.
+        let (mut smr, rx_state, _rx_timer) = StateMachine::new();
+        smr.set_on_channel_closed(OnChannelClosed::DropEvents);
+        drop(rx_state);
+        smr.process(new_height(Height(1))).unwrap();
+        let mut rx_state = smr.reattach_receiver(EventChannel::State).unwrap();
+        assert!(rx_state.try_next().is_none());
+        assert_eq!(rx_state.dropped(), 1);
+        smr.process(new_height(Height(2))).unwrap();
+        assert!(rx_state.try_next().is_some());
+
+        // Keep the events for the reattached receiver.
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        smr.set_on_channel_closed(OnChannelClosed::Reconnect);
+        drop(rx_timer);
+        smr.process(new_height(Height(1))).unwrap();
+        let mut rx_timer = smr.reattach_receiver(EventChannel::Timer).unwrap();
+        assert!(matches!(
+            rx_timer.try_next(),
+            Some(SMREvent::NewRoundInfo { .. })
+        ));
+        let (state_depth, timer_depth) = smr.channel_depths();
+        assert_eq!((state_depth.get(), timer_depth.get()), (1, 0));
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(500);
+        histogram.record(1_000);
+        histogram.record(5_000_000);
+        histogram.record(u64::MAX);
+        assert_eq!(histogram.buckets(), &[2, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.sum_ns(), u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_channel_depth() {
+        let (mut smr, mut rx_state, mut rx_timer) = StateMachine::new();
+        let (state_depth, timer_depth) = smr.channel_depths();
+
+        let msg = SMRTrigger {
+            trigger_type: TriggerType::NewHeight(Box::new(SMRStatus::new(INIT_HEIGHT + 1))),
+            source: TriggerSource::State,
+            hash: Hash::new(),
+            lock_round: None,
+            round: INIT_ROUND,
+            height: INIT_HEIGHT,
+        };
+        smr.process(msg).unwrap();
+        assert_eq!(state_depth.get(), 1);
+        assert_eq!(timer_depth.get(), 1);
+
+        rx_state.next().await.unwrap();
+        assert_eq!(rx_state.depth(), 0);
+        assert_eq!(timer_depth.get(), 1);
+
+        rx_timer.try_next().unwrap();
+        assert!(rx_timer.try_next().is_none());
+        assert_eq!(timer_depth.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_event_time() {
+        let new_height = |height: u64| SMRTrigger {
+            trigger_type: TriggerType::NewHeight(Box::new(SMRStatus::new(INIT_HEIGHT + height))),
+            source: TriggerSource::State,
+            hash: Hash::new(),
+            lock_round: None,
+            round: INIT_ROUND,
+            height: INIT_HEIGHT,
+        };
+
+        let (mut smr, mut rx_state, mut rx_timer) = StateMachine::new();
+        smr.process(new_height(1)).unwrap();
+        assert!(rx_state.next_timed().await.unwrap().1.is_none());
+        while rx_timer.try_next().is_some() {}
+
+        smr.set_timestamp_mode(TimestampMode::Monotonic);
+        smr.process(new_height(2)).unwrap();
+        let (_, time) = rx_state.next_timed().await.unwrap();
+        let time = time.unwrap();
+        assert!(time.wall_clock.is_none());
+        assert!(time.emitted_at <= rx_state.now());
+
+        // Both channels carry the same emission time.
+        let (_, timer_time) = rx_timer.try_next_timed().unwrap();
+        assert_eq!(timer_time, Some(time));
+
+        smr.set_timestamp_mode(TimestampMode::WallClock);
+        smr.process(new_height(3)).unwrap();
+        let (_, time) = rx_state.try_next_timed().unwrap();
+        assert!(time.unwrap().wall_clock.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_channel_kinds() {
+        let (tx, mut rx) = event_channel(ChannelKind::Bounded(2), Clock::new());
+        tx.send(commit(), None).unwrap();
+        tx.send(SMREvent::Stop, None).unwrap();
+        assert!(tx.send(SMREvent::Stop, None).is_err());
+        assert_eq!(rx.next().await, Some(commit()));
+        tx.send(SMREvent::Stop, None).unwrap();
+        assert_eq!(rx.depth(), 2);
+
+        let (tx, mut rx) = event_channel(ChannelKind::Ring(2), Clock::new());
+        tx.send(commit(), None).unwrap();
+        tx.send(SMREvent::Stop, None).unwrap();
+        tx.send(SMREvent::Stop, None).unwrap();
+        assert_eq!(rx.dropped(), 1);
+        assert_eq!(rx.depth(), 2);
+        assert_eq!(rx.next().await, Some(SMREvent::Stop));
+
+        // The receiver ends after the sender is dropped and the events are drained.
+        drop(tx);
+        assert_eq!(rx.next().await, Some(SMREvent::Stop));
+        assert_eq!(rx.next().await, None);
+
+        // The sender fails after the receiver is dropped.
+        let (tx, rx) = event_channel(ChannelKind::Unbounded, Clock::new());
+        drop(rx);
+        assert!(tx.send(SMREvent::Stop, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_channel_wake() {
+        let (tx, mut rx) = event_channel(ChannelKind::Unbounded, Clock::new());
+        let handle = tokio::spawn(async move { rx.next().await });
+        tokio::task::yield_now().await;
+        tx.send(SMREvent::Stop, None).unwrap();
+        assert_eq!(handle.await.unwrap(), Some(SMREvent::Stop));
+    }
+
+    /// Stress the channel kinds with a concurrent consumer. No event is lost, and only the ring
+    /// drops events. Run it by `cargo test --release -- --ignored bench_channels`.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn bench_channels() {
+        const EVENTS: usize = 1_000_000;
+
+        for kind in [
+            ChannelKind::Unbounded,
+            ChannelKind::Bounded(EVENTS),
+            ChannelKind::Ring(1024),
+        ] {
+            let (tx, mut rx) = event_channel(kind, Clock::new());
+            let consumer = tokio::spawn(async move {
+                let mut received = 0usize;
+                while rx.next().await.is_some() {
+                    received += 1;
+                }
+                (received, rx.dropped())
+            });
+
+            for _ in 0..EVENTS {
+                tx.send(commit(), None).unwrap();
+            }
+            drop(tx);
+            let (received, dropped) = consumer.await.unwrap();
+            assert_eq!(received as u64 + dropped, EVENTS as u64, "{:?}", kind);
+            if !matches!(kind, ChannelKind::Ring(_)) {
+                assert_eq!(dropped, 0, "{:?}", kind);
+            }
+        }
     }
 }
@@ -0,0 +1,690 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bytes::Bytes;
+use rand_core::{RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+
+use crate::smr::smr_types::{SMREvent, SMRStatus, SMRTrigger, TriggerSource, TriggerType};
+use crate::smr::{state_machine::StateMachine, Event};
+use crate::types::{Hash, Height, Round, VoteType, INIT_HEIGHT, INIT_ROUND};
+
+/// The faults injected into the delivery of each broadcast message, decided by the seeded random
+/// generator of the simulation.
+#[derive(Clone, Debug, Default)]
+pub struct ChaosConfig {
+    /// The probability that a message is delivered twice.
+    pub duplicate_rate: f64,
+    /// The probability that a message is corrupted. The receiver fails to verify a corrupted
+    /// message and drops it, and the transport retransmits it after another delay.
+    pub corrupt_rate: f64,
+    /// The probability that a message is delayed by a spike beyond the maximum delay, which
+    /// reorders it after the later messages.
+    pub spike_rate: f64,
+    /// The maximum extra delay of a spike, in simulated ticks.
+    pub max_spike: u64,
+}
+
+/// The configuration of a simulated network.
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    /// Number of validators.
+    pub validators: usize,
+    /// Number of heights each validator commits before stopping.
+    pub heights: u64,
+    /// The maximum delay of a message, in simulated ticks.
+    pub max_delay: u64,
+    /// The seed of the message delays and the withholding.
+    pub seed: u64,
+    /// The timeout of each step, in simulated ticks.
+    pub timeout: u64,
+    /// Number of Byzantine validators, which are the last ones.
+    pub byzantine: usize,
+    /// The probability that a Byzantine validator withholds each of its votes.
+    pub withhold_rate: f64,
+    /// Feed the buffered proposal of round 0 on entering a height, so that the propose timer of
+    /// the round is never armed.
+    pub fast_path: bool,
+    /// The faults injected into the network.
+    pub chaos: ChaosConfig,
+}
+
+impl SimConfig {
+    /// Create the configuration of an honest network.
+    pub fn honest(validators: usize, heights: u64, max_delay: u64, seed: u64) -> Self {
+        SimConfig {
+            validators,
+            heights,
+            max_delay,
+            seed,
+            timeout: 10 * (max_delay + 1),
+            byzantine: 0,
+            withhold_rate: 0.0,
+            fast_path: true,
+            chaos: ChaosConfig::default(),
+        }
+    }
+}
+
+/// The statistics of a finished simulation.
+#[derive(Clone, Debug, Default)]
+pub struct SimReport {
+    /// Number of delivered messages.
+    pub delivered: u64,
+    /// The simulated ticks when the last message was delivered.
+    pub ticks: u64,
+    /// The maximum number of in-flight messages.
+    pub max_in_flight: usize,
+    /// The maximum number of vote tallies held by a validator.
+    pub max_tallies: usize,
+    /// The maximum number of future messages buffered by a validator.
+    pub max_buffered: usize,
+    /// Number of withheld votes.
+    pub withheld: u64,
+    /// Number of delivered timeouts, most of which are stale and ignored.
+    pub timeouts: u64,
+    /// Number of duplicated messages.
+    pub duplicated: u64,
+    /// Number of corrupted and retransmitted messages.
+    pub corrupted: u64,
+    /// Number of messages delayed by a spike.
+    pub spiked: u64,
+    /// The maximum number of rounds beyond the first one that an honest validator needs to commit
+    /// all heights.
+    pub extra_rounds: u64,
+}
+
+impl SimReport {
+    /// Return the average number of extra rounds per height.
+    pub fn extra_rounds_per_height(&self, heights: u64) -> f64 {
+        self.extra_rounds as f64 / heights.max(1) as f64
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Message {
+    Proposal {
+        height: Height,
+        round: Round,
+        hash: Hash,
+        lock_round: Option<Round>,
+    },
+    Vote {
+        vote_type: VoteType,
+        height: Height,
+        round: Round,
+        hash: Hash,
+        voter: usize,
+    },
+    /// A timeout of the validator itself, which is never broadcast.
+    Timeout {
+        trigger_type: TriggerType,
+        height: Height,
+        round: Round,
+    },
+}
+
+impl Message {
+    fn height(&self) -> Height {
+        match self {
+            Message::Proposal { height, .. }
+            | Message::Vote { height, .. }
+            | Message::Timeout { height, .. } => *height,
+        }
+    }
+}
+
+/// An in-flight message, ordered by the earliest delivery time first.
+struct Envelope {
+    time: u64,
+    seq: u64,
+    to: usize,
+    msg: Message,
+}
+
+impl PartialEq for Envelope {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.seq) == (other.time, other.seq)
+    }
+}
+
+impl Eq for Envelope {}
+
+impl PartialOrd for Envelope {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Envelope {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.time, other.seq).cmp(&(self.time, self.seq))
+    }
+}
+
+#[rustfmt::skip]
+struct Node {
+    id:        usize,
+    byzantine: bool,
+    smr:       StateMachine,
+    height:    Height,
+    round:     Round,
+    rx_state:  Event,
+    rx_timer:  Event,
+    votes:     HashMap<(Height, Round, VoteType, Hash), HashSet<usize>>,
+    future:    Vec<Message>,
+    commits:   Vec<(Height, Hash)>,
+    /// The sum of the commit rounds.
+    rounds:    u64,
+}
+
+impl Node {
+    fn new(id: usize, byzantine: bool) -> Self {
+        let (smr, rx_state, rx_timer) = StateMachine::new();
+        Node {
+            id,
+            byzantine,
+            smr,
+            height: INIT_HEIGHT,
+            round: INIT_ROUND,
+            rx_state,
+            rx_timer,
+            votes: HashMap::new(),
+            future: Vec::new(),
+            commits: Vec::new(),
+            rounds: 0,
+        }
+    }
+
+    /// Goto the given height, replay the buffered messages of it and return the messages to
+    /// broadcast.
+    fn goto_height(&mut self, height: Height, config: &SimConfig) -> Vec<Message> {
+        let status = SMRStatus::new(height);
+        let trigger_type = TriggerType::NewHeight(Box::new(status));
+        let trigger = trigger(trigger_type, Hash::new(), INIT_ROUND, self.height);
+        self.smr.process(trigger).expect("new height");
+        self.height = height;
+        self.votes.retain(|(h, ..), _| *h >= height);
+
+        // The fast path feeds the proposal of round 0 before draining the events, so the prevote
+        // timer supersedes the propose timer in the same drain.
+        let proposal = self.future.iter().position(|msg| {
+            matches!(msg, Message::Proposal { round, .. } if *round == INIT_ROUND)
+                && msg.height() == height
+        });
+        let mut out = match proposal {
+            Some(index) if config.fast_path => {
+                let msg = self.future.remove(index);
+                self.handle_message(msg, config)
+            }
+            _ => self.drain_events(config),
+        };
+        out.extend(self.replay_future(config));
+        out
+    }
+
+    /// Replay the buffered messages, which are buffered again if still in the future.
+    fn replay_future(&mut self, config: &SimConfig) -> Vec<Message> {
+        let mut out = Vec::new();
+        for msg in std::mem::take(&mut self.future) {
+            out.extend(self.handle_message(msg, config));
+        }
+        out
+    }
+
+    /// Handle a message from the network and return the messages to broadcast.
+    fn handle_message(&mut self, msg: Message, config: &SimConfig) -> Vec<Message> {
+        if msg.height() > self.height {
+            self.future.push(msg);
+            return Vec::new();
+        } else if msg.height() < self.height || self.commits.len() as u64 >= config.heights {
+            return Vec::new();
+        }
+
+        let trigger = match msg {
+            Message::Proposal { round, .. } if round > self.round => {
+                self.future.push(msg);
+                return Vec::new();
+            }
+            Message::Proposal {
+                height,
+                round,
+                hash,
+                lock_round,
+            } => {
+                let mut trigger = trigger(TriggerType::Proposal, hash, round, height);
+                trigger.lock_round = lock_round;
+                trigger
+            }
+            Message::Vote {
+                vote_type,
+                height,
+                round,
+                hash,
+                voter,
+            } => {
+                let voters = self
+                    .votes
+                    .entry((height, round, vote_type.clone(), hash.clone()))
+                    .or_default();
+                if !voters.insert(voter) || voters.len() != quorum(config.validators) {
+                    return Vec::new();
+                }
+                trigger(vote_type.into(), hash, round, height)
+            }
+            Message::Timeout {
+                trigger_type,
+                height,
+                round,
+            } => {
+                let mut trigger = trigger(trigger_type, Hash::new(), round, height);
+                trigger.source = TriggerSource::Timer;
+                trigger
+            }
+        };
+
+        self.smr.process(trigger).expect("process");
+        self.drain_events(config)
+    }
+
+    fn drain_events(&mut self, config: &SimConfig) -> Vec<Message> {
+        let mut out = Vec::new();
+        let mut timer_events = Vec::new();
+        while let Some(event) = self.rx_timer.try_next() {
+            timer_events.push(event);
+        }
+        for (index, event) in timer_events.iter().enumerate() {
+            let (trigger_type, height, round) = match *event {
+                // A propose timer superseded by a prevote of the round is not armed.
+                SMREvent::NewRoundInfo { height, round, .. }
+                    if timer_events[index + 1..].iter().any(|event| {
+                        matches!(event, SMREvent::PrevoteVote { height: h, round: r, .. }
+                            if (*h, *r) == (height, round))
+                    }) =>
+                {
+                    continue
+                }
+                SMREvent::NewRoundInfo { height, round, .. } => {
+                    (TriggerType::Proposal, height, round)
+                }
+                SMREvent::PrevoteVote { height, round, .. } => {
+                    (TriggerType::PrevoteQC, height, round)
+                }
+                SMREvent::PrecommitVote { height, round, .. } => {
+                    (TriggerType::PrecommitQC, height, round)
+                }
+                _ => continue,
+            };
+            out.push(Message::Timeout {
+                trigger_type,
+                height,
+                round,
+            });
+        }
+
+        let round = self.round;
+        let mut committed = false;
+        while let Some(event) = self.rx_state.try_next() {
+            match event {
+                SMREvent::NewRoundInfo {
+                    height,
+                    round,
+                    lock_round,
+                    lock_proposal,
+                    ..
+                } => {
+                    self.round = round;
+                    if proposer(height, round, config.validators) == self.id {
+                        // Propose the lock if has, otherwise a new block.
+                        let hash = lock_proposal.unwrap_or_else(|| {
+                            Bytes::from(format!("block {} round {}", height, round))
+                        });
+                        out.push(Message::Proposal {
+                            height,
+                            round,
+                            hash,
+                            lock_round,
+                        });
+                    }
+                }
+                SMREvent::PrevoteVote {
+                    height,
+                    round,
+                    block_hash,
+                    ..
+                } => out.push(Message::Vote {
+                    vote_type: VoteType::Prevote,
+                    height,
+                    round,
+                    hash: block_hash,
+                    voter: self.id,
+                }),
+                SMREvent::PrecommitVote {
+                    height,
+                    round,
+                    block_hash,
+                    ..
+                } => out.push(Message::Vote {
+                    vote_type: VoteType::Precommit,
+                    height,
+                    round,
+                    hash: block_hash,
+                    voter: self.id,
+                }),
+                SMREvent::Commit {
+                    height, block_hash, ..
+                } => {
+                    self.commits.push((height, block_hash));
+                    self.rounds += self.round.0;
+                    committed = true;
+                }
+                _ => (),
+            }
+        }
+
+        if committed && (self.commits.len() as u64) < config.heights {
+            out.extend(self.goto_height(self.height.next(), config));
+        } else if !committed && self.round != round {
+            out.extend(self.replay_future(config));
+        }
+        out
+    }
+}
+
+/// A deterministic simulation of validators connected by a network delivering each message after
+/// a random delay. Each validator times out its steps, and the Byzantine ones may withhold their
+/// votes. It checks the safety and liveness invariants on every commit.
+pub struct Simulation {
+    config: SimConfig,
+    nodes: Vec<Node>,
+    queue: BinaryHeap<Envelope>,
+    rng: Pcg64,
+    now: u64,
+    seq: u64,
+    /// The committed hash of each height and the number of validators committed it.
+    committed: HashMap<Height, (Hash, usize)>,
+    report: SimReport,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        let honest = config.validators - config.byzantine;
+        let nodes = (0..config.validators)
+            .map(|id| Node::new(id, id >= honest))
+            .collect();
+        let rng = Pcg64::seed_from_u64(config.seed);
+        Simulation {
+            config,
+            nodes,
+            queue: BinaryHeap::new(),
+            rng,
+            now: 0,
+            seq: 0,
+            committed: HashMap::new(),
+            report: SimReport::default(),
+        }
+    }
+
+    /// Run the simulation until every validator commits the configured heights.
+    pub fn run(mut self) -> SimReport {
+        for id in 0..self.nodes.len() {
+            let out = self.nodes[id].goto_height(INIT_HEIGHT + 1, &self.config);
+            self.broadcast(id, out);
+        }
+
+        while let Some(envelope) = self.queue.pop() {
+            self.now = envelope.time;
+            if let Message::Timeout { .. } = envelope.msg {
+                self.report.timeouts += 1;
+            } else {
+                self.report.delivered += 1;
+            }
+
+            let node = &mut self.nodes[envelope.to];
+            let commits = node.commits.len();
+            let out = node.handle_message(envelope.msg, &self.config);
+            self.check_commits(envelope.to, commits);
+            self.check_memory(envelope.to);
+            self.broadcast(envelope.to, out);
+        }
+
+        for node in self.nodes.iter() {
+            assert_eq!(
+                node.commits.len() as u64,
+                self.config.heights,
+                "validator {} stalls at height {}",
+                node.id,
+                node.height
+            );
+        }
+        assert!(self.committed.is_empty());
+        self.report.ticks = self.now;
+        self.report.extra_rounds = self
+            .nodes
+            .iter()
+            .filter(|node| !node.byzantine)
+            .map(|node| node.rounds)
+            .max()
+            .unwrap_or_default();
+        self.report
+    }
+
+    /// Broadcast the messages from the validator. A timeout is only sent to the validator itself,
+    /// and a vote of a Byzantine validator may be withheld.
+    fn broadcast(&mut self, from: usize, msgs: Vec<Message>) {
+        for msg in msgs {
+            match msg {
+                Message::Timeout { .. } => {
+                    self.seq += 1;
+                    self.queue.push(Envelope {
+                        time: self.now + self.config.timeout,
+                        seq: self.seq,
+                        to: from,
+                        msg,
+                    });
+                    continue;
+                }
+                Message::Vote { .. } if self.nodes[from].byzantine && self.withhold() => {
+                    self.report.withheld += 1;
+                    continue;
+                }
+                _ => (),
+            }
+
+            for to in 0..self.nodes.len() {
+                let copies = if self.chance(self.config.chaos.duplicate_rate) {
+                    self.report.duplicated += 1;
+                    2
+                } else {
+                    1
+                };
+                for _ in 0..copies {
+                    let delay = self.delay();
+                    self.seq += 1;
+                    self.queue.push(Envelope {
+                        time: self.now + delay,
+                        seq: self.seq,
+                        to,
+                        msg: msg.clone(),
+                    });
+                }
+            }
+        }
+        self.report.max_in_flight = self.report.max_in_flight.max(self.queue.len());
+    }
+
+    /// Return the delay of a message, including the spikes and the retransmissions of the
+    /// corrupted copies.
+    fn delay(&mut self) -> u64 {
+        let mut delay = self.rng.next_u64() % (self.config.max_delay + 1);
+        if self.chance(self.config.chaos.spike_rate) {
+            self.report.spiked += 1;
+            delay += self.rng.next_u64() % (self.config.chaos.max_spike + 1);
+        }
+        while self.chance(self.config.chaos.corrupt_rate) {
+            self.report.corrupted += 1;
+            delay += self.rng.next_u64() % (self.config.max_delay + 1);
+        }
+        delay
+    }
+
+    fn withhold(&mut self) -> bool {
+        self.chance(self.config.withhold_rate)
+    }
+
+    /// Return `true` with the given probability. A zero probability consumes no randomness, so
+    /// that the runs without the faults are not changed by them.
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && (self.rng.next_u64() as f64 / u64::MAX as f64) < rate
+    }
+
+    /// Check that the new commits of the validator are monotonic and do not fork.
+    fn check_commits(&mut self, id: usize, from: usize) {
+        let node = &self.nodes[id];
+        for (index, (height, hash)) in node.commits.iter().enumerate().skip(from) {
+            assert_eq!(
+                *height,
+                INIT_HEIGHT + 1 + index as u64,
+                "validator {} commits out of order",
+                id
+            );
+
+            let (committed, count) = self
+                .committed
+                .entry(*height)
+                .or_insert_with(|| (hash.clone(), 0));
+            assert_eq!(
+                committed, hash,
+                "fork at height {} by validator {}",
+                height, id
+            );
+
+            *count += 1;
+            if *count == self.config.validators {
+                self.committed.remove(height);
+            }
+        }
+    }
+
+    /// Check that nothing grows with the number of heights.
+    fn check_memory(&mut self, id: usize) {
+        let node = &self.nodes[id];
+        let bound = 64 * self.config.validators * self.config.validators;
+
+        self.report.max_tallies = self.report.max_tallies.max(node.votes.len());
+        self.report.max_buffered = self.report.max_buffered.max(node.future.len());
+        assert!(self.queue.len() <= bound, "too many in-flight messages");
+        assert!(node.votes.len() <= bound, "too many vote tallies");
+        assert!(node.future.len() <= bound, "too many buffered messages");
+        assert!(self.committed.len() <= bound, "too many unfinished heights");
+    }
+}
+
+fn proposer(height: Height, round: Round, validators: usize) -> usize {
+    ((height.0 + round.0) % validators as u64) as usize
+}
+
+fn quorum(validators: usize) -> usize {
+    validators * 2 / 3 + 1
+}
+
+fn trigger(trigger_type: TriggerType, hash: Hash, round: Round, height: Height) -> SMRTrigger {
+    SMRTrigger {
+        trigger_type,
+        source: TriggerSource::State,
+        hash,
+        lock_round: None,
+        round,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChaosConfig, SimConfig, Simulation};
+
+    /// Read a `u64` from the environment variable, or use the default value.
+    fn env_or(key: &str, default: u64) -> u64 {
+        std::env::var(key)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    #[test]
+    fn test_simulation() {
+        for seed in 0..4 {
+            let report = Simulation::new(SimConfig::honest(4, 100, 10, seed)).run();
+            assert_eq!(report.extra_rounds, 0);
+        }
+    }
+
+    /// Measure the extra rounds the honest majority needs per withholding rate of a Byzantine
+    /// validator. The honest validators are exactly a quorum, so a withheld vote leaves no slack for
+    /// a slow honest vote, and the timeout is tight enough for delays to matter.
+    #[test]
+    fn test_withholding() {
+        let mut last = None;
+        for rate in [0.0, 0.5, 1.0] {
+            let mut config = SimConfig::honest(4, 100, 10, 0);
+            config.timeout = 12;
+            config.byzantine = 1;
+            config.withhold_rate = rate;
+            let report = Simulation::new(config).run();
+
+            if rate == 0.0 {
+                assert_eq!(report.withheld, 0);
+            } else {
+                assert!(report.withheld > 0);
+            }
+            if let Some(last) = last {
+                assert!(report.extra_rounds >= last);
+            }
+            last = Some(report.extra_rounds);
+        }
+    }
+
+    #[test]
+    fn test_fast_path() {
+        let mut config = SimConfig::honest(4, 100, 10, 0);
+        let fast = Simulation::new(config.clone()).run();
+        config.fast_path = false;
+        let slow = Simulation::new(config).run();
+        assert_eq!((fast.extra_rounds, slow.extra_rounds), (0, 0));
+        assert!(fast.timeouts < slow.timeouts);
+    }
+
+    /// The validators stay safe and live when the network duplicates, corrupts and reorders the
+    /// messages. The commits are checked for forks on every delivery.
+    #[test]
+    fn test_chaos() {
+        for seed in 0..4 {
+            let mut config = SimConfig::honest(4, 100, 10, seed);
+            config.chaos = ChaosConfig {
+                duplicate_rate: 0.2,
+                corrupt_rate: 0.1,
+                spike_rate: 0.05,
+                max_spike: 100,
+            };
+            let report = Simulation::new(config).run();
+            assert!(report.duplicated > 0 && report.corrupted > 0 && report.spiked > 0);
+        }
+    }
+
+    /// Drive 4 validators for 10,000 heights with random delays. Run it nightly by
+    /// `cargo test --release -- --ignored test_soak`. The `SOAK_HEIGHTS` and `SOAK_SEED` environment
+    /// variables override the number of heights and the seed.
+    #[test]
+    #[ignore]
+    fn test_soak() {
+        let config = SimConfig::honest(
+            4,
+            env_or("SOAK_HEIGHTS", 10_000),
+            100,
+            env_or("SOAK_SEED", 0),
+        );
+        let report = Simulation::new(config.clone()).run();
+        assert!(report.delivered >= config.heights);
+        assert!(report.extra_rounds_per_height(config.heights) < 1.0);
+    }
+}
@@ -1,11 +1,19 @@
+use std::collections::HashSet;
+
 use derive_more::Display;
-use hummer::coding::hex_encode;
 use serde::{Deserialize, Serialize};
 
-use crate::types::{DurationConfig, Hash, ViewChangeReason};
+use crate::error::ConsensusError;
+use crate::identity::check_address;
+use crate::types::{
+    Address, ConsensusHash, ConsensusResult, DurationConfig, Hash, Height, HexFmt, ParamChange,
+    Round, TimeoutConfig, ViewChangeReason, VoteType, INIT_ROUND,
+};
 
 /// SMR steps. The default step is commit step because SMR needs rich status to start a new block.
-#[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, Display, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub enum Step {
     /// Prepose step, in this step:
     /// Firstly, each node calculate the new proposer, then:
@@ -40,22 +48,23 @@ pub enum Step {
     ///     2. wait for aggregated vote,
     ///     3. check the aggregated vote.
     /// If there is no consensus in the precommit step, goto propose step and start a new round
-    /// cycle. Otherwise, goto commit step.
+    /// cycle, or goto brake step on the timeout if the brake is enabled. Otherwise, goto commit
+    /// step.
     #[display(fmt = "Precommit step")]
     Precommit,
 
+    /// Brake step, in this step each node broadcasts a choke message and retries it on every brake
+    /// timeout, until a choke QC of the round continues to the next round.
+    #[display(fmt = "Brake step")]
+    Brake,
+
     /// Commit step, in this step each node commit the block and wait for the rich status. After
     /// receiving the it, all nodes will goto propose step and start a new block consensus.
     #[display(fmt = "Commit step")]
+    #[default]
     Commit,
 }
 
-impl Default for Step {
-    fn default() -> Self {
-        Step::Commit
-    }
-}
-
 impl From<Step> for u8 {
     fn from(step: Step) -> u8 {
         match step {
@@ -63,6 +72,7 @@ impl From<Step> for u8 {
             Step::Prevote => 1,
             Step::Precommit => 2,
             Step::Commit => 3,
+            Step::Brake => 4,
         }
     }
 }
@@ -74,33 +84,48 @@ impl From<u8> for Step {
             1 => Step::Prevote,
             2 => Step::Precommit,
             3 => Step::Commit,
+            4 => Step::Brake,
             _ => panic!("Invalid step!"),
         }
     }
 }
 
-///
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// Where a new round info comes from.
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum FromWhere {
-    ///
-    PrevoteQC(u64),
-    ///
-    PrecommitQC(u64),
-    ///
-    ChokeQC(u64),
+    /// Goto a new height, the round is the initial round of the height.
+    NewHeight,
+    /// Goto the next round of a prevote QC round.
+    PrevoteQC(Round),
+    /// Goto the next round of an empty precommit QC round.
+    PrecommitQC(Round),
+    /// Goto the next round of a choke QC round.
+    ChokeQC(Round),
+    /// Skip to the round of the messages from f+1 validators.
+    RoundSkip(Round),
 }
 
 impl FromWhere {
-    pub fn get_round(&self) -> u64 {
+    /// Return the QC round, or `None` if it is not from a QC.
+    pub fn round(&self) -> Option<Round> {
         match self {
-            FromWhere::PrevoteQC(round) => *round,
-            FromWhere::PrecommitQC(round) => *round,
-            FromWhere::ChokeQC(round) => *round,
+            FromWhere::NewHeight | FromWhere::RoundSkip(_) => None,
+            FromWhere::PrevoteQC(round)
+            | FromWhere::PrecommitQC(round)
+            | FromWhere::ChokeQC(round) => Some(*round),
         }
     }
 
-    pub fn to_reason(&self, old_round: u64) -> ViewChangeReason {
+    /// Return whether it is from a QC.
+    pub fn is_qc(&self) -> bool {
+        !matches!(self, FromWhere::NewHeight | FromWhere::RoundSkip(_))
+    }
+
+    /// Return the reason of the view change from the old round, which carries the QC round or the
+    /// round skipped to.
+    pub fn to_reason(&self, old_round: Round) -> ViewChangeReason {
         match self {
+            FromWhere::NewHeight => ViewChangeReason::Others,
             FromWhere::PrevoteQC(round) => {
                 ViewChangeReason::UpdateFromHigherPrevoteQC(old_round, *round)
             }
@@ -110,6 +135,7 @@ impl FromWhere {
             FromWhere::ChokeQC(round) => {
                 ViewChangeReason::UpdateFromHigherChokeQC(old_round, *round)
             }
+            FromWhere::RoundSkip(round) => ViewChangeReason::RoundSkip(old_round, *round),
         }
     }
 }
@@ -117,25 +143,33 @@ impl FromWhere {
 /// SMR event that state and timer monitor this.
 /// **NOTICE**: The `height` field is just for the timer. Timer will take this to signal the timer
 /// height. State will ignore this field on handling event.
-#[derive(Clone, Debug, Display, PartialEq, Eq)]
-pub enum SMREvent {
+#[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq)]
+#[display(bound = "H: AsRef<[u8]>")]
+pub enum GenericSMREvent<H> {
     /// New round event,
     /// for state: update round,
     /// for timer: set a propose step timer. If `round == 0`, set an extra total height timer.
+    /// The reason is why the view changes, which is built by `FromWhere::to_reason` from the
+    /// previous round, except `NoPrecommitQCFromNetwork` for a precommit timeout.
     #[display(
-        fmt = "New round {} event, lock round {:?}, lock proposal {:?}",
+        fmt = "New round {} event, lock round {:?}, lock proposal {:?}, reason {}",
         round,
         lock_round,
-        lock_proposal
+        lock_proposal,
+        reason
     )]
     NewRoundInfo {
-        height: u64,
-        round: u64,
-        lock_round: Option<u64>,
-        lock_proposal: Option<Hash>,
+        height: Height,
+        round: Round,
+        lock_round: Option<Round>,
+        lock_proposal: Option<H>,
         from_where: FromWhere,
+        #[serde(default, skip_serializing_if = "is_default_reason")]
+        reason: ViewChangeReason,
         new_interval: Option<u64>,
-        new_config: Option<DurationConfig>,
+        new_config: Option<Box<DurationConfig>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_timeouts: Option<Box<TimeoutConfig>>,
     },
 
     /// Prevote event,
@@ -145,14 +179,14 @@ pub enum SMREvent {
         fmt = "Prevote event height {}, round {}, block hash {:?}, lock round {:?}",
         height,
         round,
-        "hex_encode(block_hash)",
+        "HexFmt(block_hash.as_ref())",
         lock_round
     )]
     PrevoteVote {
-        height: u64,
-        round: u64,
-        block_hash: Hash,
-        lock_round: Option<u64>,
+        height: Height,
+        round: Round,
+        block_hash: H,
+        lock_round: Option<Round>,
     },
 
     /// Precommit event,
@@ -162,20 +196,34 @@ pub enum SMREvent {
         fmt = "Precommit event height {}, round {}, block hash {:?}, lock round {:?}",
         height,
         round,
-        "hex_encode(block_hash)",
+        "HexFmt(block_hash.as_ref())",
         lock_round
     )]
     PrecommitVote {
-        height: u64,
-        round: u64,
-        block_hash: Hash,
-        lock_round: Option<u64>,
+        height: Height,
+        round: Round,
+        block_hash: H,
+        lock_round: Option<Round>,
     },
-    /// Commit event,
+    /// Commit event, carrying the height and round of the commit, the round of the lock and the
+    /// commit sequence number, which increases by one with every commit,
     /// for state: do commit,
     /// for timer: do nothing.
-    #[display(fmt = "Commit event hash {:?}", "hex_encode(_0)")]
-    Commit(Hash),
+    #[display(
+        fmt = "Commit event height {}, round {}, block hash {:?}, polc round {:?}, seq {}",
+        height,
+        round,
+        "HexFmt(block_hash.as_ref())",
+        polc_round,
+        commit_seq
+    )]
+    Commit {
+        height: Height,
+        round: Round,
+        block_hash: H,
+        polc_round: Option<Round>,
+        commit_seq: u64,
+    },
 
     /// Brake event,
     /// for state: broadcast Choke message,
@@ -187,9 +235,165 @@ pub enum SMREvent {
         lock_round
     )]
     Brake {
-        height: u64,
-        round: u64,
-        lock_round: Option<u64>,
+        height: Height,
+        round: Round,
+        lock_round: Option<Round>,
+    },
+
+    /// Lock expired event,
+    /// for state: the lock is released after expiry rounds without a commit,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "Lock expired event height {}, round {}, lock round {}, lock proposal {:?}",
+        height,
+        round,
+        lock_round,
+        "HexFmt(lock_proposal.as_ref())"
+    )]
+    LockExpired {
+        height: Height,
+        round: Round,
+        lock_round: Round,
+        lock_proposal: H,
+    },
+
+    /// Watchdog report event, thrown once if no step transition occurs within the watchdog timeout,
+    /// for state: dump the diagnostics,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "Watchdog report height {}, round {}, step {}, stalled for {} ms",
+        height,
+        round,
+        step,
+        stalled_ms
+    )]
+    WatchdogReport {
+        height: Height,
+        round: Round,
+        step: Step,
+        lock: Option<GenericLock<H>>,
+        stalled_ms: u64,
+        recent_events: Vec<GenericSMREvent<H>>,
+    },
+
+    /// SLO breach event, thrown when a height is committed later than the commit latency budget
+    /// after entering it,
+    /// for state: alert the operators,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "SLO breach height {}, committed in {} ms over the budget of {} ms",
+        height,
+        actual_ms,
+        budget_ms
+    )]
+    SloBreach {
+        height: Height,
+        actual_ms: u64,
+        budget_ms: u64,
+    },
+
+    /// Lock changed event, thrown whenever the PoLC is set, replaced or removed,
+    /// for state: trace the lock,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "Lock changed event height {}, round {}, from {:?} to {:?}: {}",
+        height,
+        round,
+        old,
+        new,
+        cause
+    )]
+    LockChanged {
+        height: Height,
+        round: Round,
+        old: Option<GenericLock<H>>,
+        new: Option<GenericLock<H>>,
+        cause: LockCause,
+    },
+
+    /// State transition event, thrown on every step transition, including the transitions to
+    /// a new round or a new height,
+    /// for state: track the progress,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "State transition height {}, round {}, from {} to {}: {}",
+        height,
+        round,
+        from_step,
+        to_step,
+        reason
+    )]
+    StateTransition {
+        height: Height,
+        round: Round,
+        from_step: Step,
+        to_step: Step,
+        reason: TransitionReason,
+    },
+
+    /// Quorum unavailable event, thrown when more than one third of the voting power has not
+    /// voted for a number of rounds, so that the network cannot commit,
+    /// for state: alert the operators,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "Quorum unavailable height {}, round {}, missing power {}",
+        height,
+        round,
+        missing_power
+    )]
+    QuorumUnavailable {
+        height: Height,
+        round: Round,
+        missing_power: u64,
+        validators: Vec<Address>,
+    },
+
+    /// Fork evidence event, thrown when a proposal or an adopted lock conflicts with the lock of
+    /// the same round,
+    /// for state: report the evidence,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "Fork evidence height {}, round {}, local hash {:?}, remote hash {:?}",
+        height,
+        round,
+        "HexFmt(local_hash.as_ref())",
+        "HexFmt(remote_hash.as_ref())"
+    )]
+    ForkEvidence {
+        height: Height,
+        round: Round,
+        local_hash: H,
+        remote_hash: H,
+    },
+
+    /// Parameter change pending event, thrown when a valid parameter change is committed,
+    /// for state: announce the change,
+    /// for timer: do nothing.
+    #[display(
+        fmt = "Parameter change version {} pending until height {}",
+        version,
+        activation_height
+    )]
+    ParamChangePending {
+        version: u64,
+        activation_height: Height,
+    },
+
+    /// Parameter change activated event, thrown before the new round info of the activation
+    /// height,
+    /// for state: announce the change,
+    /// for timer: do nothing. The new interval and config are carried by the new round info, and
+    /// the new max round, if any, by this event.
+    #[display(
+        fmt = "Parameter change version {} activated at height {}",
+        version,
+        height
+    )]
+    ParamChangeActivated {
+        version: u64,
+        height: Height,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_round: Option<Round>,
     },
 
     /// Stop event,
@@ -199,9 +403,217 @@ pub enum SMREvent {
     Stop,
 }
 
+/// The SMR event of the default hash type.
+pub type SMREvent = GenericSMREvent<Hash>;
+
+/// The cause of a lock change.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum LockCause {
+    /// A prevote QC of a block sets or replaces the lock.
+    #[display(fmt = "Prevote QC")]
+    PrevoteQC,
+    /// A nil prevote QC removes the lock.
+    #[display(fmt = "Nil prevote QC")]
+    NilPrevoteQC,
+    /// A proposal with a higher lock round removes the lock.
+    #[display(fmt = "Higher lock round")]
+    HigherLockRound,
+    /// The lock expires after the expiry rounds.
+    #[display(fmt = "Expired")]
+    Expired,
+    /// A lock is adopted from a fetched prevote QC.
+    #[display(fmt = "Adopted")]
+    Adopted,
+    /// A lock is restored by the driver after a restart, from the WAL or from peers.
+    #[display(fmt = "Restored")]
+    Restored,
+    /// A new height removes the lock.
+    #[display(fmt = "New height")]
+    NewHeight,
+}
+
+/// The trigger causing a state transition.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum TransitionReason {
+    /// A new height trigger.
+    #[display(fmt = "New height")]
+    NewHeight,
+    /// A proposal from the state.
+    #[display(fmt = "Proposal")]
+    Proposal,
+    /// A prevote QC from the state.
+    #[display(fmt = "Prevote QC")]
+    PrevoteQC,
+    /// A precommit QC from the state.
+    #[display(fmt = "Precommit QC")]
+    PrecommitQC,
+    /// A choke QC from the state.
+    #[display(fmt = "Choke QC")]
+    ChokeQC,
+    /// A step timeout from the timer.
+    #[display(fmt = "Timeout")]
+    Timeout,
+    /// A commit certificate from the state.
+    #[display(fmt = "Commit certificate")]
+    CommitCertificate,
+    /// A sync commit from the state.
+    #[display(fmt = "Sync commit")]
+    SyncCommit,
+    /// A continue round trigger from the state.
+    #[display(fmt = "Continue round")]
+    ContinueRound,
+    /// A round skip trigger from the state.
+    #[display(fmt = "Round skip")]
+    RoundSkip,
+    /// A nil proposal trigger from the state.
+    #[display(fmt = "Nil proposal")]
+    NilProposal,
+}
+
+/// An immutable view of the SMR state, published after every transition.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GenericSmrView<H> {
+    /// The current height.
+    pub height: Height,
+    /// The current round.
+    pub round: Round,
+    /// The current step.
+    pub step: Step,
+    /// The current lock.
+    pub lock: Option<GenericLock<H>>,
+    /// The block committed in the current height.
+    pub last_commit: Option<GenericCommitProof<H>>,
+}
+
+/// The SMR view of the default hash type.
+pub type SmrView = GenericSmrView<Hash>;
+
+impl<H> Default for GenericSmrView<H> {
+    fn default() -> Self {
+        GenericSmrView {
+            height: Height::default(),
+            round: Round::default(),
+            step: Step::default(),
+            lock: None,
+            last_commit: None,
+        }
+    }
+}
+
+/// The persisted state of the SMR, which restores it between process restarts without replaying
+/// the whole WAL.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GenericSMRState<H> {
+    /// The current height.
+    pub height: Height,
+    /// The current round.
+    pub round: Round,
+    /// The current step.
+    pub step: Step,
+    /// The proposal of the current round.
+    pub block_hash: H,
+    /// The current lock.
+    pub lock: Option<GenericLock<H>>,
+    /// The sequence number of the last commit.
+    #[serde(default)]
+    pub commit_seq: u64,
+    /// The version of the active parameters.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub params_version: u64,
+    /// The max round of a height set by the active parameters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_round: Option<Round>,
+}
+
+/// The SMR state of the default hash type.
+pub type SMRState = GenericSMRState<Hash>;
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_default_reason(reason: &ViewChangeReason) -> bool {
+    *reason == ViewChangeReason::default()
+}
+
+/// The strategy of an event channel when its consumer falls behind.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelKind {
+    /// Keep every event.
+    #[default]
+    Unbounded,
+    /// Keep at most the given number of events, and reject a new event when full, which fails
+    /// the processing trigger.
+    Bounded(usize),
+    /// Keep the given number of latest events, and drop the oldest one when full. This is for
+    /// purely observational consumers.
+    Ring(usize),
+}
+
+/// What the SMR does with the events of a channel whose receiver is dropped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnChannelClosed {
+    /// Reject the events, which fails the processing trigger.
+    #[default]
+    Halt,
+    /// Drop the events until a receiver is reattached.
+    DropEvents,
+    /// Keep the events in the channel for the reattached receiver.
+    Reconnect,
+}
+
+/// How the SMR handles a violation of its self check invariants.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Display, PartialEq, Eq)]
+pub enum CheckLevel {
+    /// Skip the self checks.
+    #[display(fmt = "Off")]
+    Off,
+    /// Log the violations and continue.
+    #[display(fmt = "Warn")]
+    #[default]
+    Warn,
+    /// Panic on a violation, for tests.
+    #[display(fmt = "Strict")]
+    Strict,
+}
+
+/// The event channels of the SMR.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventChannel {
+    /// The channel of the state events.
+    State,
+    /// The channel of the timer events.
+    Timer,
+}
+
+/// Whether and how the SMR stamps the emission time on the events.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Display, PartialEq, Eq)]
+pub enum TimestampMode {
+    /// Do not stamp events.
+    #[default]
+    #[display(fmt = "Off")]
+    Off,
+    /// Stamp the monotonic emission time.
+    #[display(fmt = "Monotonic")]
+    Monotonic,
+    /// Stamp both the monotonic emission time and the wall clock time.
+    #[display(fmt = "Wall clock")]
+    WallClock,
+}
+
+/// The emission time of an SMR event.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventTime {
+    /// Monotonic nanoseconds since the SMR was created. Compare it with `Event::now` to measure
+    /// the latency between emission and consumption.
+    pub emitted_at: u64,
+    /// Milliseconds since the unix epoch, if the wall clock is enabled.
+    pub wall_clock: Option<u64>,
+}
+
 /// SMR trigger types.
-#[derive(Clone, Debug, Display, PartialEq, Eq)]
-pub enum TriggerType {
+#[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq)]
+pub enum GenericTriggerType<H> {
     /// Proposal trigger.
     #[display(fmt = "Proposal")]
     Proposal,
@@ -213,12 +625,47 @@ pub enum TriggerType {
     PrecommitQC,
     /// New Height trigger.
     #[display(fmt = "New height")]
-    NewHeight(SMRStatus),
+    NewHeight(Box<GenericSMRStatus<H>>),
     /// Continue new round trigger.
     #[display(fmt = "Continue Round")]
     ContinueRound,
+    /// Nil proposal trigger, for a proposer with nothing to propose. The SMR prevotes nil, or its
+    /// lock, without waiting for the propose timeout.
+    #[display(fmt = "Nil proposal")]
+    NilProposal,
+    /// Commit certificate trigger, carrying the proof of a block committed by a quorum in the
+    /// current height. The certificate must be verified against the validator set before, like
+    /// the QCs.
+    #[display(fmt = "Commit certificate")]
+    CommitCertificate(Box<GenericCommitProof<H>>),
+    /// Sync commit trigger, which fast forwards a lagging SMR to the given height with the proof
+    /// of the block committed in the previous height. The proof must be verified before, like the
+    /// QCs.
+    #[display(fmt = "Sync commit to height {}", height)]
+    SyncCommit {
+        /// The height to go to.
+        height: Height,
+        /// The proof of the block committed in `height - 1`.
+        proof: Box<GenericCommitProof<H>>,
+    },
+    /// Round skip trigger, which goes to a higher round of the current height after receiving the
+    /// messages of the round from f+1 validators, at least one of which is honest.
+    #[display(fmt = "Round skip to round {}", round)]
+    RoundSkip {
+        /// The round to go to.
+        round: Round,
+        /// The senders of the messages of the round.
+        proof: Box<RoundSkipProof>,
+    },
+    /// Brake timeout trigger from timer, which throws the brake event again to retry the choke
+    /// message.
+    #[display(fmt = "Brake timeout")]
+    BrakeTimeout,
 }
 
+/// The SMR trigger type of the default hash type.
+pub type TriggerType = GenericTriggerType<Hash>;
+
 /// SMR trigger sources.
 #[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq)]
 pub enum TriggerSource {
@@ -230,93 +677,1024 @@ pub enum TriggerSource {
     Timer = 1,
 }
 
-impl From<TriggerType> for u8 {
-    fn from(t: TriggerType) -> u8 {
+impl<H> From<GenericTriggerType<H>> for u8 {
+    fn from(t: GenericTriggerType<H>) -> u8 {
         match t {
-            TriggerType::Proposal => 0u8,
-            TriggerType::PrevoteQC => 1u8,
-            TriggerType::PrecommitQC => 2u8,
-            _ => unreachable!(),
+            GenericTriggerType::Proposal => 0u8,
+            GenericTriggerType::PrevoteQC => 1u8,
+            GenericTriggerType::PrecommitQC => 2u8,
+            GenericTriggerType::NewHeight(_) => 3u8,
+            GenericTriggerType::ContinueRound => 4u8,
+            GenericTriggerType::NilProposal => 5u8,
+            GenericTriggerType::CommitCertificate(_) => 6u8,
+            GenericTriggerType::SyncCommit { .. } => 7u8,
+            GenericTriggerType::RoundSkip { .. } => 8u8,
+            GenericTriggerType::BrakeTimeout => 9u8,
         }
     }
 }
 
-impl From<u8> for TriggerType {
-    /// It should not occur that call `from(3u8)`.
+impl<H> From<u8> for GenericTriggerType<H> {
+    /// It should not occur that call `from` with the number of a trigger type carrying data, which
+    /// is 3, 6, 7 or 8.
     fn from(s: u8) -> Self {
         match s {
-            0 => TriggerType::Proposal,
-            1 => TriggerType::PrevoteQC,
-            2 => TriggerType::PrecommitQC,
-            3 => unreachable!(),
+            0 => GenericTriggerType::Proposal,
+            1 => GenericTriggerType::PrevoteQC,
+            2 => GenericTriggerType::PrecommitQC,
+            4 => GenericTriggerType::ContinueRound,
+            5 => GenericTriggerType::NilProposal,
+            9 => GenericTriggerType::BrakeTimeout,
+            3 | 6..=8 => unreachable!(),
             _ => panic!("Invalid trigger type!"),
         }
     }
 }
 
+/// The value of a prevote or precommit QC, which is either a block hash or nil. A nil QC was
+/// conventionally an empty hash, which is still accepted by the conversions from and into `Hash`.
+#[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq, Hash)]
+#[display(bound = "H: AsRef<[u8]>")]
+pub enum GenericQcValue<H> {
+    /// A QC of a block.
+    #[display(fmt = "{}", "HexFmt(_0.as_ref())")]
+    Block(H),
+    /// A nil QC.
+    #[display(fmt = "nil")]
+    Nil,
+}
+
+/// The QC value of the default hash type.
+pub type QcValue = GenericQcValue<Hash>;
+
+impl<H: ConsensusHash> GenericQcValue<H> {
+    /// Return whether it is a nil QC.
+    pub fn is_nil(&self) -> bool {
+        *self == GenericQcValue::Nil
+    }
+
+    /// Return the hash of the QC, which is the empty hash for a nil QC.
+    pub fn into_hash(self) -> H {
+        match self {
+            GenericQcValue::Block(hash) => hash,
+            GenericQcValue::Nil => H::empty(),
+        }
+    }
+}
+
+impl<H: ConsensusHash> From<H> for GenericQcValue<H> {
+    /// An empty hash is a nil QC.
+    fn from(hash: H) -> Self {
+        if hash.is_empty() {
+            GenericQcValue::Nil
+        } else {
+            GenericQcValue::Block(hash)
+        }
+    }
+}
+
+impl From<QcValue> for Hash {
+    /// A nil QC is an empty hash.
+    fn from(value: QcValue) -> Self {
+        value.into_hash()
+    }
+}
+
 /// A SMR trigger to touch off SMR process. For different trigger type,
 /// the field `hash` and `round` have different restrictions and meaning.
 /// While trigger type is `Proposal`:
 ///     * `hash`: Proposal block hash,
 ///     * `round`: Optional lock round.
 /// While trigger type is `PrevoteQC` or `PrecommitQC`:
-///     * `hash`: QC block hash,
+///     * `hash`: QC block hash, or an empty hash for a nil QC, see `QcValue`,
 ///     * `round`: QC round, this must be `Some`.
 /// While trigger type is `NewHeight`:
 ///     * `hash`: A empty hash,
 ///     * `round`: This must be `None`.
 /// For each sources, while filling the `SMRTrigger`, the `height` field take the current height
 /// directly.
-#[derive(Clone, Debug, Display, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Debug, Display, PartialEq, Eq)]
 #[display(
     fmt = "{:?} trigger from {:?}, height {}",
     trigger_type,
     source,
     height
 )]
-pub struct SMRTrigger {
+pub struct GenericSMRTrigger<H> {
     /// SMR trigger type.
-    pub trigger_type: TriggerType,
+    pub trigger_type: GenericTriggerType<H>,
     /// SMR trigger source.
     pub source: TriggerSource,
     /// SMR trigger hash, the meaning shown above.
-    pub hash: Hash,
+    pub hash: H,
+    /// SMR trigger lock round, the meaning shown above.
+    pub lock_round: Option<Round>,
     /// SMR trigger round, the meaning shown above.
-    pub lock_round: Option<u64>,
-    ///
-    pub round: u64,
+    pub round: Round,
     /// **NOTICE**: This field is only for timer to signed timer's height. Therefore, the SMR can
     /// filter out the outdated timers.
-    pub height: u64,
+    pub height: Height,
 }
 
-/// An inner lock struct.
+/// The SMR trigger of the default hash type.
+pub type SMRTrigger = GenericSMRTrigger<Hash>;
+
+impl<H: ConsensusHash> GenericSMRTrigger<H> {
+    /// Create a proposal trigger from state, with the lock round of the PoLC carried by the
+    /// proposal, if any.
+    pub fn proposal(hash: H, lock_round: Option<Round>, round: Round, height: Height) -> Self {
+        GenericSMRTrigger {
+            trigger_type: GenericTriggerType::Proposal,
+            source: TriggerSource::State,
+            hash,
+            lock_round,
+            round,
+            height,
+        }
+    }
+
+    /// Create a nil proposal trigger from state, for a proposer with nothing to propose.
+    pub fn nil_proposal(round: Round, height: Height) -> Self {
+        GenericSMRTrigger {
+            trigger_type: GenericTriggerType::NilProposal,
+            source: TriggerSource::State,
+            hash: H::empty(),
+            lock_round: None,
+            round,
+            height,
+        }
+    }
+
+    /// Create a timeout trigger of the given step from timer. Only the propose, prevote, precommit
+    /// and brake steps time out.
+    pub fn timeout(step: Step, round: Round, height: Height) -> ConsensusResult<Self> {
+        let trigger_type = match step {
+            Step::Propose => GenericTriggerType::Proposal,
+            Step::Prevote => GenericTriggerType::PrevoteQC,
+            Step::Precommit => GenericTriggerType::PrecommitQC,
+            Step::Brake => GenericTriggerType::BrakeTimeout,
+            _ => {
+                return Err(ConsensusError::TimerErr(format!(
+                    "No timeout of {:?} step",
+                    step
+                )))
+            }
+        };
+
+        Ok(GenericSMRTrigger {
+            trigger_type,
+            source: TriggerSource::Timer,
+            hash: H::empty(),
+            lock_round: None,
+            round,
+            height,
+        })
+    }
+
+    /// Create a continue round trigger from state, for a choke QC of the previous round.
+    pub fn continue_round(round: Round, height: Height) -> Self {
+        GenericSMRTrigger {
+            trigger_type: GenericTriggerType::ContinueRound,
+            source: TriggerSource::State,
+            hash: H::empty(),
+            lock_round: None,
+            round,
+            height,
+        }
+    }
+
+    /// Create a QC trigger from state.
+    pub fn qc(vote_type: VoteType, value: GenericQcValue<H>, round: Round, height: Height) -> Self {
+        GenericSMRTrigger {
+            trigger_type: vote_type.into(),
+            source: TriggerSource::State,
+            hash: value.into_hash(),
+            lock_round: None,
+            round,
+            height,
+        }
+    }
+
+    /// Return the QC value of the hash, which is only meaningful for the QC triggers.
+    pub fn qc_value(&self) -> GenericQcValue<H> {
+        self.hash.clone().into()
+    }
+}
+
+impl<H: ConsensusHash> From<GenericSMRStatus<H>> for GenericSMRTrigger<H> {
+    /// Create a new height trigger from state. As the driver does, the trigger is stamped with
+    /// the height before the new one.
+    fn from(status: GenericSMRStatus<H>) -> Self {
+        GenericSMRTrigger {
+            height: Height(status.height.0.saturating_sub(1)),
+            trigger_type: GenericTriggerType::NewHeight(Box::new(status)),
+            source: TriggerSource::State,
+            hash: H::empty(),
+            lock_round: None,
+            round: INIT_ROUND,
+        }
+    }
+}
+
+/// An error event of a rejected trigger, so that the components other than the caller of
+/// `process`, such as metrics and peer scoring, can react to it.
+#[derive(Clone, Debug, Display)]
+#[display(
+    fmt = "{} trigger from {:?}, height {}, round {}, peer {:?}: {}",
+    trigger_type,
+    source,
+    height,
+    round,
+    "peer.as_ref().map(|peer| HexFmt(peer))",
+    error
+)]
+pub struct GenericErrorEvent<H> {
+    /// The error of processing the trigger.
+    pub error: ConsensusError,
+    /// The rejected trigger type.
+    pub trigger_type: GenericTriggerType<H>,
+    /// The rejected trigger source.
+    pub source: TriggerSource,
+    /// The rejected trigger height.
+    pub height: Height,
+    /// The rejected trigger round.
+    pub round: Round,
+    /// The peer that the trigger comes from, if known.
+    pub peer: Option<Address>,
+}
+
+/// The error event of the default hash type.
+pub type ErrorEvent = GenericErrorEvent<Hash>;
+
+/// A state transition of the SMR, passed to the hooks registered by
+/// `StateMachine::on_transition` and yielded by the audit stream of
+/// `StateMachine::new_with_audit`.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Lock {
+pub struct GenericTransitionRecord<H> {
+    /// The processed trigger.
+    pub trigger: GenericSMRTrigger<H>,
+    /// The state before processing the trigger.
+    pub before: GenericSMRState<H>,
+    /// The state after processing the trigger.
+    pub after: GenericSMRState<H>,
+    /// The events thrown by processing the trigger.
+    pub events: Vec<GenericSMREvent<H>>,
+    /// The error rejecting the trigger, which is always `None` for the hooks.
+    pub rejection: Option<String>,
+}
+
+/// The transition record of the default hash type.
+pub type TransitionRecord = GenericTransitionRecord<Hash>;
+
+/// The report of replaying a trigger log into the SMR.
+#[derive(Clone, Debug)]
+pub struct GenericReplayReport<H> {
+    /// Number of triggers processed, including the rejected ones.
+    pub processed: usize,
+    /// The index and the error of every rejected trigger.
+    pub rejected: Vec<(usize, ConsensusError)>,
+    /// Every thrown event with the index of the trigger throwing it.
+    pub events: Vec<(usize, GenericSMREvent<H>)>,
+    /// The state of the SMR after the replay.
+    pub view: GenericSmrView<H>,
+}
+
+/// The replay report of the default hash type.
+pub type ReplayReport = GenericReplayReport<Hash>;
+
+impl<H> Default for GenericReplayReport<H> {
+    fn default() -> Self {
+        GenericReplayReport {
+            processed: 0,
+            rejected: Vec::new(),
+            events: Vec::new(),
+            view: GenericSmrView::default(),
+        }
+    }
+}
+
+/// The reason why the SMR ignores a trigger without an error.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, PartialEq, Eq, Hash)]
+pub enum IgnoreReason {
+    /// A timeout QC of a round other than the current one, which is from a stale timer.
+    #[display(fmt = "Stale timer round")]
+    StaleTimerRound,
+}
+
+/// The outcome of a trigger processed without an error.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum ProcessOutcome {
+    /// The trigger changes the state or throws events.
+    #[display(fmt = "Applied")]
+    Applied,
+    /// The trigger is dropped since its height and round are not ahead of the SMR, such as a
+    /// trigger of a past round or a duplicate one.
+    #[display(fmt = "Ignored stale trigger of height {}, round {}", height, round)]
+    IgnoredStale { height: Height, round: Round },
+    /// The trigger is buffered since its height or round is ahead of the SMR. It is replayed when
+    /// the SMR reaches its height and round.
+    #[display(fmt = "Buffered future trigger of height {}, round {}", height, round)]
+    BufferedFuture { height: Height, round: Round },
+    /// The trigger is dropped since its height or round is ahead of the SMR, which means the SMR
+    /// lags behind and the driver may sync.
+    #[display(fmt = "Ignored future trigger of height {}, round {}", height, round)]
+    IgnoredFuture { height: Height, round: Round },
+}
+
+/// A diagnostic event for debugging, which does not affect the consensus.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+#[display(bound = "H: AsRef<[u8]>")]
+pub enum GenericDiagnostic<H> {
+    /// The trigger is ignored.
+    #[display(
+        fmt = "Ignore {} trigger of height {}, round {}: {}",
+        kind,
+        height,
+        round,
+        reason
+    )]
+    TriggerIgnored {
+        kind: GenericTriggerType<H>,
+        reason: IgnoreReason,
+        height: Height,
+        round: Round,
+    },
+    /// The proposal fails the check, such as a payload not hashing to the proposal hash, and the
+    /// SMR prevotes nil or its lock instead.
+    #[display(
+        fmt = "Reject proposal {:?} of height {}, round {}: {}",
+        "HexFmt(hash.as_ref())",
+        height,
+        round,
+        reason
+    )]
+    ProposalRejected {
+        hash: H,
+        reason: ViewChangeReason,
+        height: Height,
+        round: Round,
+    },
+}
+
+/// The diagnostic event of the default hash type.
+pub type Diagnostic = GenericDiagnostic<Hash>;
+
+/// An inner lock struct.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GenericLock<H> {
     /// Lock round.
-    pub round: u64,
+    pub round: Round,
     /// Lock hash.
-    pub hash: Hash,
+    pub hash: H,
+}
+
+/// The lock of the default hash type.
+pub type Lock = GenericLock<Hash>;
+
+/// A proof that the block committed in the previous height has been executed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GenericCommitProof<H> {
+    /// Committed height.
+    pub height: Height,
+    /// Committed block hash.
+    pub block_hash: H,
+}
+
+/// The commit proof of the default hash type.
+pub type CommitProof = GenericCommitProof<Hash>;
+
+/// The proof of a round skip, which is the distinct senders of the messages of a higher round.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoundSkipProof {
+    /// The addresses of the senders.
+    pub senders: Vec<Address>,
+}
+
+impl RoundSkipProof {
+    /// Check that the senders are well-formed, distinct and more than the faulty validators of
+    /// the given number of validators, which must come from the local configuration rather than
+    /// from the peer.
+    pub fn validate(&self, address_len: usize, validators: u64) -> ConsensusResult<()> {
+        let mut senders = HashSet::new();
+        for address in self.senders.iter() {
+            check_address(address, address_len)?;
+            if !senders.insert(address) {
+                return Err(ConsensusError::Other(format!(
+                    "Duplicate round skip sender {:?}",
+                    HexFmt(address)
+                )));
+            }
+        }
+
+        let faulty = validators.saturating_sub(1) / 3;
+        if validators == 0 || (senders.len() as u64) <= faulty {
+            return Err(ConsensusError::Other(format!(
+                "Round skip of {} senders of {} validators",
+                senders.len(),
+                validators
+            )));
+        }
+        Ok(())
+    }
 }
 
 /// SMR new status.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct SMRStatus {
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GenericSMRStatus<H> {
     /// New height.
-    pub height: u64,
+    pub height: Height,
     /// New height interval.
     pub new_interval: Option<u64>,
     /// New timeout configuration.
     pub new_config: Option<DurationConfig>,
+    /// New timeout configuration in absolute durations, which takes precedence over `new_config`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_timeouts: Option<TimeoutConfig>,
+    /// The proof of the just committed block. This is required while the SMR is in strict commit
+    /// mode.
+    pub commit_proof: Option<GenericCommitProof<H>>,
+    /// The parameter change included in the just committed block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param_change: Option<ParamChange>,
 }
 
-#[cfg(test)]
-impl SMRStatus {
-    pub fn new(height: u64) -> Self {
-        SMRStatus {
+/// The SMR status of the default hash type.
+pub type SMRStatus = GenericSMRStatus<Hash>;
+
+impl<H> GenericSMRStatus<H> {
+    /// Create a new status of the given height without any change.
+    pub fn new(height: Height) -> Self {
+        GenericSMRStatus {
             height,
             new_interval: None,
             new_config: None,
+            new_timeouts: None,
+            commit_proof: None,
+            param_change: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+    use std::fmt::Debug;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use crate::types::{
+        DurationConfig, Hash, Height, Round, TimeoutConfig, ViewChangeReason, VoteType,
+    };
+
+    use super::{
+        CommitProof, FromWhere, Lock, LockCause, QcValue, RoundSkipProof, SMREvent, SMRState,
+        SMRStatus, SMRTrigger, Step, TransitionReason, TriggerSource, TriggerType,
+    };
+
+    /// Check that the value serializes to the fixture and the fixture deserializes to the value.
+    /// The fixtures must not change, since these types are persisted and sent across processes.
+    fn check<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: T, fixture: &str) {
+        assert_eq!(serde_json::to_string(&value).unwrap(), fixture);
+        assert_eq!(serde_json::from_str::<T>(fixture).unwrap(), value);
+    }
+
+    fn hash() -> Bytes {
+        Bytes::from(vec![1u8, 2])
+    }
+
+    fn status() -> SMRStatus {
+        SMRStatus {
+            height: Height(2),
+            new_interval: Some(3000),
+            new_config: Some(DurationConfig::new(24, 10, 5, 3)),
+            commit_proof: Some(CommitProof {
+                height: Height(1),
+                block_hash: hash(),
+            }),
+            param_change: None,
+            new_timeouts: None,
+        }
+    }
+
+    #[test]
+    fn test_from_where() {
+        for from_where in [FromWhere::NewHeight, FromWhere::RoundSkip(Round(1))] {
+            assert_eq!(from_where.round(), None);
+            assert!(!from_where.is_qc());
+        }
+
+        for from_where in [
+            FromWhere::PrevoteQC(Round(1)),
+            FromWhere::PrecommitQC(Round(1)),
+            FromWhere::ChokeQC(Round(1)),
+        ] {
+            assert_eq!(from_where.round(), Some(Round(1)));
+            assert!(from_where.is_qc());
+        }
+    }
+
+    #[test]
+    fn test_round_skip_proof() {
+        let proof = |senders: &[u8]| RoundSkipProof {
+            senders: senders
+                .iter()
+                .map(|byte| Bytes::from(vec![*byte; 20]))
+                .collect(),
+        };
+        assert!(proof(&[1]).validate(20, 3).is_ok());
+        assert!(proof(&[1]).validate(20, 4).is_err());
+        assert!(proof(&[1, 2]).validate(20, 4).is_ok());
+        assert!(proof(&[1, 1]).validate(20, 4).is_err());
+        assert!(proof(&[1, 2]).validate(20, 7).is_err());
+        assert!(proof(&[1, 2, 3]).validate(20, 7).is_ok());
+        assert!(proof(&[1, 2, 3]).validate(32, 7).is_err());
+        assert!(proof(&[]).validate(20, 0).is_err());
+    }
+
+    #[test]
+    fn test_qc_value() {
+        assert_eq!(QcValue::from(Hash::new()), QcValue::Nil);
+        assert_eq!(QcValue::from(hash()), QcValue::Block(hash()));
+        assert_eq!(Hash::from(QcValue::Nil), Hash::new());
+        assert_eq!(Hash::from(QcValue::Block(hash())), hash());
+        assert_eq!(QcValue::Nil.to_string(), "nil");
+        assert_eq!(QcValue::Block(hash()).to_string(), "0102");
+
+        // A nil QC trigger keeps the empty hash convention on the wire.
+        let trigger = SMRTrigger::qc(VoteType::Precommit, QcValue::Nil, Round(1), Height(2));
+        assert_eq!(trigger.trigger_type, TriggerType::PrecommitQC);
+        assert!(trigger.hash.is_empty());
+        assert!(trigger.qc_value().is_nil());
+
+        check(QcValue::Nil, r#""Nil""#);
+        check(QcValue::Block(hash()), r#"{"Block":[1,2]}"#);
+    }
+
+    /// Check that the fixtures cover every variant. The `variant` functions below match the
+    /// variants exhaustively, so that a new variant does not compile until it is numbered, and the
+    /// numbering fails the test until the variant has a fixture.
+    fn check_variants<T>(values: &[T], variant: fn(&T) -> usize, count: usize) {
+        let covered = values.iter().map(variant).collect::<BTreeSet<_>>();
+        assert_eq!(covered, (0..count).collect::<BTreeSet<_>>());
+    }
+
+    fn step_variant(step: &Step) -> usize {
+        match step {
+            Step::Propose => 0,
+            Step::Prevote => 1,
+            Step::Precommit => 2,
+            Step::Brake => 3,
+            Step::Commit => 4,
+        }
+    }
+
+    fn from_where_variant(from_where: &FromWhere) -> usize {
+        match from_where {
+            FromWhere::NewHeight => 0,
+            FromWhere::PrevoteQC(_) => 1,
+            FromWhere::PrecommitQC(_) => 2,
+            FromWhere::ChokeQC(_) => 3,
+            FromWhere::RoundSkip(_) => 4,
+        }
+    }
+
+    fn trigger_type_variant(trigger_type: &TriggerType) -> usize {
+        match trigger_type {
+            TriggerType::Proposal => 0,
+            TriggerType::PrevoteQC => 1,
+            TriggerType::PrecommitQC => 2,
+            TriggerType::NewHeight(_) => 3,
+            TriggerType::ContinueRound => 4,
+            TriggerType::NilProposal => 5,
+            TriggerType::CommitCertificate(_) => 6,
+            TriggerType::SyncCommit { .. } => 7,
+            TriggerType::RoundSkip { .. } => 8,
+            TriggerType::BrakeTimeout => 9,
+        }
+    }
+
+    fn event_variant(event: &SMREvent) -> usize {
+        match event {
+            SMREvent::NewRoundInfo { .. } => 0,
+            SMREvent::PrevoteVote { .. } => 1,
+            SMREvent::PrecommitVote { .. } => 2,
+            SMREvent::Commit { .. } => 3,
+            SMREvent::Brake { .. } => 4,
+            SMREvent::LockExpired { .. } => 5,
+            SMREvent::WatchdogReport { .. } => 6,
+            SMREvent::SloBreach { .. } => 7,
+            SMREvent::LockChanged { .. } => 8,
+            SMREvent::StateTransition { .. } => 9,
+            SMREvent::QuorumUnavailable { .. } => 10,
+            SMREvent::ForkEvidence { .. } => 11,
+            SMREvent::ParamChangePending { .. } => 12,
+            SMREvent::ParamChangeActivated { .. } => 13,
+            SMREvent::Stop => 14,
+        }
+    }
+
+    #[test]
+    fn test_step_serde() {
+        let fixtures = [
+            (Step::Propose, r#""Propose""#),
+            (Step::Prevote, r#""Prevote""#),
+            (Step::Precommit, r#""Precommit""#),
+            (Step::Brake, r#""Brake""#),
+            (Step::Commit, r#""Commit""#),
+        ];
+        fixtures
+            .iter()
+            .for_each(|(step, fixture)| check(step.clone(), fixture));
+        let values = fixtures.map(|(value, _)| value);
+        check_variants(&values, step_variant, 5);
+    }
+
+    #[test]
+    fn test_from_where_serde() {
+        let fixtures = [
+            (FromWhere::NewHeight, r#""NewHeight""#),
+            (FromWhere::PrevoteQC(Round(1)), r#"{"PrevoteQC":1}"#),
+            (FromWhere::PrecommitQC(Round(1)), r#"{"PrecommitQC":1}"#),
+            (FromWhere::ChokeQC(Round(1)), r#"{"ChokeQC":1}"#),
+            (FromWhere::RoundSkip(Round(1)), r#"{"RoundSkip":1}"#),
+        ];
+        fixtures
+            .iter()
+            .for_each(|(from_where, fixture)| check(from_where.clone(), fixture));
+        let values = fixtures.map(|(value, _)| value);
+        check_variants(&values, from_where_variant, 5);
+    }
+
+    #[test]
+    fn test_event_serde() {
+        let fixtures = vec![
+            (
+                SMREvent::NewRoundInfo {
+                    height: Height(1),
+                    round: Round(2),
+                    lock_round: Some(Round(1)),
+                    lock_proposal: Some(hash()),
+                    from_where: FromWhere::PrevoteQC(Round(1)),
+                    reason: ViewChangeReason::Others,
+                    new_interval: None,
+                    new_config: None,
+                    new_timeouts: None,
+                },
+                r#"{"NewRoundInfo":{"height":1,"round":2,"lock_round":1,"lock_proposal":[1,2],"from_where":{"PrevoteQC":1},"new_interval":null,"new_config":null}}"#,
+            ),
+            (
+                SMREvent::NewRoundInfo {
+                    height: Height(1),
+                    round: Round(2),
+                    lock_round: Some(Round(1)),
+                    lock_proposal: Some(hash()),
+                    from_where: FromWhere::PrevoteQC(Round(1)),
+                    reason: ViewChangeReason::UpdateFromHigherPrevoteQC(Round(0), Round(1)),
+                    new_interval: None,
+                    new_config: None,
+                    new_timeouts: None,
+                },
+                r#"{"NewRoundInfo":{"height":1,"round":2,"lock_round":1,"lock_proposal":[1,2],"from_where":{"PrevoteQC":1},"reason":{"UpdateFromHigherPrevoteQC":[0,1]},"new_interval":null,"new_config":null}}"#,
+            ),
+            (
+                SMREvent::PrevoteVote {
+                    height: Height(1),
+                    round: Round(2),
+                    block_hash: hash(),
+                    lock_round: None,
+                },
+                r#"{"PrevoteVote":{"height":1,"round":2,"block_hash":[1,2],"lock_round":null}}"#,
+            ),
+            (
+                SMREvent::PrecommitVote {
+                    height: Height(1),
+                    round: Round(2),
+                    block_hash: hash(),
+                    lock_round: Some(Round(2)),
+                },
+                r#"{"PrecommitVote":{"height":1,"round":2,"block_hash":[1,2],"lock_round":2}}"#,
+            ),
+            (
+                SMREvent::Commit {
+                    height: Height(1),
+                    round: Round(2),
+                    block_hash: hash(),
+                    polc_round: Some(Round(2)),
+                    commit_seq: 3,
+                },
+                r#"{"Commit":{"height":1,"round":2,"block_hash":[1,2],"polc_round":2,"commit_seq":3}}"#,
+            ),
+            (
+                SMREvent::Brake {
+                    height: Height(1),
+                    round: Round(2),
+                    lock_round: None,
+                },
+                r#"{"Brake":{"height":1,"round":2,"lock_round":null}}"#,
+            ),
+            (
+                SMREvent::StateTransition {
+                    height: Height(1),
+                    round: Round(2),
+                    from_step: Step::Prevote,
+                    to_step: Step::Precommit,
+                    reason: TransitionReason::Timeout,
+                },
+                r#"{"StateTransition":{"height":1,"round":2,"from_step":"Prevote","to_step":"Precommit","reason":"Timeout"}}"#,
+            ),
+            (
+                SMREvent::SloBreach {
+                    height: Height(1),
+                    actual_ms: 3500,
+                    budget_ms: 3000,
+                },
+                r#"{"SloBreach":{"height":1,"actual_ms":3500,"budget_ms":3000}}"#,
+            ),
+            (
+                SMREvent::LockExpired {
+                    height: Height(1),
+                    round: Round(4),
+                    lock_round: Round(1),
+                    lock_proposal: hash(),
+                },
+                r#"{"LockExpired":{"height":1,"round":4,"lock_round":1,"lock_proposal":[1,2]}}"#,
+            ),
+            (
+                SMREvent::WatchdogReport {
+                    height: Height(1),
+                    round: Round(2),
+                    step: Step::Prevote,
+                    lock: Some(Lock {
+                        round: Round(1),
+                        hash: hash(),
+                    }),
+                    stalled_ms: 5000,
+                    recent_events: vec![SMREvent::Stop],
+                },
+                r#"{"WatchdogReport":{"height":1,"round":2,"step":"Prevote","lock":{"round":1,"hash":[1,2]},"stalled_ms":5000,"recent_events":["Stop"]}}"#,
+            ),
+            (
+                SMREvent::LockChanged {
+                    height: Height(1),
+                    round: Round(4),
+                    old: Some(Lock {
+                        round: Round(1),
+                        hash: hash(),
+                    }),
+                    new: None,
+                    cause: LockCause::Expired,
+                },
+                r#"{"LockChanged":{"height":1,"round":4,"old":{"round":1,"hash":[1,2]},"new":null,"cause":"Expired"}}"#,
+            ),
+            (
+                SMREvent::QuorumUnavailable {
+                    height: Height(1),
+                    round: Round(2),
+                    missing_power: 3,
+                    validators: vec![hash()],
+                },
+                r#"{"QuorumUnavailable":{"height":1,"round":2,"missing_power":3,"validators":[[1,2]]}}"#,
+            ),
+            (
+                SMREvent::ForkEvidence {
+                    height: Height(1),
+                    round: Round(2),
+                    local_hash: hash(),
+                    remote_hash: Bytes::from(vec![3u8, 4]),
+                },
+                r#"{"ForkEvidence":{"height":1,"round":2,"local_hash":[1,2],"remote_hash":[3,4]}}"#,
+            ),
+            (
+                SMREvent::ParamChangePending {
+                    version: 2,
+                    activation_height: Height(5),
+                },
+                r#"{"ParamChangePending":{"version":2,"activation_height":5}}"#,
+            ),
+            (
+                SMREvent::ParamChangeActivated {
+                    version: 2,
+                    height: Height(5),
+                    max_round: None,
+                },
+                r#"{"ParamChangeActivated":{"version":2,"height":5}}"#,
+            ),
+            (SMREvent::Stop, r#""Stop""#),
+        ];
+        fixtures
+            .iter()
+            .for_each(|(event, fixture)| check(event.clone(), fixture));
+        let values = fixtures
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect::<Vec<_>>();
+        check_variants(&values, event_variant, 15);
+    }
+
+    /// Every channel send and trigger copies the largest variant, so the large payloads, such as
+    /// the status and the proofs, are boxed. A new variant must not grow the hot path types. The
+    /// new round info carries the boxed height parameters, a pointer each.
+    #[test]
+    fn test_size() {
+        const _: () = assert!(std::mem::size_of::<TriggerType>() <= 24);
+        const _: () = assert!(std::mem::size_of::<SMRTrigger>() <= 96);
+        const _: () = assert!(std::mem::size_of::<SMREvent>() <= 136);
+    }
+
+    #[test]
+    fn test_trigger_constructors() {
+        let new_height = SMRTrigger::from(status());
+        assert_eq!(
+            new_height.trigger_type,
+            TriggerType::NewHeight(Box::new(status()))
+        );
+        assert_eq!(new_height.source, TriggerSource::State);
+        assert_eq!((new_height.height, new_height.round), (Height(1), Round(0)));
+
+        let proposal = SMRTrigger::proposal(hash(), Some(Round(1)), Round(2), Height(3));
+        assert_eq!(proposal.trigger_type, TriggerType::Proposal);
+        assert_eq!(proposal.source, TriggerSource::State);
+        assert_eq!(proposal.lock_round, Some(Round(1)));
+        assert_eq!((proposal.height, proposal.round), (Height(3), Round(2)));
+
+        for (step, trigger_type) in [
+            (Step::Propose, TriggerType::Proposal),
+            (Step::Prevote, TriggerType::PrevoteQC),
+            (Step::Precommit, TriggerType::PrecommitQC),
+        ] {
+            let timeout = SMRTrigger::timeout(step, Round(2), Height(3)).unwrap();
+            assert_eq!(timeout.trigger_type, trigger_type);
+            assert_eq!(timeout.source, TriggerSource::Timer);
+            assert!(timeout.hash.is_empty());
+        }
+        assert!(SMRTrigger::timeout(Step::Commit, Round(2), Height(3)).is_err());
+
+        let nil_proposal = SMRTrigger::nil_proposal(Round(2), Height(3));
+        assert_eq!(nil_proposal.trigger_type, TriggerType::NilProposal);
+        assert_eq!(nil_proposal.source, TriggerSource::State);
+
+        let continue_round = SMRTrigger::continue_round(Round(2), Height(3));
+        assert_eq!(continue_round.trigger_type, TriggerType::ContinueRound);
+        assert_eq!(continue_round.source, TriggerSource::State);
+    }
+
+    #[test]
+    fn test_trigger_type_number() {
+        let proof = CommitProof {
+            height: Height(1),
+            block_hash: hash(),
+        };
+        let trigger_types = [
+            TriggerType::Proposal,
+            TriggerType::PrevoteQC,
+            TriggerType::PrecommitQC,
+            TriggerType::NewHeight(Box::new(status())),
+            TriggerType::ContinueRound,
+            TriggerType::NilProposal,
+            TriggerType::CommitCertificate(Box::new(proof.clone())),
+            TriggerType::SyncCommit {
+                height: Height(2),
+                proof: Box::new(proof),
+            },
+            TriggerType::RoundSkip {
+                round: Round(3),
+                proof: Box::new(RoundSkipProof { senders: vec![] }),
+            },
+            TriggerType::BrakeTimeout,
+        ];
+        check_variants(&trigger_types, trigger_type_variant, 10);
+        for trigger_type in trigger_types {
+            let number = u8::from(trigger_type.clone());
+            assert_eq!(number as usize, trigger_type_variant(&trigger_type));
+            if !matches!(number, 3 | 6..=8) {
+                assert_eq!(TriggerType::from(number), trigger_type);
+            }
         }
     }
+
+    #[test]
+    fn test_trigger_serde() {
+        check(TriggerSource::State, r#""State""#);
+        check(TriggerSource::Timer, r#""Timer""#);
+
+        let fixtures = vec![
+            (TriggerType::Proposal, r#""Proposal""#),
+            (TriggerType::PrevoteQC, r#""PrevoteQC""#),
+            (TriggerType::PrecommitQC, r#""PrecommitQC""#),
+            (
+                TriggerType::NewHeight(Box::new(status())),
+                r#"{"NewHeight":{"height":2,"new_interval":3000,"new_config":{"propose_ratio":24,"prevote_ratio":10,"precommit_ratio":5,"brake_ratio":3},"commit_proof":{"height":1,"block_hash":[1,2]}}}"#,
+            ),
+            (TriggerType::ContinueRound, r#""ContinueRound""#),
+            (TriggerType::NilProposal, r#""NilProposal""#),
+            (
+                TriggerType::RoundSkip {
+                    round: Round(3),
+                    proof: Box::new(RoundSkipProof {
+                        senders: vec![hash()],
+                    }),
+                },
+                r#"{"RoundSkip":{"round":3,"proof":{"senders":[[1,2]]}}}"#,
+            ),
+            (
+                TriggerType::CommitCertificate(Box::new(CommitProof {
+                    height: Height(1),
+                    block_hash: hash(),
+                })),
+                r#"{"CommitCertificate":{"height":1,"block_hash":[1,2]}}"#,
+            ),
+            (
+                TriggerType::SyncCommit {
+                    height: Height(2),
+                    proof: Box::new(CommitProof {
+                        height: Height(1),
+                        block_hash: hash(),
+                    }),
+                },
+                r#"{"SyncCommit":{"height":2,"proof":{"height":1,"block_hash":[1,2]}}}"#,
+            ),
+            (TriggerType::BrakeTimeout, r#""BrakeTimeout""#),
+        ];
+        fixtures
+            .iter()
+            .for_each(|(trigger_type, fixture)| check(trigger_type.clone(), fixture));
+        let values = fixtures
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect::<Vec<_>>();
+        check_variants(&values, trigger_type_variant, 10);
+
+        check(
+            SMRTrigger {
+                trigger_type: TriggerType::Proposal,
+                source: TriggerSource::State,
+                hash: hash(),
+                lock_round: Some(Round(1)),
+                round: Round(2),
+                height: Height(1),
+            },
+            r#"{"trigger_type":"Proposal","source":"State","hash":[1,2],"lock_round":1,"round":2,"height":1}"#,
+        );
+    }
+
+    #[test]
+    fn test_status_serde() {
+        check(
+            status(),
+            r#"{"height":2,"new_interval":3000,"new_config":{"propose_ratio":24,"prevote_ratio":10,"precommit_ratio":5,"brake_ratio":3},"commit_proof":{"height":1,"block_hash":[1,2]}}"#,
+        );
+        check(
+            SMRStatus::new(Height(2)),
+            r#"{"height":2,"new_interval":null,"new_config":null,"commit_proof":null}"#,
+        );
+        let mut absolute = SMRStatus::new(Height(2));
+        absolute.new_timeouts = Some(TimeoutConfig::new(
+            Duration::from_secs(3),
+            Duration::from_secs(1),
+            Duration::from_millis(500),
+            Duration::from_millis(300),
+        ));
+        check(
+            absolute,
+            r#"{"height":2,"new_interval":null,"new_config":null,"new_timeouts":{"propose":{"secs":3,"nanos":0},"prevote":{"secs":1,"nanos":0},"precommit":{"secs":0,"nanos":500000000},"brake":{"secs":0,"nanos":300000000}},"commit_proof":null}"#,
+        );
+        check(
+            Lock {
+                round: Round(1),
+                hash: hash(),
+            },
+            r#"{"round":1,"hash":[1,2]}"#,
+        );
+        check(
+            SMRState {
+                height: Height(2),
+                round: Round(1),
+                step: Step::Precommit,
+                block_hash: hash(),
+                lock: Some(Lock {
+                    round: Round(1),
+                    hash: hash(),
+                }),
+                commit_seq: 1,
+                params_version: 0,
+                max_round: None,
+            },
+            r#"{"height":2,"round":1,"step":"Precommit","block_hash":[1,2],"lock":{"round":1,"hash":[1,2]},"commit_seq":1}"#,
+        );
+        check(
+            SMRState {
+                height: Height(2),
+                round: Round(1),
+                step: Step::Propose,
+                block_hash: Hash::new(),
+                lock: None,
+                commit_seq: 1,
+                params_version: 3,
+                max_round: Some(Round(9)),
+            },
+            r#"{"height":2,"round":1,"step":"Propose","block_hash":[],"lock":null,"commit_seq":1,"params_version":3,"max_round":9}"#,
+        );
+    }
 }
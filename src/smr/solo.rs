@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+use crate::error::ConsensusError;
+use crate::smr::smr_types::{CommitProof, SMREvent, SMRStatus, SMRTrigger};
+use crate::smr::{state_machine::StateMachine, wal::WalBackend, Event};
+use crate::types::{ConsensusResult, Hash, Height, VoteType, INIT_HEIGHT};
+
+/// A single validator driver for local development chains. As the only validator, its own votes
+/// are trivially a QC, so it feeds every vote back to the SMR as the corresponding QC and starts a
+/// new height on each tick of the block interval after a commit.
+///
+/// The driver is generic over the proposal function and the WAL backend, so that neither is
+/// called through a trait object on the hot path. Use [`DynSolo`] to pick them at runtime instead.
+#[rustfmt::skip]
+pub struct Solo<F, W = ()> {
+    smr:      StateMachine,
+    height:   Height,
+    rx_state: Event,
+    rx_timer: Event,
+    ticker:   Interval,
+    propose:  F,
+    wal:      W,
+    commit:   UnboundedSender<CommitProof>,
+}
+
+/// A solo driver with the boxed proposal function and WAL backend.
+pub type DynSolo = Solo<Box<dyn FnMut(Height) -> Hash + Send>, Box<dyn WalBackend + Send>>;
+
+impl<F> Solo<F>
+where
+    F: FnMut(Height) -> Hash,
+{
+    /// Create a solo driver with the block interval and a function returning the non-empty
+    /// proposal hash of a height. The committed blocks are sent to the returned receiver.
+    pub fn new(block_interval: Duration, propose: F) -> (Self, UnboundedReceiver<CommitProof>) {
+        let (mut smr, rx_state, rx_timer) = StateMachine::new();
+        smr.set_strict_commit(true);
+        let (tx_commit, rx_commit) = unbounded();
+
+        let mut ticker = interval(block_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let solo = Solo {
+            smr,
+            height: INIT_HEIGHT,
+            rx_state,
+            rx_timer,
+            ticker,
+            propose,
+            wal: (),
+            commit: tx_commit,
+        };
+        (solo, rx_commit)
+    }
+}
+
+impl<F, W> Solo<F, W>
+where
+    F: FnMut(Height) -> Hash,
+    W: WalBackend,
+{
+    /// Append every trigger to the WAL before processing it.
+    pub fn with_wal<V: WalBackend>(self, wal: V) -> Solo<F, V> {
+        Solo {
+            smr: self.smr,
+            height: self.height,
+            rx_state: self.rx_state,
+            rx_timer: self.rx_timer,
+            ticker: self.ticker,
+            propose: self.propose,
+            wal,
+            commit: self.commit,
+        }
+    }
+
+    /// Run the solo chain. This returns `Ok(())` after the commit receiver is dropped, or the
+    /// first error of the SMR.
+    pub async fn run(mut self) -> ConsensusResult<()> {
+        let mut proof = None;
+
+        loop {
+            self.ticker.tick().await;
+            let mut status = SMRStatus::new(self.height + 1);
+            status.commit_proof = proof.take();
+            self.process(status.into())?;
+            self.height = self.height.next();
+
+            let committed = self.run_height().await?;
+            if self.commit.unbounded_send(committed.clone()).is_err() {
+                smr_debug!("Tendermint: solo commit receiver dropped, stop");
+                return Ok(());
+            }
+            proof = Some(committed);
+        }
+    }
+
+    /// Drive the SMR of the current height until commit.
+    async fn run_height(&mut self) -> ConsensusResult<CommitProof> {
+        let height = self.height;
+
+        loop {
+            // The timer events are useless because nothing times out in solo mode.
+            while self.rx_timer.try_next().is_some() {}
+
+            let event = self.rx_state.next().await.ok_or_else(|| {
+                ConsensusError::MonitorEventErr("Solo state event channel closed".to_string())
+            })?;
+
+            let trigger = match event {
+                SMREvent::NewRoundInfo { round, .. } => {
+                    SMRTrigger::proposal((self.propose)(height), None, round, height)
+                }
+                SMREvent::PrevoteVote {
+                    round, block_hash, ..
+                } => SMRTrigger::qc(VoteType::Prevote, block_hash.into(), round, height),
+                SMREvent::PrecommitVote {
+                    round, block_hash, ..
+                } => SMRTrigger::qc(VoteType::Precommit, block_hash.into(), round, height),
+                SMREvent::Commit { block_hash, .. } => {
+                    return Ok(CommitProof { height, block_hash });
+                }
+                _ => continue,
+            };
+            self.process(trigger)?;
+        }
+    }
+
+    fn process(&mut self, trigger: SMRTrigger) -> ConsensusResult<()> {
+        self.wal.append(&trigger)?;
+        self.smr.process(trigger)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::channel::mpsc::{unbounded, UnboundedSender};
+    use futures::StreamExt;
+
+    use crate::smr::smr_types::SMRTrigger;
+    use crate::smr::state_machine::StateMachine;
+    use crate::smr::wal::{ReplayProgress, WalBackend, WalReplayer};
+    use crate::types::{ConsensusResult, Hash, Height};
+
+    use super::{DynSolo, Solo};
+
+    struct Recorder(UnboundedSender<SMRTrigger>);
+
+    impl WalBackend for Recorder {
+        fn append(&mut self, trigger: &SMRTrigger) -> ConsensusResult<()> {
+            let _ = self.0.unbounded_send(trigger.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solo() {
+        let (solo, mut rx_commit) = Solo::new(Duration::from_millis(1), |height: Height| {
+            Bytes::from(height.0.to_be_bytes().to_vec())
+        });
+        let handle = tokio::spawn(solo.run());
+
+        for height in 1..=3u64 {
+            let proof = rx_commit.next().await.unwrap();
+            assert_eq!(proof.height, Height(height));
+            assert_eq!(proof.block_hash, Bytes::from(height.to_be_bytes().to_vec()));
+        }
+
+        drop(rx_commit);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_solo_wal() {
+        let propose = |height: Height| Bytes::from(height.0.to_be_bytes().to_vec());
+        let (tx_wal, rx_wal) = unbounded();
+        let (solo, mut rx_commit) = Solo::new(
+            Duration::from_millis(1),
+            Box::new(propose) as Box<dyn FnMut(Height) -> Hash + Send>,
+        );
+        let solo: DynSolo = solo.with_wal(Box::new(Recorder(tx_wal)) as Box<dyn WalBackend + Send>);
+        let handle = tokio::spawn(solo.run());
+
+        for _ in 1..=3u64 {
+            rx_commit.next().await.unwrap();
+        }
+        drop(rx_commit);
+        assert!(handle.await.unwrap().is_ok());
+
+        // Replaying the WAL brings a new SMR to the height the solo driver stopped at.
+        let entries = rx_wal.collect::<Vec<_>>().await;
+        let height = entries.last().unwrap().height;
+        assert!(height >= Height(3));
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let progress = WalReplayer::new(4, |_: &ReplayProgress| {}).replay(&mut smr, entries);
+        assert_eq!(progress.rejected, 0);
+        assert_eq!(progress.height, height);
+    }
+}
@@ -1,460 +1,4472 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use bytes::Bytes;
 use derive_more::Display;
-use futures::channel::mpsc::{unbounded, UnboundedSender};
-use hummer::coding::hex_encode;
 
+use crate::error::ConsensusError;
+use crate::identity::{check_address, ADDRESS_LEN};
 use crate::smr::smr_types::{
-    FromWhere, Lock, SMREvent, SMRStatus, SMRTrigger, Step, TriggerSource, TriggerType,
+    ChannelKind, CheckLevel, EventChannel, FromWhere, GenericCommitProof, GenericDiagnostic,
+    GenericErrorEvent, GenericLock, GenericQcValue, GenericReplayReport, GenericSMREvent,
+    GenericSMRState, GenericSMRStatus, GenericSMRTrigger, GenericSmrView, GenericTransitionRecord,
+    GenericTriggerType, IgnoreReason, LockCause, OnChannelClosed, ProcessOutcome, RoundSkipProof,
+    Step, TimestampMode, TransitionReason, TriggerSource,
+};
+use crate::smr::{
+    channel, event_channel, ChannelDepth, Clock, EventSender, GenericEvent, GenericSmrViewHandle,
+    LatencyHistogram, Receiver, Sender,
+};
+use crate::types::{
+    Address, ConsensusHash, ConsensusResult, DurationConfig, GenesisConfig, Hash, Hasher, Height,
+    HexFmt, ParamChange, Round, TimeoutConfig, ViewChangeReason,
 };
-use crate::{error::ConsensusError, smr::Event, types::Hash};
-use crate::types::{ConsensusResult, INIT_HEIGHT, INIT_ROUND};
 
 #[derive(Debug, Display)]
 #[rustfmt::skip]
 #[display(fmt = "State machine height {}, round {}, step {:?}", height, round, step)]
-pub struct StateMachine {
-    height:        u64,
-    round:         u64,
+pub struct GenericStateMachine<H> {
+    height:        Height,
+    round:         Round,
     step:          Step,
-    block_hash:    Hash,
-    lock:          Option<Lock>,
+    block_hash:    H,
+    lock:          Option<GenericLock<H>>,
+    last_commit:   Option<GenericCommitProof<H>>,
+    commit_seq:    u64,
+    strict_commit: bool,
+    strict_timer:  bool,
+    brake:         bool,
+    check_level:   CheckLevel,
+    transitions:   bool,
+    init_round:    Round,
+    address_len:   usize,
+    validators:    Option<u64>,
+    lock_expiry:   Option<u64>,
+    watchdog:      Option<Watchdog<H>>,
+    commit_slo:    Option<CommitSlo>,
+    view:          GenericSmrViewHandle<H>,
+    params_version: u64,
+    pending_params: Option<ParamChange>,
+    max_round:      Option<Round>,
+    received:       Option<(TriggerSource, u64)>,
+    latency:        (LatencyHistogram, LatencyHistogram),
+    captured:       Option<Vec<GenericSMREvent<H>>>,
+    hooks:          TransitionHooks<H>,
+    hook_events:    Option<Vec<GenericSMREvent<H>>>,
+    audit:          Option<Sender<GenericTransitionRecord<H>>>,
+    events_thrown:  u64,
+    future_buffered: u64,
+    future:         Vec<GenericSMRTrigger<H>>,
+    stopped:        bool,
 
-    event:   (UnboundedSender<SMREvent>, UnboundedSender<SMREvent>),
+    event:   (EventSender<H>, EventSender<H>),
+    errors:  Option<Sender<GenericErrorEvent<H>>>,
+    diagnostics: Option<Sender<GenericDiagnostic<H>>>,
+    ignored: HashMap<IgnoreReason, u64>,
+    clock:   Clock,
+    timestamp_mode: TimestampMode,
 }
 
-impl StateMachine {
+/// The state machine of the default hash type.
+pub type StateMachine = GenericStateMachine<Hash>;
+
+impl<H: ConsensusHash> GenericStateMachine<H> {
     /// Create a new state machine.
-    pub fn new() -> (Self, Event, Event) {
-        let (tx_state, rx_state) = unbounded();
-        let (tx_timer, rx_timer) = unbounded();
+    pub fn new() -> (Self, GenericEvent<H>, GenericEvent<H>) {
+        Self::with_genesis(GenesisConfig::default())
+    }
+
+    /// Create a new state machine starting from the given genesis parameters.
+    pub fn with_genesis(genesis: GenesisConfig) -> (Self, GenericEvent<H>, GenericEvent<H>) {
+        Self::with_channels(genesis, ChannelKind::default(), ChannelKind::default())
+    }
+
+    /// Create a new state machine with an audit stream besides the state and timer event streams.
+    /// The audit stream yields a record of every processed trigger, including the ignored and the
+    /// rejected ones, for conformance checking. Dropping it does not affect processing.
+    pub fn new_with_audit() -> (
+        Self,
+        GenericEvent<H>,
+        GenericEvent<H>,
+        Receiver<GenericTransitionRecord<H>>,
+    ) {
+        let (mut smr, rx_state, rx_timer) = Self::new();
+        let (tx, rx_audit) = channel();
+        smr.audit = Some(tx);
+        (smr, rx_state, rx_timer, rx_audit)
+    }
+
+    /// Return a builder to resume the state machine from a given state.
+    pub fn builder() -> GenericStateMachineBuilder<H> {
+        GenericStateMachineBuilder::new()
+    }
+
+    /// Create a new state machine with the given kinds of the state and timer event channels.
+    pub fn with_channels(
+        genesis: GenesisConfig,
+        state: ChannelKind,
+        timer: ChannelKind,
+    ) -> (Self, GenericEvent<H>, GenericEvent<H>) {
+        let clock = Clock::new();
+        let (tx_state, rx_state) = event_channel(state, clock);
+        let (tx_timer, rx_timer) = event_channel(timer, clock);
 
-        let state_machine = StateMachine {
-            height: INIT_HEIGHT,
-            round: INIT_ROUND,
+        let state_machine = GenericStateMachine {
+            height: genesis.init_height,
+            round: genesis.init_round,
             step: Step::default(),
-            block_hash: Hash::new(),
+            block_hash: H::empty(),
             lock: None,
+            last_commit: None,
+            commit_seq: 0,
+            strict_commit: false,
+            strict_timer: false,
+            brake: false,
+            check_level: CheckLevel::default(),
+            transitions: false,
+            init_round: genesis.init_round,
+            address_len: ADDRESS_LEN,
+            validators: None,
+            lock_expiry: None,
+            watchdog: None,
+            commit_slo: None,
+            view: GenericSmrViewHandle::default(),
+            params_version: 0,
+            pending_params: None,
+            max_round: None,
+            received: None,
+            latency: Default::default(),
+            captured: None,
+            hooks: TransitionHooks::default(),
+            hook_events: None,
+            audit: None,
+            events_thrown: 0,
+            future_buffered: 0,
+            future: Vec::new(),
+            stopped: false,
             event: (tx_state, tx_timer),
+            errors: None,
+            diagnostics: None,
+            ignored: HashMap::new(),
+            clock,
+            timestamp_mode: TimestampMode::default(),
         };
+        state_machine.publish_view();
 
-        (state_machine, Event::new(rx_state), Event::new(rx_timer))
+        (state_machine, rx_state, rx_timer)
     }
 
-    pub fn process(&mut self, msg: SMRTrigger) -> ConsensusResult<()> {
-        let trigger_type = msg.trigger_type.clone();
-        let res = match trigger_type {
-            TriggerType::NewHeight(status) => {
-                self.handle_new_height(status, msg.source)
-            }
-            TriggerType::Proposal => self.handle_proposal(
-                msg.hash,
-                msg.round,
-                msg.lock_round,
-                msg.source,
-                msg.height,
-            ),
-            TriggerType::PrevoteQC => {
-                self.handle_prevote(msg.hash, msg.round, msg.source, msg.height)
-            }
-            TriggerType::PrecommitQC => {
-                self.handle_precommit(msg.hash, msg.round, msg.source, msg.height)
-            }
-            TriggerType::ContinueRound => {
-                assert!(msg.source == TriggerSource::State);
-                self.handle_continue_round(msg.height, msg.round)
-            }
-        };
-        return res;
+    /// Return the current height.
+    pub fn height(&self) -> Height {
+        self.height
     }
 
-    /// Handle a new height trigger. If new height is higher than current, goto new height and
-    /// throw a new round info event.
-    fn handle_new_height(
-        &mut self,
-        status: SMRStatus,
-        source: TriggerSource,
-    ) -> ConsensusResult<()> {
-        log::debug!("Tendermint: SMR triggered by new height {}", status.height);
-
-        let height = status.height;
-        if source != TriggerSource::State {
-            return Err(ConsensusError::Other(
-                "Rich status source error".to_string(),
-            ));
-        } else if height <= self.height {
-            return Err(ConsensusError::Other("Delayed status".to_string()));
-        }
-
-        self.goto_new_height(height);
-        self.send_event(SMREvent::NewRoundInfo {
-            height: self.height,
-            round: INIT_ROUND,
-            lock_round: None,
-            lock_proposal: None,
-            new_interval: status.new_interval,
-            new_config: status.new_config,
-            from_where: FromWhere::PrecommitQC(u64::max_value()),
-        })?;
-        self.goto_step(Step::Propose);
-        Ok(())
+    /// Return the current round.
+    pub fn round(&self) -> Round {
+        self.round
     }
 
-    /// Handle a proposal trigger. Only if self step is propose, the proposal is valid.
-    /// If proposal hash is empty, prevote to an empty hash. If the lock round is some, and the lock
-    /// round is higher than self lock round, remove PoLC. Finally throw prevote vote event. It is
-    /// impossible that the proposal hash is empty with the lock round is some.
-    fn handle_proposal(
-        &mut self,
-        proposal_hash: Hash,
-        round: u64,
-        lock_round: Option<u64>,
-        source: TriggerSource,
-        height: u64,
-    ) -> ConsensusResult<()> {
-        if self.height != height || self.round != round {
-            return Ok(());
-        }
+    /// Return the sequence number of the last commit, which is zero before the first commit.
+    pub fn commit_seq(&self) -> u64 {
+        self.commit_seq
+    }
 
-        if self.step > Step::Propose {
-            return Ok(());
-        }
+    /// Return the current step.
+    pub fn step(&self) -> &Step {
+        &self.step
+    }
 
-        log::debug!(
-            "Tendermint: SMR triggered by a proposal hash {:?}, from {:?}, height {}, round {}",
-            hex_encode(proposal_hash.clone()),
-            source,
-            self.height,
-            self.round
-        );
+    /// Return the proposal of the current round, which is empty if there is none.
+    pub fn block_hash(&self) -> &H {
+        &self.block_hash
+    }
 
-        // If the proposal trigger is from timer, goto prevote step directly.
-        if source == TriggerSource::Timer {
-            // This event is for timer to set a prevote timer.
-            let (round, hash) = if let Some(lock) = &self.lock {
-                (Some(lock.round), lock.hash.clone())
-            } else {
-                (None, Hash::new())
-            };
+    /// Return the current lock.
+    pub fn lock(&self) -> Option<&GenericLock<H>> {
+        self.lock.as_ref()
+    }
 
-            self.send_event(SMREvent::PrevoteVote {
-                height: self.height,
-                round: self.round,
-                block_hash: hash,
-                lock_round: round,
-            })?;
-            self.goto_step(Step::Prevote);
-            return Ok(());
-        } else if proposal_hash.is_empty() {
-            return Err(ConsensusError::ProposalErr("Empty proposal".to_string()));
-        }
+    /// Return the version of the active consensus parameters, which is 0 before any change.
+    pub fn params_version(&self) -> u64 {
+        self.params_version
+    }
 
-        // update PoLC
-        self.check()?;
-        if let Some(lock_round) = lock_round {
-            if let Some(lock) = self.lock.clone() {
-                log::debug!("Tendermint: SMR handle proposal with a lock");
+    /// Return the depth gauges of the state and timer event channels, so that a slow consumer can
+    /// be detected before the unbounded channel grows too much.
+    pub fn channel_depths(&self) -> (ChannelDepth, ChannelDepth) {
+        (self.event.0.depth(), self.event.1.depth())
+    }
 
-                if lock_round > lock.round {
-                    self.remove_polc();
-                    self.set_proposal(proposal_hash);
-                } else if lock_round == lock.round && proposal_hash != self.block_hash {
-                    return Err(ConsensusError::CorrectnessErr("Fork".to_string()));
-                }
-            } else {
-                self.set_proposal(proposal_hash);
-            }
-        } else if self.lock.is_none() {
-            self.set_proposal(proposal_hash);
+    /// Return the histogram of the latencies from the receipt of a trigger to the step transition
+    /// it causes, for the triggers of the given source.
+    pub fn transition_latency(&self, source: TriggerSource) -> &LatencyHistogram {
+        match source {
+            TriggerSource::State => &self.latency.0,
+            TriggerSource::Timer => &self.latency.1,
         }
+    }
 
-        let round = self.lock.as_ref().map(|lock| lock.round);
+    /// Set the length of the peer addresses, which is `ADDRESS_LEN` by default. A trigger from a
+    /// peer whose address has another length is rejected.
+    pub fn set_address_len(&mut self, len: usize) {
+        self.address_len = len;
+    }
 
-        self.send_event(SMREvent::PrevoteVote {
-            height: self.height,
-            round: self.round,
-            block_hash: self.block_hash.clone(),
-            lock_round: round,
-        })?;
-        self.goto_step(Step::Prevote);
-        Ok(())
+    /// Set the number of validators of the current validator set, which a round skip proof must
+    /// have more senders than the faulty validators of. Round skips are rejected until it is set.
+    pub fn set_validator_count(&mut self, validators: u64) {
+        self.validators = Some(validators);
     }
 
-    /// Handle a prevote quorum certificate trigger. Only if self step is prevote, the prevote QC is
-    /// valid.  
-    /// The prevote round must be some. If the vote round is higher than self lock round, update
-    /// PoLC. Finally throw precommit vote event.
-    fn handle_prevote(
-        &mut self,
-        prevote_hash: Hash,
-        prevote_round: u64,
-        source: TriggerSource,
-        height: u64,
-    ) -> ConsensusResult<()> {
-        if self.height != height {
-            return Ok(());
-        }
+    /// Set what to do with the events of a channel whose receiver is dropped. By default, the
+    /// events are rejected, which fails every following trigger.
+    pub fn set_on_channel_closed(&mut self, on_closed: OnChannelClosed) {
+        self.event.0.set_on_closed(on_closed);
+        self.event.1.set_on_closed(on_closed);
+    }
 
-        if prevote_round == self.round && self.step > Step::Prevote {
-            return Ok(());
+    /// Attach a new receiver to the event channel whose receiver is dropped, so that an
+    /// observation only consumer can come back.
+    pub fn reattach_receiver(&mut self, channel: EventChannel) -> ConsensusResult<GenericEvent<H>> {
+        match channel {
+            EventChannel::State => self.event.0.reattach(self.clock),
+            EventChannel::Timer => self.event.1.reattach(self.clock),
         }
+    }
 
-        log::debug!(
-            "Tendermint: SMR triggered by prevote QC hash {:?} qc round {} from {:?}, height {}, round {}",
-            hex_encode(prevote_hash.clone()),
-            prevote_round,
-            source,
-            self.height,
-            self.round
-        );
+    /// Set whether and how to stamp the emission time on the events.
+    pub fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        self.timestamp_mode = mode;
+    }
 
-        if source == TriggerSource::Timer {
-            if prevote_round != self.round {
-                return Ok(());
-            }
+    /// Set how a violation of the self check invariants is handled. By default, the violations
+    /// are logged.
+    pub fn set_check_level(&mut self, level: CheckLevel) {
+        self.check_level = level;
+    }
 
-            // This event is for timer to set a precommit timer.
-            let round = if let Some(lock) = &self.lock {
-                Some(lock.round)
-            } else {
-                self.block_hash = Hash::new();
-                None
-            };
+    /// Set whether to throw a state transition event on every step transition, including the
+    /// transitions to a new round or a new height. Disabled by default, so that the consumers only
+    /// interested in the votes are not flooded.
+    pub fn set_transition_events(&mut self, enabled: bool) {
+        self.transitions = enabled;
+    }
 
-            self.send_event(SMREvent::PrecommitVote {
-                height: self.height,
-                round: self.round,
-                block_hash: Hash::new(),
-                lock_round: round,
-            })?;
-            self.goto_step(Step::Precommit);
-            return Ok(());
+    /// Set the strict commit mode. In strict commit mode, the SMR only leaves the commit step by a
+    /// new height trigger carrying the proof of the block committed in the current height.
+    pub fn set_strict_commit(&mut self, strict: bool) {
+        self.strict_commit = strict;
+        if strict {
+            self.lock_expiry = None;
         }
+    }
 
-        // A prevote QC from timer which means prevote timeout can not lead to unlock. Therefore,
-        // only prevote QCs from state will update the PoLC. If the prevote QC is from timer, goto
-        // precommit step directly.
-        self.check()?;
+    /// Set the strict timer mode. A proposal timeout of the current height must carry an empty hash
+    /// and must not be of a future round. In strict timer mode, a malformed timeout is rejected,
+    /// otherwise it is logged, and the hash is ignored while a timeout of a future round is
+    /// dropped. An empty timeout of a past round is stale and ignored in either mode.
+    pub fn set_strict_timer(&mut self, strict: bool) {
+        self.strict_timer = strict;
+    }
 
-        if prevote_round < self.round {
-            return Ok(());
+    /// Enable the brake step. With the brake, a precommit timeout without a QC goes to the brake
+    /// step and throws a brake event for the state to broadcast a choke message, instead of going
+    /// to the next round. Every brake timeout throws the brake event again, until a continue round
+    /// trigger of a choke QC moves the SMR to the next round. Disabled by default.
+    pub fn set_brake(&mut self, enabled: bool) {
+        self.brake = enabled;
+    }
+
+    /// Set the number of rounds after which a lock without a commit is released, or `None` to
+    /// never release it. Releasing a lock breaks the safety of the protocol if any validator has
+    /// precommitted the locked block, so it is only allowed out of strict commit mode, and enabling
+    /// strict commit mode disables it.
+    pub fn set_lock_expiry_rounds(&mut self, rounds: Option<u64>) -> ConsensusResult<()> {
+        if rounds.is_some() && self.strict_commit {
+            return Err(ConsensusError::StateErr(
+                "Lock expiry in strict commit mode".to_string(),
+            ));
+        } else if rounds == Some(0) {
+            return Err(ConsensusError::StateErr(
+                "Zero lock expiry rounds".to_string(),
+            ));
         }
 
-        self.update_polc(prevote_hash, prevote_round);
+        if let Some(rounds) = rounds {
+            log::warn!(
+                "Tendermint: SMR lock expires after {} rounds, which is unsafe under Byzantine validators",
+                rounds
+            );
+        }
+        self.lock_expiry = rounds;
+        Ok(())
+    }
 
-        if prevote_round > self.round {
-            let (lock_round, lock_proposal) = self
-                .lock
-                .clone()
-                .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
+    /// Adopt a PoLC from a verified prevote QC of the current height which was fetched from
+    /// peers, for example after being offline for part of a round. The lock can only be adopted
+    /// at the start of a round, and the QC round must be lower than self round, like `set_lock`.
+    /// A lock with a higher round than the existing one replaces it. The QC digest is only checked
+    /// to be non-empty, and the QC is not verified here, so the caller must verify it before.
+    pub fn adopt_lock(&mut self, round: Round, hash: H, qc_digest: H) -> ConsensusResult<()> {
+        if self.step != Step::Propose {
+            return Err(ConsensusError::StateErr(format!(
+                "Adopt a lock in {:?}, height {}, round {}",
+                self.step, self.height, self.round
+            )));
+        } else if round == self.round {
+            return Err(ConsensusError::StateErr(format!(
+                "Adopt a lock of the current round {} in {:?}, height {}",
+                round, self.step, self.height
+            )));
+        }
+        self.install_lock(round, hash, qc_digest, LockCause::Adopted)
+    }
 
-            self.round = prevote_round;
-            self.send_event(SMREvent::NewRoundInfo {
-                height: self.height,
-                round: self.round + 1,
-                lock_round,
-                lock_proposal,
-                new_interval: None,
-                new_config: None,
-                from_where: FromWhere::PrevoteQC(prevote_round),
-            })?;
-            self.goto_next_round();
+    /// Restore a PoLC of the current height after a restart mid-height, learned from the WAL or
+    /// from peers with the digest of its verified prevote QC, so that the validator keeps voting
+    /// for its prior lock instead of nil. A lock of the current round can only be restored from
+    /// the precommit step, since its prevote QC ends the prevote step. A lock with a higher round
+    /// than the existing one replaces it. Call `revote` to vote for the restored lock.
+    pub fn set_lock(
+        &mut self,
+        height: Height,
+        round: Round,
+        hash: H,
+        proof: H,
+    ) -> ConsensusResult<()> {
+        if height != self.height {
+            return Err(ConsensusError::StateErr(format!(
+                "Set a lock of height {} in height {}",
+                height, self.height
+            )));
         }
 
-        // throw precommit vote event
-        let round = self.lock.as_ref().map(|lock| lock.round);
-        self.send_event(SMREvent::PrecommitVote {
-            height: self.height,
-            round: self.round,
-            block_hash: self.block_hash.clone(),
-            lock_round: round,
-        })?;
-        self.goto_step(Step::Precommit);
-        Ok(())
+        if self.step == Step::Commit || (round == self.round && self.step < Step::Precommit) {
+            return Err(ConsensusError::StateErr(format!(
+                "Set a lock of round {} in {:?}, height {}, round {}",
+                round, self.step, self.height, self.round
+            )));
+        }
+        self.install_lock(round, hash, proof, LockCause::Restored)
     }
 
-    /// Handle a precommit quorum certificate trigger. Only if self step is precommit, the precommit
-    /// QC is valid.
-    /// The precommit round must be some. If its hash is empty, throw new round event and goto next
-    /// round. Otherwise, throw commit event.
-    fn handle_precommit(
+    /// Install a lock from the digest of a verified prevote QC. The QC round must not be higher
+    /// than self round, and a lock conflicting with the existing one of the same round is a fork.
+    fn install_lock(
         &mut self,
-        precommit_hash: Hash,
-        precommit_round: u64,
-        source: TriggerSource,
-        height: u64,
+        round: Round,
+        hash: H,
+        qc_digest: H,
+        cause: LockCause,
     ) -> ConsensusResult<()> {
-        if self.height != height {
-            return Ok(());
+        if hash.is_empty() {
+            return Err(ConsensusError::StateErr(format!("{} an empty lock", cause)));
         }
 
-        if self.step == Step::Commit {
-            return Ok(());
+        if qc_digest.is_empty() {
+            return Err(ConsensusError::StateErr(format!(
+                "{} a lock without QC",
+                cause
+            )));
         }
 
-        log::debug!(
-            "Tendermint: SMR triggered by precommit QC hash {:?} qc round {} from {:?}, height {}, round {}",
-            hex_encode(precommit_hash.clone()),
-            precommit_round,
-            source,
-            self.height,
-            self.round
-        );
-
-        let (lock_round, lock_proposal) = self
-            .lock
-            .clone()
-            .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
+        if round > self.round {
+            return Err(ConsensusError::RoundDiff {
+                local: self.round.into(),
+                vote: round.into(),
+            });
+        }
 
-        if precommit_hash.is_empty() {
-            if precommit_round < self.round {
+        if let Some(lock) = &self.lock {
+            if round == lock.round && hash != lock.hash {
+                let local_hash = lock.hash.clone();
+                return self.detect_fork(round, local_hash, hash);
+            } else if round <= lock.round {
                 return Ok(());
             }
-
-            self.round = precommit_round;
-            self.send_event(SMREvent::NewRoundInfo {
-                height: self.height,
-                round: self.round + 1,
-                lock_round,
-                lock_proposal,
-                new_interval: None,
-                new_config: None,
-                from_where: FromWhere::PrecommitQC(precommit_round),
-            })?;
-
-            self.goto_next_round();
-            return Ok(());
         }
 
-        self.check()?;
-        self.send_event(SMREvent::Commit(precommit_hash))?;
-        self.goto_step(Step::Commit);
+        smr_debug!(
+            "Tendermint: SMR {} lock round {}, hash {:?}, QC digest {:?}",
+            cause,
+            round,
+            HexFmt(hash.as_ref()),
+            HexFmt(qc_digest.as_ref())
+        );
+
+        self.check();
+        self.set_proposal(hash.clone());
+        self.change_lock(Some(GenericLock { round, hash }), cause)?;
+        self.publish_view();
         Ok(())
     }
 
-    fn handle_continue_round(&mut self, height: u64, round: u64) -> ConsensusResult<()> {
-        if height != self.height || round <= self.round {
-            return Ok(());
-        }
+    /// Re-emit the vote of the current step after recovering mid-round, for example from the WAL,
+    /// so that the validator does not wait idle for the timeout. A locked validator votes for the
+    /// lock, otherwise it prevotes the current proposal and precommits nil. Nothing is emitted in
+    /// the propose and commit steps, since no vote is cast in them.
+    pub fn revote(&mut self) -> ConsensusResult<()> {
+        let lock_round = self.lock.as_ref().map(|lock| lock.round);
+        let lock_hash = self.lock.as_ref().map(|lock| lock.hash.clone());
+        smr_debug!(
+            "Tendermint: SMR revote in {:?}, height {}, round {}, lock round {:?}",
+            self.step,
+            self.height,
+            self.round,
+            lock_round
+        );
 
-        log::debug!("Tendermint: SMR continue round {}", round);
+        match self.step {
+            Step::Prevote => self.send_event(GenericSMREvent::PrevoteVote {
+                height: self.height,
+                round: self.round,
+                block_hash: lock_hash.unwrap_or_else(|| self.block_hash.clone()),
+                lock_round,
+            }),
+            Step::Precommit => self.send_event(GenericSMREvent::PrecommitVote {
+                height: self.height,
+                round: self.round,
+                block_hash: lock_hash.unwrap_or_else(H::empty),
+                lock_round,
+            }),
+            _ => Ok(()),
+        }
+    }
 
-        self.round = round - 1;
-        let (lock_round, lock_proposal) = self
-            .lock
-            .clone()
-            .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
-        self.send_event(SMREvent::NewRoundInfo {
+    /// Export the state to persist it between process restarts.
+    pub fn export_state(&self) -> GenericSMRState<H> {
+        GenericSMRState {
             height: self.height,
-            round: self.round + 1,
-            lock_round,
-            lock_proposal,
-            new_interval: None,
-            new_config: None,
-            from_where: FromWhere::ChokeQC(round - 1),
-        })?;
-        self.goto_next_round();
-        Ok(())
+            round: self.round,
+            step: self.step.clone(),
+            block_hash: self.block_hash.clone(),
+            lock: self.lock.clone(),
+            commit_seq: self.commit_seq,
+            params_version: self.params_version,
+            max_round: self.max_round,
+        }
     }
 
-    fn send_event(&mut self, event: SMREvent) -> ConsensusResult<()> {
-        log::debug!("Tendermint: SMR throw {} event", event);
-        self.event.0.unbounded_send(event.clone()).map_err(|err| {
-            ConsensusError::ThrowEventErr(format!("event: {}, error: {:?}", event.clone(), err))
-        })?;
-        self.event.1.unbounded_send(event.clone()).map_err(|err| {
-            ConsensusError::ThrowEventErr(format!("event: {}, error: {:?}", event.clone(), err))
-        })?;
+    /// Import the persisted state. The lock is checked as in `StateMachineBuilder::build`, and the
+    /// commit of the current height is not restored. The version and the max round of the active
+    /// parameters are restored, while the buffered future triggers and the pending parameter
+    /// change of the replaced state are dropped. No event is thrown, so call `revote` to resume
+    /// voting in the current step.
+    pub fn import_state(&mut self, state: GenericSMRState<H>) -> ConsensusResult<()> {
+        let block_hash = resumed_block_hash(state.round, state.block_hash, state.lock.as_ref())?;
+        self.height = state.height;
+        self.round = state.round;
+        self.step = state.step;
+        self.block_hash = block_hash;
+        self.lock = state.lock;
+        self.last_commit = None;
+        self.commit_seq = state.commit_seq;
+        self.params_version = state.params_version;
+        self.max_round = state.max_round;
+        self.future.clear();
+        self.pending_params = None;
+        self.publish_view();
         Ok(())
     }
 
-    /// Goto new height and clear everything.
-    fn goto_new_height(&mut self, height: u64) {
-        log::debug!("Tendermint: SMR goto new height: {}", height);
-        self.height = height;
-        self.round = INIT_ROUND;
-        self.block_hash = Hash::new();
-        self.lock = None;
-    }
-
-    /// Keep the lock, if any, when go to the next round.
-    fn goto_next_round(&mut self) {
-        log::debug!("Tendermint: SMR goto next round {}", self.round + 1);
-        self.round += 1;
-        self.goto_step(Step::Propose);
+    /// Return a handle to read the latest view of the SMR, which is published after every
+    /// processed trigger.
+    pub fn view_handle(&self) -> GenericSmrViewHandle<H> {
+        self.view.clone()
     }
 
-    /// Goto the given step.
-    #[inline]
-    fn goto_step(&mut self, step: Step) {
-        log::debug!("Tendermint: SMR goto step {:?}", step);
-        self.step = step;
+    /// Set the watchdog timeout, or `None` to disable the watchdog. If no step transition occurs
+    /// within the timeout, `check_watchdog` throws a watchdog report event with the recent events.
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) {
+        self.watchdog = timeout.map(|timeout| Watchdog {
+            timeout,
+            last_transition: self.clock.now(),
+            reported: false,
+            recent_events: VecDeque::with_capacity(WATCHDOG_EVENTS),
+        });
     }
 
-    /// Update the PoLC. Firstly set self proposal as the given hash. Secondly update the PoLC. If
-    /// the hash is empty, remove it. Otherwise, set lock round and hash as the given round and
-    /// hash.
-    fn update_polc(&mut self, hash: Hash, round: u64) {
-        log::debug!("Tendermint: SMR update PoLC at round {}", round);
-        self.set_proposal(hash.clone());
-
-        if hash.is_empty() {
-            self.remove_polc();
-        } else {
-            self.lock = Some(Lock { round, hash });
-        }
+    /// Set the commit latency budget, or `None` to disable the tracking. A height committed later
+    /// than the budget after entering it throws an SLO breach event.
+    pub fn set_commit_budget(&mut self, budget: Option<Duration>) {
+        self.commit_slo = budget.map(|budget| CommitSlo {
+            budget,
+            height_start: self.clock.now(),
+            recent: VecDeque::with_capacity(SLO_WINDOW),
+        });
     }
 
-    #[inline]
-    fn remove_polc(&mut self) {
-        self.lock = None;
+    /// Return the ratio of the heights committed within the budget among the last `SLO_WINDOW`
+    /// committed heights, or `None` if the budget is not set or no height is committed yet.
+    pub fn commit_compliance(&self) -> Option<f64> {
+        let slo = self
+            .commit_slo
+            .as_ref()
+            .filter(|slo| !slo.recent.is_empty())?;
+        let compliant = slo.recent.iter().filter(|within| **within).count();
+        Some(compliant as f64 / slo.recent.len() as f64)
+    }
+
+    /// Throw a quorum unavailable event of the current round with the validators which have not
+    /// voted, as detected by the authority module.
+    pub fn report_quorum_unavailable(
+        &mut self,
+        missing_power: u64,
+        validators: Vec<Address>,
+    ) -> ConsensusResult<()> {
+        log::warn!(
+            "Tendermint: SMR quorum unavailable, height {}, round {}, missing power {}",
+            self.height,
+            self.round,
+            missing_power
+        );
+        self.send_event(GenericSMREvent::QuorumUnavailable {
+            height: self.height,
+            round: self.round,
+            missing_power,
+            validators,
+        })
+    }
+
+    /// Check the watchdog and throw a watchdog report event if the SMR stalls. Each stall is
+    /// reported once. Return whether a report is thrown.
+    pub fn check_watchdog(&mut self) -> ConsensusResult<bool> {
+        let now = self.clock.now();
+        let stalled = match &mut self.watchdog {
+            Some(watchdog) if !watchdog.reported => {
+                let stalled = Duration::from_nanos(now - watchdog.last_transition);
+                if stalled < watchdog.timeout {
+                    return Ok(false);
+                }
+                watchdog.reported = true;
+                stalled
+            }
+            _ => return Ok(false),
+        };
+
+        let recent_events = self
+            .watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.recent_events.iter().cloned().collect())
+            .unwrap_or_default();
+        log::warn!(
+            "Tendermint: SMR stalls for {:?}, height {}, round {}, step {:?}",
+            stalled,
+            self.height,
+            self.round,
+            self.step
+        );
+        self.send_event(GenericSMREvent::WatchdogReport {
+            height: self.height,
+            round: self.round,
+            step: self.step.clone(),
+            lock: self.lock.clone(),
+            stalled_ms: stalled.as_millis() as u64,
+            recent_events,
+        })?;
+        Ok(true)
+    }
+
+    /// Subscribe the error events of rejected triggers. A new subscription replaces the previous
+    /// one. Dropping the receiver does not affect processing.
+    pub fn subscribe_errors(&mut self) -> Receiver<GenericErrorEvent<H>> {
+        let (tx, rx) = channel();
+        self.errors = Some(tx);
+        rx
+    }
+
+    /// Subscribe the diagnostic events, such as the ignored triggers. A new subscription replaces
+    /// the previous one. Dropping the receiver does not affect processing.
+    pub fn subscribe_diagnostics(&mut self) -> Receiver<GenericDiagnostic<H>> {
+        let (tx, rx) = channel();
+        self.diagnostics = Some(tx);
+        rx
+    }
+
+    /// Register a hook invoked after every trigger changing the state or throwing events, with the
+    /// trigger, the states before and after it and the thrown events. The hooks are invoked in the
+    /// order of registration, and a rejected trigger does not invoke them.
+    pub fn on_transition(&mut self, hook: GenericTransitionHook<H>) {
+        self.hooks.0.push(hook);
+    }
+
+    /// Return the number of triggers ignored for the reason.
+    pub fn ignored_count(&self, reason: IgnoreReason) -> u64 {
+        self.ignored.get(&reason).copied().unwrap_or(0)
+    }
+
+    pub fn process(&mut self, msg: GenericSMRTrigger<H>) -> ConsensusResult<()> {
+        self.process_from(msg, None)
+    }
+
+    /// Process a trigger and return whether it is applied, buffered, or dropped as a stale or a
+    /// future one. A trigger is applied if it changes the height, the round or the step, or throws
+    /// events.
+    pub fn process_with_outcome(
+        &mut self,
+        msg: GenericSMRTrigger<H>,
+    ) -> ConsensusResult<ProcessOutcome> {
+        let height = msg.height;
+        let round = match &msg.trigger_type {
+            GenericTriggerType::RoundSkip { round, .. } => *round,
+            _ => msg.round,
+        };
+        let before = (
+            self.height,
+            self.round,
+            self.step.clone(),
+            self.events_thrown,
+        );
+        let buffered = self.future_buffered;
+        self.process(msg)?;
+
+        if (
+            self.height,
+            self.round,
+            self.step.clone(),
+            self.events_thrown,
+        ) != before
+        {
+            Ok(ProcessOutcome::Applied)
+        } else if self.future_buffered > buffered {
+            Ok(ProcessOutcome::BufferedFuture { height, round })
+        } else if (height, round) > (self.height, self.round) {
+            smr_debug!(
+                "Tendermint: SMR ignore future trigger of height {}, round {}",
+                height,
+                round
+            );
+            Ok(ProcessOutcome::IgnoredFuture { height, round })
+        } else {
+            Ok(ProcessOutcome::IgnoredStale { height, round })
+        }
+    }
+
+    /// Shut down the SMR by throwing a stop event and closing both event channels, so that the
+    /// event streams end after the events in them are received. Every trigger after the shutdown
+    /// is rejected, including the stale ones and the ones processed by `process_sync` or `replay`,
+    /// which do not use the channels. Shutting down again does nothing.
+    pub fn shutdown(&mut self) -> ConsensusResult<()> {
+        if self.stopped {
+            return Ok(());
+        }
+        self.stopped = true;
+
+        log::info!(
+            "Tendermint: SMR shutdown at height {}, round {}",
+            self.height,
+            self.round
+        );
+        let time = self.clock.stamp(self.timestamp_mode);
+        let res = self
+            .event
+            .0
+            .send(GenericSMREvent::Stop, time.clone())
+            .and(self.event.1.send(GenericSMREvent::Stop, time));
+        self.event.0.close();
+        self.event.1.close();
+        res
+    }
+
+    /// Process a trigger and return the thrown events instead of sending them to the event
+    /// channels, for embedding the SMR synchronously. Each event is returned once, and it is for
+    /// both the state and the timer. The events thrown before a rejection are discarded.
+    pub fn process_sync(
+        &mut self,
+        msg: GenericSMRTrigger<H>,
+    ) -> ConsensusResult<Vec<GenericSMREvent<H>>> {
+        self.check_stopped()?;
+        self.captured = Some(Vec::new());
+        let res = self.process_from(msg, None);
+        let events = self.captured.take().unwrap_or_default();
+        res.map(|_| events)
+    }
+
+    /// Replay a recorded trigger log, such as the WAL contents, and report the final state with
+    /// every thrown event. The events are not sent to the event channels. A rejected trigger was
+    /// rejected in the live run too, so it is recorded in the report rather than failing the
+    /// replay.
+    pub fn replay(
+        &mut self,
+        triggers: Vec<GenericSMRTrigger<H>>,
+    ) -> ConsensusResult<GenericReplayReport<H>> {
+        let mut report = GenericReplayReport::default();
+        for (index, trigger) in triggers.into_iter().enumerate() {
+            match self.process_sync(trigger) {
+                Ok(events) => report
+                    .events
+                    .extend(events.into_iter().map(|event| (index, event))),
+                Err(e) => report.rejected.push((index, e)),
+            }
+            report.processed += 1;
+        }
+        report.view = GenericSmrView::clone(&self.view.load());
+        Ok(report)
+    }
+
+    /// Process a trigger from the given peer. If the trigger is rejected, the peer is reported in
+    /// the error event. A trigger from a malformed peer address is rejected as `InvalidAddress`.
+    pub fn process_from(
+        &mut self,
+        msg: GenericSMRTrigger<H>,
+        peer: Option<Address>,
+    ) -> ConsensusResult<()> {
+        self.check_stopped()?;
+        let trigger = self.errors.as_ref().map(|_| msg.clone());
+        let before = if self.hooks.0.is_empty() && self.audit.is_none() {
+            None
+        } else {
+            self.hook_events = Some(Vec::new());
+            Some((msg.clone(), self.export_state()))
+        };
+        self.received = Some((msg.source.clone(), self.clock.now()));
+        let res = match &peer {
+            Some(address) => check_address(address, self.address_len),
+            None => Ok(()),
+        }
+        .and_then(|_| self.dispatch(msg));
+        if res.is_ok() {
+            self.replay_future();
+        }
+        self.received = None;
+        self.publish_view();
+
+        if let Some((trigger, before)) = before {
+            let events = self.hook_events.take().unwrap_or_default();
+            let after = self.export_state();
+            let mut record = GenericTransitionRecord {
+                trigger,
+                before,
+                after,
+                events,
+                rejection: None,
+            };
+            if res.is_ok() && (record.before != record.after || !record.events.is_empty()) {
+                self.hooks.0.iter().for_each(|hook| hook(&record));
+            }
+            if let Some(tx) = &self.audit {
+                record.rejection = res.as_ref().err().map(ToString::to_string);
+                if tx.send(record).is_err() {
+                    self.audit = None;
+                }
+            }
+        }
+
+        if let (Err(error), Some(trigger)) = (&res, trigger) {
+            self.report_error(GenericErrorEvent {
+                error: error.clone(),
+                trigger_type: trigger.trigger_type,
+                source: trigger.source,
+                height: trigger.height,
+                round: trigger.round,
+                peer,
+            });
+        }
+        res
+    }
+
+    fn check_stopped(&self) -> ConsensusResult<()> {
+        if self.stopped {
+            return Err(ConsensusError::StateErr(
+                "Trigger after the SMR shutdown".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn dispatch(&mut self, msg: GenericSMRTrigger<H>) -> ConsensusResult<()> {
+        let trigger_type = msg.trigger_type.clone();
+        if let Some(max_round) = self.max_round {
+            let round = match &trigger_type {
+                GenericTriggerType::RoundSkip { round, .. } => *round,
+                _ => msg.round,
+            };
+            if round > max_round && !matches!(trigger_type, GenericTriggerType::NewHeight(_)) {
+                return Err(ConsensusError::Other(format!(
+                    "Round {} exceeds max round {}",
+                    round, max_round
+                )));
+            }
+        }
+
+        if self.is_future(&msg) {
+            self.buffer_future(msg);
+            return Ok(());
+        }
+
+        match trigger_type {
+            GenericTriggerType::NewHeight(status) => self.handle_new_height(*status, msg.source),
+            GenericTriggerType::Proposal => {
+                self.handle_proposal(msg.hash, msg.round, msg.lock_round, msg.source, msg.height)
+            }
+            GenericTriggerType::PrevoteQC => {
+                self.handle_prevote(msg.qc_value(), msg.round, msg.source, msg.height)
+            }
+            GenericTriggerType::PrecommitQC => {
+                self.handle_precommit(msg.qc_value(), msg.round, msg.source, msg.height)
+            }
+            GenericTriggerType::ContinueRound => {
+                assert!(msg.source == TriggerSource::State);
+                self.handle_continue_round(msg.height, msg.round)
+            }
+            GenericTriggerType::NilProposal => {
+                self.handle_nil_proposal(msg.round, msg.source, msg.height)
+            }
+            GenericTriggerType::CommitCertificate(proof) => {
+                self.handle_commit_certificate(*proof, msg.round, msg.source)
+            }
+            GenericTriggerType::SyncCommit { height, proof } => {
+                self.handle_sync_commit(height, *proof, msg.source)
+            }
+            GenericTriggerType::RoundSkip { round, proof } => {
+                self.handle_round_skip(round, *proof, msg.source, msg.height)
+            }
+            GenericTriggerType::BrakeTimeout => self.handle_brake_timeout(msg.round, msg.height),
+        }
+    }
+
+    /// Return whether the trigger is a proposal of a higher round, or a proposal or a QC of the
+    /// next height, which the SMR can not handle until it reaches the round or the height. A QC of
+    /// a higher round is not in the future, since the SMR jumps to the round on it.
+    fn is_future(&self, msg: &GenericSMRTrigger<H>) -> bool {
+        if msg.source != TriggerSource::State {
+            return false;
+        }
+        match msg.trigger_type {
+            GenericTriggerType::Proposal => {
+                (msg.height == self.height && msg.round > self.round)
+                    || Some(msg.height) == self.height.checked_next()
+            }
+            GenericTriggerType::PrevoteQC | GenericTriggerType::PrecommitQC => {
+                Some(msg.height) == self.height.checked_next()
+            }
+            _ => false,
+        }
+    }
+
+    /// Buffer a future trigger. A trigger more than `FUTURE_ROUNDS` ahead is dropped. If the buffer
+    /// is full, the trigger furthest ahead is evicted for a nearer one, so that the triggers of far
+    /// rounds can not crowd out the ones of the next height.
+    fn buffer_future(&mut self, msg: GenericSMRTrigger<H>) {
+        let distance = self.future_distance(&msg);
+        if distance > FUTURE_ROUNDS {
+            log::warn!(
+                "Tendermint: SMR drop future {} trigger of height {}, round {}, too far ahead",
+                msg.trigger_type,
+                msg.height,
+                msg.round
+            );
+            return;
+        }
+        if self.future.len() >= FUTURE_TRIGGERS {
+            let furthest = (0..self.future.len())
+                .max_by_key(|index| self.future_distance(&self.future[*index]))
+                .filter(|index| self.future_distance(&self.future[*index]) > distance);
+            let dropped = match furthest {
+                Some(index) => self.future.remove(index),
+                None => msg.clone(),
+            };
+            log::warn!(
+                "Tendermint: SMR drop future {} trigger of height {}, round {}, buffer full",
+                dropped.trigger_type,
+                dropped.height,
+                dropped.round
+            );
+            if furthest.is_none() {
+                return;
+            }
+        }
+        smr_debug!(
+            "Tendermint: SMR buffer future {} trigger of height {}, round {}",
+            msg.trigger_type,
+            msg.height,
+            msg.round
+        );
+        self.future_buffered += 1;
+        self.future.push(msg);
+    }
+
+    /// Return the number of rounds the trigger is ahead of the round it is replayed from, which is
+    /// the initial round for the next height.
+    fn future_distance(&self, msg: &GenericSMRTrigger<H>) -> u64 {
+        let from = if msg.height == self.height {
+            self.round
+        } else {
+            self.init_round
+        };
+        msg.round.0.saturating_sub(from.0)
+    }
+
+    /// Replay the buffered triggers which are no longer in the future, in the order of receiving,
+    /// until the SMR does not move on. The triggers of the past heights are dropped.
+    fn replay_future(&mut self) {
+        loop {
+            let height = self.height;
+            self.future.retain(|msg| msg.height >= height);
+            let (ready, future): (Vec<_>, Vec<_>) = std::mem::take(&mut self.future)
+                .into_iter()
+                .partition(|msg| !self.is_future(msg));
+            self.future = future;
+            if ready.is_empty() {
+                return;
+            }
+
+            for msg in ready {
+                let (height, round) = (msg.height, msg.round);
+                if let Err(e) = self.dispatch(msg) {
+                    log::warn!(
+                        "Tendermint: SMR replay trigger of height {}, round {}: {}",
+                        height,
+                        round,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Handle a new height trigger. If new height is higher than current, goto new height and
+    /// throw a new round info event. An invalid new duration or timeout configuration is dropped
+    /// without rejecting the new height, since the block carrying it is already committed.
+    fn handle_new_height(
+        &mut self,
+        status: GenericSMRStatus<H>,
+        source: TriggerSource,
+    ) -> ConsensusResult<()> {
+        smr_debug!("Tendermint: SMR triggered by new height {}", status.height);
+
+        let height = status.height;
+        if source != TriggerSource::State {
+            return Err(ConsensusError::Other(
+                "Rich status source error".to_string(),
+            ));
+        } else if height <= self.height {
+            return Err(ConsensusError::Other("Delayed status".to_string()));
+        }
+
+        if self.strict_commit {
+            self.check_commit_proof(status.commit_proof.as_ref())?;
+        }
+
+        if let Some(change) = status.param_change {
+            self.schedule_params(change, height)?;
+        }
+
+        let new_config = status.new_config.filter(|config| match config.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!(
+                    "Tendermint: SMR drop new config of height {}: {}",
+                    height,
+                    e
+                );
+                false
+            }
+        });
+        let new_timeouts = status
+            .new_timeouts
+            .filter(|timeouts| match timeouts.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    log::error!(
+                        "Tendermint: SMR drop new timeouts of height {}: {}",
+                        height,
+                        e
+                    );
+                    false
+                }
+            });
+        self.enter_height(
+            height,
+            status.new_interval,
+            new_config,
+            new_timeouts,
+            TransitionReason::NewHeight,
+        )
+    }
+
+    /// Handle a sync commit trigger. The SMR goes to the given height directly if the proof is of
+    /// the previous height, which is not lower than the current height. A proof of the current
+    /// height conflicting with the committed block is a fork. In strict commit mode, the committed
+    /// height is only left by a new height trigger with the executor proof.
+    fn handle_sync_commit(
+        &mut self,
+        height: Height,
+        proof: GenericCommitProof<H>,
+        source: TriggerSource,
+    ) -> ConsensusResult<()> {
+        smr_debug!(
+            "Tendermint: SMR triggered by sync commit to height {}",
+            height
+        );
+
+        if source != TriggerSource::State {
+            return Err(ConsensusError::Other(
+                "Sync commit source error".to_string(),
+            ));
+        } else if height <= self.height {
+            return Err(ConsensusError::Other("Delayed sync commit".to_string()));
+        } else if proof.block_hash.is_empty() || proof.height.checked_next() != Some(height) {
+            return Err(ConsensusError::CommitProofErr(format!(
+                "Proof of height {} can not sync to height {}",
+                proof.height, height
+            )));
+        } else if proof.height < self.height {
+            return Err(ConsensusError::CommitProofErr(format!(
+                "Proof of height {} is lower than height {}",
+                proof.height, self.height
+            )));
+        }
+
+        match self.last_commit.clone() {
+            Some(commit) if proof.height == self.height && commit != proof => {
+                return self.detect_fork(self.round, commit.block_hash, proof.block_hash);
+            }
+            _ => (),
+        }
+        if self.strict_commit && self.step == Step::Commit && proof.height == self.height {
+            return Err(ConsensusError::CommitProofErr(format!(
+                "Sync commit of committed height {} in strict commit mode",
+                self.height
+            )));
+        }
+        self.enter_height(height, None, None, None, TransitionReason::SyncCommit)
+    }
+
+    /// Go to the new height, activate the parameter change of the height and throw a new round
+    /// info event with the new height interval and timeout configurations.
+    fn enter_height(
+        &mut self,
+        height: Height,
+        mut new_interval: Option<u64>,
+        mut new_config: Option<DurationConfig>,
+        mut new_timeouts: Option<TimeoutConfig>,
+        reason: TransitionReason,
+    ) -> ConsensusResult<()> {
+        self.change_lock(None, LockCause::NewHeight)?;
+        self.goto_new_height(height);
+        if let Some(slo) = &mut self.commit_slo {
+            slo.height_start = self.clock.now();
+        }
+        if let Some(change) = self.activate_params(height)? {
+            new_interval = change.new_interval.or(new_interval);
+            new_config = change.new_config.or(new_config);
+            new_timeouts = change.new_timeouts.or(new_timeouts);
+        }
+        self.send_event(GenericSMREvent::NewRoundInfo {
+            height: self.height,
+            round: self.round,
+            lock_round: None,
+            lock_proposal: None,
+            new_interval,
+            new_config: new_config.map(Box::new),
+            new_timeouts: new_timeouts.map(Box::new),
+            from_where: FromWhere::NewHeight,
+            reason: FromWhere::NewHeight.to_reason(self.round),
+        })?;
+        self.goto_step(Step::Propose, reason)
+    }
+
+    /// Handle a proposal trigger. Only if self step is propose, the proposal is valid.
+    /// If proposal hash is empty, prevote to an empty hash. If the lock round is some, and the lock
+    /// round is higher than self lock round, remove PoLC. Finally throw prevote vote event. It is
+    /// impossible that the proposal hash is empty with the lock round is some.
+    fn handle_proposal(
+        &mut self,
+        proposal_hash: H,
+        round: Round,
+        lock_round: Option<Round>,
+        source: TriggerSource,
+        height: Height,
+    ) -> ConsensusResult<()> {
+        if source == TriggerSource::Timer && self.height == height {
+            self.check_proposal_timeout(&proposal_hash, round)?;
+        }
+        if self.height != height || self.round != round {
+            return Ok(());
+        }
+
+        if self.step > Step::Propose {
+            return Ok(());
+        }
+
+        smr_debug!(
+            "Tendermint: SMR triggered by a proposal hash {:?}, from {:?}, height {}, round {}",
+            HexFmt(proposal_hash.as_ref()),
+            source,
+            self.height,
+            self.round
+        );
+
+        // If the proposal trigger is from timer, goto prevote step directly.
+        if source == TriggerSource::Timer {
+            return self.prevote_without_proposal(TransitionReason::Timeout);
+        } else if proposal_hash.is_empty() {
+            return Err(ConsensusError::ProposalErr("Empty proposal".to_string()));
+        }
+
+        // update PoLC
+        self.check();
+        if let Some(lock_round) = lock_round {
+            if let Some(lock) = self.lock.clone() {
+                smr_debug!("Tendermint: SMR handle proposal with a lock");
+
+                if lock_round > lock.round {
+                    self.change_lock(None, LockCause::HigherLockRound)?;
+                    self.set_proposal(proposal_hash);
+                } else if lock_round == lock.round && proposal_hash != self.block_hash {
+                    return self.detect_fork(lock_round, self.block_hash.clone(), proposal_hash);
+                }
+            } else {
+                self.set_proposal(proposal_hash);
+            }
+        } else if self.lock.is_none() {
+            self.set_proposal(proposal_hash);
+        }
+
+        let round = self.lock.as_ref().map(|lock| lock.round);
+
+        self.send_event(GenericSMREvent::PrevoteVote {
+            height: self.height,
+            round: self.round,
+            block_hash: self.block_hash.clone(),
+            lock_round: round,
+        })?;
+        self.goto_step(Step::Prevote, TransitionReason::Proposal)
+    }
+
+    /// Handle a proposal trigger together with the delivered block payload. If the payload does
+    /// not hash to the proposal hash, the proposal is processed as a nil proposal: prevote the lock
+    /// if has, otherwise prevote an empty hash. The rejection is reported to the diagnostics with
+    /// `ViewChangeReason::CheckBlockNotPass`.
+    pub fn process_with_payload<D: Hasher<H>>(
+        &mut self,
+        msg: GenericSMRTrigger<H>,
+        hasher: &D,
+        payload: &[u8],
+    ) -> ConsensusResult<()> {
+        if msg.trigger_type != GenericTriggerType::Proposal || msg.source != TriggerSource::State {
+            return Err(ConsensusError::ProposalErr(format!(
+                "Payload with {} trigger from {:?}",
+                msg.trigger_type, msg.source
+            )));
+        }
+
+        let reason = match verify_payload(hasher, &msg.hash, payload) {
+            Ok(()) => return self.process(msg),
+            Err(reason) => reason,
+        };
+
+        if self.height != msg.height || self.round != msg.round || self.step > Step::Propose {
+            return Ok(());
+        }
+
+        let event = GenericDiagnostic::ProposalRejected {
+            hash: msg.hash.clone(),
+            reason,
+            height: msg.height,
+            round: msg.round,
+        };
+        log::warn!("Tendermint: SMR {}", event);
+        self.diagnose(event);
+        self.process(GenericSMRTrigger {
+            trigger_type: GenericTriggerType::NilProposal,
+            hash: H::empty(),
+            lock_round: None,
+            ..msg
+        })
+    }
+
+    /// Handle a nil proposal trigger of the current round, which is the empty block fast path.
+    /// Prevote the lock if has, otherwise prevote an empty hash, as on a propose timeout.
+    fn handle_nil_proposal(
+        &mut self,
+        round: Round,
+        source: TriggerSource,
+        height: Height,
+    ) -> ConsensusResult<()> {
+        if source != TriggerSource::State {
+            return Err(ConsensusError::ProposalErr(
+                "Nil proposal source error".to_string(),
+            ));
+        }
+
+        if self.height != height || self.round != round || self.step > Step::Propose {
+            return Ok(());
+        }
+
+        smr_debug!(
+            "Tendermint: SMR triggered by a nil proposal, height {}, round {}",
+            self.height,
+            self.round
+        );
+        self.prevote_without_proposal(TransitionReason::NilProposal)
+    }
+
+    /// Prevote without a valid proposal. Prevote the lock if has, otherwise prevote an empty hash.
+    fn prevote_without_proposal(&mut self, reason: TransitionReason) -> ConsensusResult<()> {
+        // This event is for timer to set a prevote timer.
+        let (round, hash) = if let Some(lock) = &self.lock {
+            (Some(lock.round), lock.hash.clone())
+        } else {
+            (None, H::empty())
+        };
+
+        self.send_event(GenericSMREvent::PrevoteVote {
+            height: self.height,
+            round: self.round,
+            block_hash: hash,
+            lock_round: round,
+        })?;
+        self.goto_step(Step::Prevote, reason)
+    }
+
+    /// Handle a prevote quorum certificate trigger. Only if self step is prevote, the prevote QC is
+    /// valid.  
+    /// The prevote round must be some. If the vote round is higher than self lock round, update
+    /// PoLC. Finally throw precommit vote event.
+    fn handle_prevote(
+        &mut self,
+        prevote: GenericQcValue<H>,
+        prevote_round: Round,
+        source: TriggerSource,
+        height: Height,
+    ) -> ConsensusResult<()> {
+        if self.height != height {
+            return Ok(());
+        }
+
+        if prevote_round == self.round && self.step > Step::Prevote {
+            return Ok(());
+        }
+
+        smr_debug!(
+            "Tendermint: SMR triggered by prevote QC {} qc round {} from {:?}, height {}, round {}",
+            prevote,
+            prevote_round,
+            source,
+            self.height,
+            self.round
+        );
+
+        if source == TriggerSource::Timer {
+            if prevote_round != self.round {
+                self.ignore(
+                    GenericTriggerType::PrevoteQC,
+                    IgnoreReason::StaleTimerRound,
+                    prevote_round,
+                );
+                return Ok(());
+            }
+
+            // This event is for timer to set a precommit timer.
+            let round = if let Some(lock) = &self.lock {
+                Some(lock.round)
+            } else {
+                self.block_hash = H::empty();
+                None
+            };
+
+            self.send_event(GenericSMREvent::PrecommitVote {
+                height: self.height,
+                round: self.round,
+                block_hash: H::empty(),
+                lock_round: round,
+            })?;
+            let reason = transition_reason(source, TransitionReason::PrevoteQC);
+            return self.goto_step(Step::Precommit, reason);
+        }
+
+        // A prevote QC from timer which means prevote timeout can not lead to unlock. Therefore,
+        // only prevote QCs from state will update the PoLC. If the prevote QC is from timer, goto
+        // precommit step directly.
+        self.check();
+
+        if prevote_round < self.round {
+            return Ok(());
+        }
+
+        // Expire the old lock before taking the lock of the QC, which would otherwise expire at
+        // once with a one round expiry.
+        let next_round = next_round(prevote_round)?;
+        if prevote_round > self.round {
+            self.expire_lock(next_round)?;
+        }
+        self.update_polc(prevote, prevote_round)?;
+
+        if prevote_round > self.round {
+            let (lock_round, lock_proposal) = self
+                .lock
+                .clone()
+                .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
+
+            let from_where = FromWhere::PrevoteQC(prevote_round);
+            let reason = from_where.to_reason(self.round);
+            self.round = prevote_round;
+            self.send_event(GenericSMREvent::NewRoundInfo {
+                height: self.height,
+                round: next_round,
+                lock_round,
+                lock_proposal,
+                new_interval: None,
+                new_config: None,
+                new_timeouts: None,
+                from_where,
+                reason,
+            })?;
+            self.goto_next_round(TransitionReason::PrevoteQC)?;
+        }
+
+        // throw precommit vote event
+        let round = self.lock.as_ref().map(|lock| lock.round);
+        self.send_event(GenericSMREvent::PrecommitVote {
+            height: self.height,
+            round: self.round,
+            block_hash: self.block_hash.clone(),
+            lock_round: round,
+        })?;
+        self.goto_step(
+            Step::Precommit,
+            transition_reason(source, TransitionReason::PrevoteQC),
+        )
+    }
+
+    /// Handle a precommit quorum certificate trigger. Only if self step is precommit, the precommit
+    /// QC is valid.
+    /// The precommit round must be some. If it is nil, throw new round event and goto next round,
+    /// or goto brake step if it is a timeout with the brake enabled. Otherwise, throw commit event.
+    fn handle_precommit(
+        &mut self,
+        precommit: GenericQcValue<H>,
+        precommit_round: Round,
+        source: TriggerSource,
+        height: Height,
+    ) -> ConsensusResult<()> {
+        if self.height != height {
+            return Ok(());
+        }
+
+        if self.step == Step::Commit {
+            return Ok(());
+        }
+
+        smr_debug!(
+            "Tendermint: SMR triggered by precommit QC {} qc round {} from {:?}, height {}, round {}",
+            precommit,
+            precommit_round,
+            source,
+            self.height,
+            self.round
+        );
+
+        let precommit_hash = match precommit {
+            GenericQcValue::Block(hash) => hash,
+            GenericQcValue::Nil => {
+                if precommit_round < self.round {
+                    if source == TriggerSource::Timer {
+                        self.ignore(
+                            GenericTriggerType::PrecommitQC,
+                            IgnoreReason::StaleTimerRound,
+                            precommit_round,
+                        );
+                    }
+                    return Ok(());
+                }
+
+                let next_round = next_round(precommit_round)?;
+                if source == TriggerSource::Timer && self.brake && precommit_round == self.round {
+                    if self.step == Step::Brake {
+                        return Ok(());
+                    }
+                    self.send_brake()?;
+                    return self.goto_step(Step::Brake, TransitionReason::Timeout);
+                }
+                self.expire_lock(next_round)?;
+                let (lock_round, lock_proposal) = self
+                    .lock
+                    .clone()
+                    .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
+
+                // A precommit timeout carries no QC, so its reason is not derived from one.
+                let from_where = FromWhere::PrecommitQC(precommit_round);
+                let reason = if source == TriggerSource::Timer {
+                    ViewChangeReason::NoPrecommitQCFromNetwork
+                } else {
+                    from_where.to_reason(self.round)
+                };
+                self.round = precommit_round;
+                self.send_event(GenericSMREvent::NewRoundInfo {
+                    height: self.height,
+                    round: next_round,
+                    lock_round,
+                    lock_proposal,
+                    new_interval: None,
+                    new_config: None,
+                    new_timeouts: None,
+                    from_where,
+                    reason,
+                })?;
+
+                let reason = transition_reason(source, TransitionReason::PrecommitQC);
+                return self.goto_next_round(reason);
+            }
+        };
+
+        self.check();
+        self.last_commit = Some(GenericCommitProof {
+            height: self.height,
+            block_hash: precommit_hash.clone(),
+        });
+        self.commit_seq += 1;
+        self.send_event(GenericSMREvent::Commit {
+            height: self.height,
+            round: precommit_round,
+            block_hash: precommit_hash,
+            polc_round: self.lock.as_ref().map(|lock| lock.round),
+            commit_seq: self.commit_seq,
+        })?;
+        self.track_commit_latency()?;
+        self.goto_step(
+            Step::Commit,
+            transition_reason(source, TransitionReason::PrecommitQC),
+        )
+    }
+
+    /// Schedule the parameter change committed before the new height. An invalid change is dropped
+    /// without rejecting the new height, since the block carrying it is already committed.
+    fn schedule_params(&mut self, change: ParamChange, height: Height) -> ConsensusResult<()> {
+        let version = self
+            .pending_params
+            .as_ref()
+            .map_or(self.params_version, |pending| pending.version);
+        if let Err(e) = change.validate(height, version) {
+            log::warn!("Tendermint: SMR drop parameter change: {}", e);
+            return Ok(());
+        }
+
+        self.send_event(GenericSMREvent::ParamChangePending {
+            version: change.version,
+            activation_height: change.activation_height,
+        })?;
+        self.pending_params = Some(change);
+        Ok(())
+    }
+
+    /// Activate the pending parameter change if the height reaches its activation height.
+    fn activate_params(&mut self, height: Height) -> ConsensusResult<Option<ParamChange>> {
+        match &self.pending_params {
+            Some(change) if change.activation_height <= height => (),
+            _ => return Ok(None),
+        }
+
+        let change = self.pending_params.take().expect("checked above");
+        self.params_version = change.version;
+        if let Some(max_round) = change.max_round {
+            self.max_round = Some(max_round);
+        }
+        self.send_event(GenericSMREvent::ParamChangeActivated {
+            version: change.version,
+            height,
+            max_round: change.max_round,
+        })?;
+        Ok(Some(change))
+    }
+
+    /// Check that a proposal timeout of the current height carries an empty hash and is not of a
+    /// future round. A malformed timeout is rejected in strict timer mode, or logged otherwise. An
+    /// empty timeout of a past round is stale, such as one queued before the round changes, so it
+    /// is ignored.
+    fn check_proposal_timeout(&mut self, hash: &H, round: Round) -> ConsensusResult<()> {
+        let err = if !hash.is_empty() {
+            format!("Proposal timeout with hash {:?}", HexFmt(hash.as_ref()))
+        } else if round > self.round {
+            format!(
+                "Proposal timeout of round {} in round {}",
+                round, self.round
+            )
+        } else if round < self.round {
+            self.ignore(
+                GenericTriggerType::Proposal,
+                IgnoreReason::StaleTimerRound,
+                round,
+            );
+            return Ok(());
+        } else {
+            return Ok(());
+        };
+
+        if self.strict_timer {
+            return Err(ConsensusError::TimerErr(err));
+        }
+        log::warn!("Tendermint: SMR {}", err);
+        Ok(())
+    }
+
+    /// Check the commit proof of a new height trigger in strict commit mode. If the SMR has
+    /// committed a block in the current height, the proof must be given and match the committed
+    /// height and block hash.
+    fn check_commit_proof(&self, proof: Option<&GenericCommitProof<H>>) -> ConsensusResult<()> {
+        let last_commit = match &self.last_commit {
+            Some(commit) => commit,
+            None => return Ok(()),
+        };
+
+        let proof = proof.ok_or_else(|| {
+            ConsensusError::CommitProofErr(format!("Missing proof of height {}", self.height))
+        })?;
+
+        if proof != last_commit {
+            return Err(ConsensusError::CommitProofErr(format!(
+                "Proof of height {} hash {:?} mismatch committed hash {:?}",
+                proof.height,
+                HexFmt(proof.block_hash.as_ref()),
+                HexFmt(last_commit.block_hash.as_ref())
+            )));
+        }
+        Ok(())
+    }
+
+    /// Handle a commit certificate trigger. The certified block of the current height is committed
+    /// directly, even if the QCs of its round were never seen, so that the next new height trigger
+    /// moves the SMR to `height + 1`. A certificate of a lower height is ignored, and one
+    /// conflicting with the committed block is a fork.
+    fn handle_commit_certificate(
+        &mut self,
+        proof: GenericCommitProof<H>,
+        round: Round,
+        source: TriggerSource,
+    ) -> ConsensusResult<()> {
+        if source != TriggerSource::State {
+            return Err(ConsensusError::Other(
+                "Commit certificate source error".to_string(),
+            ));
+        } else if proof.block_hash.is_empty() {
+            return Err(ConsensusError::CommitProofErr(
+                "Empty commit certificate hash".to_string(),
+            ));
+        } else if proof.height < self.height {
+            return Ok(());
+        } else if proof.height > self.height {
+            return Err(ConsensusError::CommitProofErr(format!(
+                "Commit certificate of height {} is higher than height {}",
+                proof.height, self.height
+            )));
+        }
+
+        smr_debug!(
+            "Tendermint: SMR triggered by commit certificate of height {}, round {}",
+            proof.height,
+            round
+        );
+
+        if self.step == Step::Commit {
+            let committed = self.last_commit.clone().map(|commit| commit.block_hash);
+            return match committed {
+                Some(hash) if hash != proof.block_hash => {
+                    self.detect_fork(round, hash, proof.block_hash)
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let block_hash = proof.block_hash.clone();
+        self.last_commit = Some(proof);
+        self.commit_seq += 1;
+        self.send_event(GenericSMREvent::Commit {
+            height: self.height,
+            round,
+            block_hash,
+            polc_round: self.lock.as_ref().map(|lock| lock.round),
+            commit_seq: self.commit_seq,
+        })?;
+        self.track_commit_latency()?;
+        self.goto_step(Step::Commit, TransitionReason::CommitCertificate)
+    }
+
+    /// Handle a brake timeout trigger. If the SMR is still braking in the round, throw the brake
+    /// event again to retry the choke message.
+    fn handle_brake_timeout(&mut self, round: Round, height: Height) -> ConsensusResult<()> {
+        if height != self.height || self.step != Step::Brake {
+            return Ok(());
+        }
+        if round != self.round {
+            self.ignore(
+                GenericTriggerType::BrakeTimeout,
+                IgnoreReason::StaleTimerRound,
+                round,
+            );
+            return Ok(());
+        }
+
+        smr_debug!("Tendermint: SMR retry brake of round {}", round);
+        self.send_brake()
+    }
+
+    fn send_brake(&mut self) -> ConsensusResult<()> {
+        self.send_event(GenericSMREvent::Brake {
+            height: self.height,
+            round: self.round,
+            lock_round: self.lock.as_ref().map(|lock| lock.round),
+        })
+    }
+
+    fn handle_continue_round(&mut self, height: Height, round: Round) -> ConsensusResult<()> {
+        if height != self.height || round <= self.round {
+            return Ok(());
+        }
+
+        smr_debug!("Tendermint: SMR continue round {}", round);
+
+        let choke_round = round
+            .checked_sub(1)
+            .ok_or_else(|| ConsensusError::OverflowErr(format!("Continue round {}", round)))?;
+        let from_where = FromWhere::ChokeQC(choke_round);
+        let reason = from_where.to_reason(self.round);
+        self.round = choke_round;
+        self.expire_lock(round)?;
+        let (lock_round, lock_proposal) = self
+            .lock
+            .clone()
+            .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
+        self.send_event(GenericSMREvent::NewRoundInfo {
+            height: self.height,
+            round,
+            lock_round,
+            lock_proposal,
+            new_interval: None,
+            new_config: None,
+            new_timeouts: None,
+            from_where,
+            reason,
+        })?;
+        self.goto_next_round(TransitionReason::ContinueRound)
+    }
+
+    /// Handle a round skip trigger. If the round is higher than self round, goto the round
+    /// directly and keep the lock. The proof must have more senders than the faulty validators of
+    /// the configured validator count.
+    fn handle_round_skip(
+        &mut self,
+        round: Round,
+        proof: RoundSkipProof,
+        source: TriggerSource,
+        height: Height,
+    ) -> ConsensusResult<()> {
+        if source != TriggerSource::State {
+            return Err(ConsensusError::Other("Round skip source error".to_string()));
+        }
+
+        if height != self.height || round <= self.round || self.step == Step::Commit {
+            return Ok(());
+        }
+
+        let validators = self.validators.ok_or_else(|| {
+            ConsensusError::Other("Round skip without the validator count".to_string())
+        })?;
+        proof.validate(self.address_len, validators)?;
+        smr_debug!(
+            "Tendermint: SMR skip from round {} to round {} by {} senders",
+            self.round,
+            round,
+            proof.senders.len()
+        );
+
+        self.expire_lock(round)?;
+        let (lock_round, lock_proposal) = self
+            .lock
+            .clone()
+            .map_or_else(|| (None, None), |lock| (Some(lock.round), Some(lock.hash)));
+        let from_where = FromWhere::RoundSkip(round);
+        let reason = from_where.to_reason(self.round);
+        self.send_event(GenericSMREvent::NewRoundInfo {
+            height: self.height,
+            round,
+            lock_round,
+            lock_proposal,
+            new_interval: None,
+            new_config: None,
+            new_timeouts: None,
+            from_where,
+            reason,
+        })?;
+        // The round is above self round, so it has a previous round.
+        self.round = Round(round.0 - 1);
+        self.goto_next_round(TransitionReason::RoundSkip)
+    }
+
+    /// Record whether the current height is committed within the budget, and throw an SLO breach
+    /// event if not.
+    fn track_commit_latency(&mut self) -> ConsensusResult<()> {
+        let now = self.clock.now();
+        let (actual, budget) = match &mut self.commit_slo {
+            Some(slo) => {
+                let actual = Duration::from_nanos(now.saturating_sub(slo.height_start));
+                if slo.recent.len() == SLO_WINDOW {
+                    slo.recent.pop_front();
+                }
+                slo.recent.push_back(actual <= slo.budget);
+                (actual, slo.budget)
+            }
+            None => return Ok(()),
+        };
+        if actual <= budget {
+            return Ok(());
+        }
+
+        log::warn!(
+            "Tendermint: SMR commit height {} in {:?} over the budget {:?}",
+            self.height,
+            actual,
+            budget
+        );
+        self.send_event(GenericSMREvent::SloBreach {
+            height: self.height,
+            actual_ms: actual.as_millis() as u64,
+            budget_ms: budget.as_millis() as u64,
+        })
+    }
+
+    fn send_event(&mut self, event: GenericSMREvent<H>) -> ConsensusResult<()> {
+        smr_debug!("Tendermint: SMR throw {} event", event);
+        self.events_thrown += 1;
+        if let Some(events) = &mut self.hook_events {
+            events.push(event.clone());
+        }
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.record(&event);
+        }
+        if let Some(captured) = &mut self.captured {
+            captured.push(event);
+            return Ok(());
+        }
+        let time = self.clock.stamp(self.timestamp_mode);
+        self.event.0.send(event.clone(), time.clone())?;
+        self.event.1.send(event, time)?;
+        Ok(())
+    }
+
+    /// Throw the evidence of a fork in the lock round and reject the trigger.
+    fn detect_fork(&mut self, round: Round, local_hash: H, remote_hash: H) -> ConsensusResult<()> {
+        log::error!(
+            "Tendermint: SMR detect fork in height {}, round {}, local hash {:?}, remote hash {:?}",
+            self.height,
+            round,
+            HexFmt(local_hash.as_ref()),
+            HexFmt(remote_hash.as_ref())
+        );
+        self.send_event(GenericSMREvent::ForkEvidence {
+            height: self.height,
+            round,
+            local_hash: local_hash.clone(),
+            remote_hash: remote_hash.clone(),
+        })?;
+        Err(ConsensusError::ForkDetected {
+            round: round.into(),
+            local_hash: Bytes::copy_from_slice(local_hash.as_ref()),
+            remote_hash: Bytes::copy_from_slice(remote_hash.as_ref()),
+        })
+    }
+
+    fn publish_view(&self) {
+        self.view.publish(GenericSmrView {
+            height: self.height,
+            round: self.round,
+            step: self.step.clone(),
+            lock: self.lock.clone(),
+            last_commit: self.last_commit.clone(),
+        });
+    }
+
+    fn report_error(&mut self, event: GenericErrorEvent<H>) {
+        smr_debug!("Tendermint: SMR reject {}", event);
+        if let Some(tx) = &self.errors {
+            if tx.send(event).is_err() {
+                self.errors = None;
+            }
+        }
+    }
+
+    fn ignore(&mut self, kind: GenericTriggerType<H>, reason: IgnoreReason, round: Round) {
+        *self.ignored.entry(reason).or_insert(0) += 1;
+        let event = GenericDiagnostic::TriggerIgnored {
+            kind,
+            reason,
+            height: self.height,
+            round,
+        };
+        smr_debug!("Tendermint: SMR {}", event);
+        self.diagnose(event);
+    }
+
+    fn diagnose(&mut self, event: GenericDiagnostic<H>) {
+        if let Some(tx) = &self.diagnostics {
+            if tx.send(event).is_err() {
+                self.diagnostics = None;
+            }
+        }
+    }
+
+    /// Goto new height and clear everything.
+    fn goto_new_height(&mut self, height: Height) {
+        smr_debug!("Tendermint: SMR goto new height: {}", height);
+        self.height = height;
+        self.round = self.init_round;
+        self.block_hash = H::empty();
+        self.lock = None;
+        self.last_commit = None;
+    }
+
+    /// Release the lock if it has been held for the expiry rounds when going to the given round.
+    fn expire_lock(&mut self, next_round: Round) -> ConsensusResult<()> {
+        let (expiry, lock) = match (self.lock_expiry, &self.lock) {
+            (Some(expiry), Some(lock)) if next_round.0.saturating_sub(lock.round.0) >= expiry => {
+                (expiry, lock.clone())
+            }
+            _ => return Ok(()),
+        };
+
+        log::warn!(
+            "Tendermint: SMR release lock round {} after {} rounds, height {}, round {}",
+            lock.round,
+            expiry,
+            self.height,
+            next_round
+        );
+        self.change_lock(None, LockCause::Expired)?;
+        self.set_proposal(H::empty());
+        self.send_event(GenericSMREvent::LockExpired {
+            height: self.height,
+            round: next_round,
+            lock_round: lock.round,
+            lock_proposal: lock.hash,
+        })
+    }
+
+    /// Keep the lock, if any, when go to the next round. The callers check that the next round
+    /// does not overflow. Without a lock, the proposal of the round is dropped, since the next
+    /// round has a new proposer and the self check requires an empty proposal in propose step
+    /// unless locked. Carrying it over would also prevote it on a propose timeout of the next
+    /// round, which the SMR has not received in that round.
+    fn goto_next_round(&mut self, reason: TransitionReason) -> ConsensusResult<()> {
+        self.round = self.round.next();
+        if self.lock.is_none() {
+            self.block_hash = H::empty();
+        }
+        smr_debug!("Tendermint: SMR goto next round {}", self.round);
+        self.goto_step(Step::Propose, reason)
+    }
+
+    /// Goto the given step and throw a state transition event if enabled.
+    #[inline]
+    fn goto_step(&mut self, step: Step, reason: TransitionReason) -> ConsensusResult<()> {
+        smr_debug!("Tendermint: SMR goto step {:?}", step);
+        let from_step = std::mem::replace(&mut self.step, step.clone());
+        if let Some((source, received)) = self.received.take() {
+            let latency = self.clock.now().saturating_sub(received);
+            match source {
+                TriggerSource::State => self.latency.0.record(latency),
+                TriggerSource::Timer => self.latency.1.record(latency),
+            }
+        }
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.last_transition = self.clock.now();
+            watchdog.reported = false;
+        }
+        if !self.transitions {
+            return Ok(());
+        }
+        self.send_event(GenericSMREvent::StateTransition {
+            height: self.height,
+            round: self.round,
+            from_step,
+            to_step: step,
+            reason,
+        })
+    }
+
+    /// Update the PoLC. Firstly set self proposal as the QC value. Secondly update the PoLC. If
+    /// the QC is nil, remove it. Otherwise, set lock round and hash as the given round and hash.
+    fn update_polc(&mut self, value: GenericQcValue<H>, round: Round) -> ConsensusResult<()> {
+        smr_debug!("Tendermint: SMR update PoLC at round {}", round);
+        match value {
+            GenericQcValue::Block(hash) => {
+                self.set_proposal(hash.clone());
+                self.change_lock(Some(GenericLock { round, hash }), LockCause::PrevoteQC)
+            }
+            GenericQcValue::Nil => {
+                self.set_proposal(H::empty());
+                self.change_lock(None, LockCause::NilPrevoteQC)
+            }
+        }
+    }
+
+    /// Change the PoLC and throw a lock changed event if it differs from the current one.
+    fn change_lock(
+        &mut self,
+        lock: Option<GenericLock<H>>,
+        cause: LockCause,
+    ) -> ConsensusResult<()> {
+        if self.lock == lock {
+            return Ok(());
+        }
+
+        let old = std::mem::replace(&mut self.lock, lock.clone());
+        self.send_event(GenericSMREvent::LockChanged {
+            height: self.height,
+            round: self.round,
+            old,
+            new: lock,
+            cause,
+        })
     }
 
     /// Set self proposal hash as the given hash.
     #[inline]
-    fn set_proposal(&mut self, proposal_hash: Hash) {
+    fn set_proposal(&mut self, proposal_hash: H) {
         self.block_hash = proposal_hash;
     }
 
-    /// Do below self checks before each message is processed:
-    /// 1. Whenever the lock is some and the proposal hash is empty, is impossible.
-    /// 2. As long as there is a lock, the lock and proposal hash must be consistent.
-    /// 3. Before precommit step, and round is 0, there can be no lock.
-    /// 4. If the step is propose, proposal hash must be empty unless lock is some.
-    #[inline(always)]
-    fn check(&mut self) -> ConsensusResult<()> {
-        log::debug!("Tendermint: SMR do self check");
-
-        // // Lock hash must be same as proposal hash, if has.
-        // if self.round == 0
-        //     && self.lock.is_some()
-        //     && self.lock.clone().unwrap().hash != self.block_hash
-        // {
-        //     return Err(ConsensusError::SelfCheckErr("Lock".to_string()));
-        // }
-
-        // // While self step lt precommit and round is 0, self lock must be none.
-        // if self.step < Step::Precommit && self.round == 0 && self.lock.is_some() {
-        //     return Err(ConsensusError::SelfCheckErr(format!(
-        //         "Invalid lock, height {}, round {}",
-        //         self.height, self.round
-        //     )));
-        // }
-
-        // // While in precommit step, the lock and the proposal hash must be NOR.
-        // if self.step == Step::Precommit &&
-        // (self.block_hash.is_empty().bitxor(self.lock.is_none())) {
-        //     return Err(ConsensusError::SelfCheckErr(format!(
-        //         "Invalid status in precommit, height {}, round {}",
-        //         self.height, self.round
-        //     )));
-        // }
-        Ok(())
+    /// Do below self checks before each message is processed:
+    /// 1. Whenever the lock is some and the proposal hash is empty, is impossible.
+    /// 2. As long as there is a lock, the lock and proposal hash must be consistent.
+    /// 3. Before precommit step, and round is the initial round, there can be no lock.
+    /// 4. If the step is propose, proposal hash must be empty unless lock is some.
+    ///
+    /// A violation is handled according to the check level.
+    #[inline(always)]
+    fn check(&self) {
+        if self.check_level == CheckLevel::Off {
+            return;
+        }
+        smr_debug!("Tendermint: SMR do self check");
+
+        let violation = match &self.lock {
+            Some(lock) if lock.hash.is_empty() || lock.hash != self.block_hash => {
+                Some("Lock mismatches proposal hash")
+            }
+            Some(_) if self.step < Step::Precommit && self.round == self.init_round => {
+                Some("Lock before precommit step of the initial round")
+            }
+            None if self.step == Step::Propose && !self.block_hash.is_empty() => {
+                Some("Proposal hash without lock in propose step")
+            }
+            _ => None,
+        };
+
+        if let Some(violation) = violation {
+            let err = ConsensusError::SelfCheckErr(format!(
+                "{}, height {}, round {}, step {:?}",
+                violation, self.height, self.round, self.step
+            ));
+            match self.check_level {
+                CheckLevel::Strict => panic!("Tendermint: SMR {}", err),
+                _ => log::error!("Tendermint: SMR {}", err),
+            }
+        }
+    }
+}
+
+/// Mutators and assertions for the unit tests of downstream crates, which put the SMR in a given
+/// state without replaying the triggers leading to it. The mutators throw no event and keep the
+/// lock consistent with the round and the step. Use `set_lock` to set the lock.
+#[cfg(any(test, feature = "test-utils"))]
+impl<H: ConsensusHash> GenericStateMachine<H> {
+    /// Set the round. The round must not be lower than the lock round.
+    pub fn set_round(&mut self, round: Round) -> ConsensusResult<()> {
+        if let Some(lock) = self.lock.as_ref().filter(|lock| lock.round > round) {
+            return Err(ConsensusError::StateErr(format!(
+                "Set round {} below the lock round {}",
+                round, lock.round
+            )));
+        }
+        self.round = round;
+        self.publish_view();
+        Ok(())
+    }
+
+    /// Set the step. A lock of the current round is only held from the precommit step.
+    pub fn set_step(&mut self, step: Step) -> ConsensusResult<()> {
+        if self.lock.as_ref().map(|lock| lock.round) == Some(self.round) && step < Step::Precommit {
+            return Err(ConsensusError::StateErr(format!(
+                "Set {:?} with a lock of round {}",
+                step, self.round
+            )));
+        }
+        self.step = step;
+        self.publish_view();
+        Ok(())
+    }
+
+    /// Assert that the SMR is in the given state.
+    #[track_caller]
+    pub fn assert_state(&self, expected: &GenericSMRState<H>) {
+        assert_eq!(
+            &self.export_state(),
+            expected,
+            "Tendermint: SMR state mismatch"
+        );
+    }
+
+    /// Assert that the SMR is in the given height, round and step.
+    #[track_caller]
+    pub fn assert_view(&self, height: Height, round: Round, step: Step) {
+        assert_eq!(
+            (self.height, self.round, &self.step),
+            (height, round, &step),
+            "Tendermint: SMR view mismatch"
+        );
+    }
+}
+
+/// A builder of the state machine, which resumes consensus from a given height, round, step,
+/// block hash and lock, for example after recovering from a crash or syncing mid-chain.
+#[rustfmt::skip]
+#[derive(Clone, Debug)]
+pub struct GenericStateMachineBuilder<H> {
+    genesis:    GenesisConfig,
+    state:      ChannelKind,
+    timer:      ChannelKind,
+    height:     Option<Height>,
+    round:      Option<Round>,
+    step:       Step,
+    block_hash: H,
+    lock:       Option<GenericLock<H>>,
+    commit_seq: u64,
+}
+
+/// The state machine builder of the default hash type.
+pub type StateMachineBuilder = GenericStateMachineBuilder<Hash>;
+
+impl<H: ConsensusHash> Default for GenericStateMachineBuilder<H> {
+    fn default() -> Self {
+        GenericStateMachineBuilder {
+            genesis: GenesisConfig::default(),
+            state: ChannelKind::default(),
+            timer: ChannelKind::default(),
+            height: None,
+            round: None,
+            step: Step::default(),
+            block_hash: H::empty(),
+            lock: None,
+            commit_seq: 0,
+        }
+    }
+}
+
+impl<H: ConsensusHash> GenericStateMachineBuilder<H> {
+    /// Create a builder starting from the default genesis.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the genesis parameters. The height and round default to the genesis ones.
+    pub fn genesis(mut self, genesis: GenesisConfig) -> Self {
+        self.genesis = genesis;
+        self
+    }
+
+    /// Set the kinds of the state and timer event channels.
+    pub fn channels(mut self, state: ChannelKind, timer: ChannelKind) -> Self {
+        self.state = state;
+        self.timer = timer;
+        self
+    }
+
+    /// Set the height to resume from.
+    pub fn height(mut self, height: Height) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Set the round to resume from.
+    pub fn round(mut self, round: Round) -> Self {
+        self.round = Some(round);
+        self
+    }
+
+    /// Set the step to resume from.
+    pub fn step(mut self, step: Step) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Set the proposal of the current round.
+    pub fn block_hash(mut self, block_hash: H) -> Self {
+        self.block_hash = block_hash;
+        self
+    }
+
+    /// Set the lock to resume with. The block hash defaults to the lock hash.
+    pub fn lock(mut self, lock: GenericLock<H>) -> Self {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// Set the sequence number of the last commit, so that the commits after resuming go on from
+    /// it rather than from zero, which the commit filter of the executor would drop.
+    pub fn commit_seq(mut self, commit_seq: u64) -> Self {
+        self.commit_seq = commit_seq;
+        self
+    }
+
+    /// Build the state machine. The height must not be lower than the genesis height, and the
+    /// lock must be non-empty, not higher than the round and agree with the block hash.
+    pub fn build(
+        self,
+    ) -> ConsensusResult<(GenericStateMachine<H>, GenericEvent<H>, GenericEvent<H>)> {
+        let height = self.height.unwrap_or(self.genesis.init_height);
+        let round = self.round.unwrap_or(self.genesis.init_round);
+        if height < self.genesis.init_height {
+            return Err(ConsensusError::StateErr(format!(
+                "Height {} is lower than genesis height {}",
+                height, self.genesis.init_height
+            )));
+        }
+
+        let block_hash = resumed_block_hash(round, self.block_hash, self.lock.as_ref())?;
+        let (mut smr, rx_state, rx_timer) =
+            GenericStateMachine::with_channels(self.genesis, self.state, self.timer);
+        smr.height = height;
+        smr.round = round;
+        smr.step = self.step;
+        smr.block_hash = block_hash;
+        smr.lock = self.lock;
+        smr.commit_seq = self.commit_seq;
+        smr.publish_view();
+        Ok((smr, rx_state, rx_timer))
+    }
+}
+
+/// Check the lock to resume with and return the block hash, which defaults to the lock hash. The
+/// lock must be non-empty, not higher than the round and agree with the block hash.
+fn resumed_block_hash<H: ConsensusHash>(
+    round: Round,
+    block_hash: H,
+    lock: Option<&GenericLock<H>>,
+) -> ConsensusResult<H> {
+    let lock = match lock {
+        Some(lock) => lock,
+        None => return Ok(block_hash),
+    };
+
+    if lock.hash.is_empty() {
+        return Err(ConsensusError::StateErr("Resume an empty lock".to_string()));
+    } else if lock.round > round {
+        return Err(ConsensusError::RoundDiff {
+            local: round.into(),
+            vote: lock.round.into(),
+        });
+    } else if !block_hash.is_empty() && block_hash != lock.hash {
+        return Err(ConsensusError::StateErr(format!(
+            "Block hash {:?} mismatch lock hash {:?}",
+            HexFmt(block_hash.as_ref()),
+            HexFmt(lock.hash.as_ref())
+        )));
+    }
+    Ok(lock.hash.clone())
+}
+
+/// Return the reason of a transition caused by a trigger from the given source, which is a
+/// timeout for the timer.
+fn transition_reason(source: TriggerSource, reason: TransitionReason) -> TransitionReason {
+    match source {
+        TriggerSource::State => reason,
+        TriggerSource::Timer => TransitionReason::Timeout,
+    }
+}
+
+/// Return the round after the given one, or an error on overflow.
+fn next_round(round: Round) -> ConsensusResult<Round> {
+    round
+        .checked_next()
+        .ok_or_else(|| ConsensusError::OverflowErr(format!("Next round of {}", round)))
+}
+
+/// The maximum number of buffered future triggers.
+pub const FUTURE_TRIGGERS: usize = 256;
+
+/// The maximum number of rounds a buffered future trigger is ahead.
+pub const FUTURE_ROUNDS: u64 = 16;
+
+/// The number of recent events kept by the watchdog.
+const WATCHDOG_EVENTS: usize = 16;
+
+/// The number of recent heights over which the commit compliance ratio is computed.
+pub const SLO_WINDOW: usize = 100;
+
+#[rustfmt::skip]
+#[derive(Debug)]
+struct Watchdog<H> {
+    timeout:         Duration,
+    last_transition: u64,
+    reported:        bool,
+    recent_events:   VecDeque<GenericSMREvent<H>>,
+}
+
+impl<H: ConsensusHash> Watchdog<H> {
+    fn record(&mut self, event: &GenericSMREvent<H>) {
+        if let GenericSMREvent::WatchdogReport { .. } = event {
+            return;
+        }
+
+        if self.recent_events.len() == WATCHDOG_EVENTS {
+            self.recent_events.pop_front();
+        }
+        self.recent_events.push_back(event.clone());
+    }
+}
+
+/// A hook on the state transitions, registered by `StateMachine::on_transition`.
+pub type GenericTransitionHook<H> = Box<dyn Fn(&GenericTransitionRecord<H>) + Send>;
+
+/// The transition hook of the default hash type.
+pub type TransitionHook = GenericTransitionHook<Hash>;
+
+struct TransitionHooks<H>(Vec<GenericTransitionHook<H>>);
+
+impl<H> Default for TransitionHooks<H> {
+    fn default() -> Self {
+        TransitionHooks(Vec::new())
+    }
+}
+
+impl<H> std::fmt::Debug for TransitionHooks<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} transition hooks", self.0.len())
+    }
+}
+
+#[rustfmt::skip]
+#[derive(Debug)]
+struct CommitSlo {
+    budget:       Duration,
+    height_start: u64,
+    /// Whether each of the recent heights is committed within the budget.
+    recent:       VecDeque<bool>,
+}
+
+/// Verify that the block payload hashes to the proposal hash.
+pub fn verify_payload<H: ConsensusHash, D: Hasher<H>>(
+    hasher: &D,
+    hash: &H,
+    payload: &[u8],
+) -> Result<(), ViewChangeReason> {
+    if hasher.hash(payload) != *hash {
+        return Err(ViewChangeReason::CheckBlockNotPass);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use rand_core::{RngCore, SeedableRng};
+    use rand_pcg::Pcg64;
+    use std::ops::BitXor;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::error::ConsensusError;
+    use crate::identity::ADDRESS_LEN;
+    use crate::smr::smr_types::{
+        CheckLevel, CommitProof, Diagnostic, FromWhere, GenericTriggerType, IgnoreReason, Lock,
+        LockCause, ProcessOutcome, QcValue, RoundSkipProof, SMREvent, SMRState, SMRStatus,
+        SMRTrigger, SmrView, Step, TransitionReason, TransitionRecord, TriggerSource, TriggerType,
+    };
+    use crate::smr::Event;
+    use crate::testing::scenario::scenario;
+    use crate::types::{
+        DurationConfig, GenesisConfig, Hash, Hasher, Height, ParamChange, Round, TimeoutConfig,
+        ViewChangeReason, VoteType,
+    };
+
+    use super::{GenericStateMachine, StateMachine, FUTURE_ROUNDS, FUTURE_TRIGGERS};
+
+    /// A hasher taking the payload itself as the hash.
+    struct IdentityHasher;
+
+    impl Hasher for IdentityHasher {
+        fn hash(&self, bytes: &[u8]) -> Hash {
+            Bytes::copy_from_slice(bytes)
+        }
+    }
+
+    /// The expected result of feeding a trigger to the state machine.
+    #[derive(Debug, PartialEq, Eq)]
+    enum Outcome {
+        /// `process_with_outcome` returns `IgnoredStale` of the trigger height and round.
+        Stale,
+        /// `process_with_outcome` returns `BufferedFuture` of the trigger height and round.
+        Buffered,
+        /// `process_with_outcome` returns `IgnoredFuture` of the trigger height and round.
+        Future,
+        /// `process_with_outcome` returns an error.
+        Rejected,
+    }
+
+    struct Case {
+        desc: &'static str,
+        height: u64,
+        round: u64,
+        step: Step,
+        trigger: SMRTrigger,
+        expect: Outcome,
+    }
+
+    fn hash() -> Hash {
+        Bytes::from(vec![1u8, 2, 3])
+    }
+
+    fn trigger(
+        trigger_type: TriggerType,
+        source: TriggerSource,
+        hash: Hash,
+        round: u64,
+        height: u64,
+    ) -> SMRTrigger {
+        SMRTrigger {
+            trigger_type,
+            source,
+            hash,
+            lock_round: None,
+            round: Round(round),
+            height: Height(height),
+        }
+    }
+
+    fn state_machine(height: u64, round: u64, step: Step) -> (StateMachine, Event, Event) {
+        let (mut smr, rx_state, rx_timer) = StateMachine::new();
+        smr.set_check_level(CheckLevel::Strict);
+        smr.height = Height(height);
+        smr.round = Round(round);
+        smr.step = step;
+        (smr, rx_state, rx_timer)
+    }
+
+    fn new_height(height: u64, commit_proof: Option<CommitProof>) -> SMRTrigger {
+        let mut status = SMRStatus::new(Height(height));
+        status.commit_proof = commit_proof;
+        trigger(
+            TriggerType::NewHeight(Box::new(status)),
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            height - 1,
+        )
+    }
+
+    /// Drive the state machine from the start of the given height to commit the given hash in
+    /// round 0.
+    fn commit_in_round_zero(smr: &mut StateMachine, height: u64, hash: Hash) {
+        use GenericTriggerType::{PrecommitQC, PrevoteQC, Proposal};
+        use TriggerSource::State;
+
+        smr.process(trigger(Proposal, State, hash.clone(), 0, height))
+            .unwrap();
+        smr.process(trigger(PrevoteQC, State, hash.clone(), 0, height))
+            .unwrap();
+        smr.process(trigger(PrecommitQC, State, hash, 0, height))
+            .unwrap();
+        assert_eq!(smr.step, Step::Commit);
+    }
+
+    #[rustfmt::skip]
+    fn early_return_cases() -> Vec<Case> {
+        use Outcome::{Buffered, Future, Rejected, Stale};
+        use TriggerSource::{State, Timer};
+        use GenericTriggerType::{ContinueRound, NewHeight, PrecommitQC, PrevoteQC, Proposal};
+
+        vec![
+            // handle_new_height
+            Case { desc: "new height from timer", height: 1, round: 0, step: Step::Commit,
+                trigger: trigger(NewHeight(Box::new(SMRStatus::new(Height(2)))), Timer, Hash::new(), 0, 1), expect: Rejected },
+            Case { desc: "new height equals current", height: 1, round: 0, step: Step::Commit,
+                trigger: trigger(NewHeight(Box::new(SMRStatus::new(Height(1)))), State, Hash::new(), 0, 1), expect: Rejected },
+            Case { desc: "new height lower than current", height: 2, round: 0, step: Step::Commit,
+                trigger: trigger(NewHeight(Box::new(SMRStatus::new(Height(1)))), State, Hash::new(), 0, 2), expect: Rejected },
+
+            // handle_proposal
+            Case { desc: "proposal lower height", height: 2, round: 0, step: Step::Propose,
+                trigger: trigger(Proposal, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "proposal higher height", height: 2, round: 0, step: Step::Propose,
+                trigger: trigger(Proposal, State, hash(), 0, 3), expect: Buffered },
+            Case { desc: "proposal lower round", height: 1, round: 2, step: Step::Propose,
+                trigger: trigger(Proposal, State, hash(), 1, 1), expect: Stale },
+            Case { desc: "proposal higher round", height: 1, round: 2, step: Step::Propose,
+                trigger: trigger(Proposal, State, hash(), 3, 1), expect: Buffered },
+            Case { desc: "proposal in prevote step", height: 1, round: 0, step: Step::Prevote,
+                trigger: trigger(Proposal, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "proposal in precommit step", height: 1, round: 0, step: Step::Precommit,
+                trigger: trigger(Proposal, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "proposal in commit step", height: 1, round: 0, step: Step::Commit,
+                trigger: trigger(Proposal, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "proposal timeout in prevote step", height: 1, round: 0, step: Step::Prevote,
+                trigger: trigger(Proposal, Timer, Hash::new(), 0, 1), expect: Stale },
+            Case { desc: "empty proposal from state", height: 1, round: 0, step: Step::Propose,
+                trigger: trigger(Proposal, State, Hash::new(), 0, 1), expect: Rejected },
+
+            // handle_prevote
+            Case { desc: "prevote QC lower height", height: 2, round: 0, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "prevote QC higher height", height: 2, round: 0, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, State, hash(), 0, 3), expect: Buffered },
+            Case { desc: "prevote QC in precommit step", height: 1, round: 0, step: Step::Precommit,
+                trigger: trigger(PrevoteQC, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "prevote QC in commit step", height: 1, round: 0, step: Step::Commit,
+                trigger: trigger(PrevoteQC, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "prevote QC lower round", height: 1, round: 2, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, State, hash(), 1, 1), expect: Stale },
+            Case { desc: "prevote timeout lower round", height: 1, round: 2, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, Timer, Hash::new(), 1, 1), expect: Stale },
+            Case { desc: "prevote timeout higher round", height: 1, round: 2, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, Timer, Hash::new(), 3, 1), expect: Future },
+            Case { desc: "prevote timeout in precommit step", height: 1, round: 0, step: Step::Precommit,
+                trigger: trigger(PrevoteQC, Timer, Hash::new(), 0, 1), expect: Stale },
+
+            // handle_precommit
+            Case { desc: "precommit QC lower height", height: 2, round: 0, step: Step::Precommit,
+                trigger: trigger(PrecommitQC, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "precommit QC higher height", height: 2, round: 0, step: Step::Precommit,
+                trigger: trigger(PrecommitQC, State, hash(), 0, 3), expect: Buffered },
+            Case { desc: "precommit QC in commit step", height: 1, round: 0, step: Step::Commit,
+                trigger: trigger(PrecommitQC, State, hash(), 0, 1), expect: Stale },
+            Case { desc: "empty precommit QC lower round", height: 1, round: 2, step: Step::Precommit,
+                trigger: trigger(PrecommitQC, State, Hash::new(), 1, 1), expect: Stale },
+            Case { desc: "precommit timeout lower round", height: 1, round: 2, step: Step::Precommit,
+                trigger: trigger(PrecommitQC, Timer, Hash::new(), 1, 1), expect: Stale },
+
+            // handle_continue_round
+            Case { desc: "continue round lower height", height: 2, round: 0, step: Step::Propose,
+                trigger: trigger(ContinueRound, State, Hash::new(), 1, 1), expect: Stale },
+            Case { desc: "continue round higher height", height: 2, round: 0, step: Step::Propose,
+                trigger: trigger(ContinueRound, State, Hash::new(), 1, 3), expect: Future },
+            Case { desc: "continue round equal round", height: 1, round: 2, step: Step::Propose,
+                trigger: trigger(ContinueRound, State, Hash::new(), 2, 1), expect: Stale },
+            Case { desc: "continue round lower round", height: 1, round: 2, step: Step::Propose,
+                trigger: trigger(ContinueRound, State, Hash::new(), 1, 1), expect: Stale },
+            Case { desc: "continue round 0", height: 1, round: 0, step: Step::Propose,
+                trigger: trigger(ContinueRound, State, Hash::new(), 0, 1), expect: Stale },
+
+            // numeric boundaries
+            Case { desc: "prevote QC max round", height: 1, round: 0, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, State, hash(), u64::MAX, 1), expect: Rejected },
+            Case { desc: "empty prevote QC max round", height: 1, round: 0, step: Step::Prevote,
+                trigger: trigger(PrevoteQC, State, Hash::new(), u64::MAX, 1), expect: Rejected },
+            Case { desc: "empty precommit QC max round", height: 1, round: 0, step: Step::Precommit,
+                trigger: trigger(PrecommitQC, State, Hash::new(), u64::MAX, 1), expect: Rejected },
+            Case { desc: "precommit timeout max round", height: 1, round: u64::MAX, step: Step::Precommit,
+                trigger: trigger(PrecommitQC, Timer, Hash::new(), u64::MAX, 1), expect: Rejected },
+        ]
+    }
+
+    #[test]
+    fn test_early_returns() {
+        for case in early_return_cases() {
+            let (mut smr, mut rx_state, mut rx_timer) =
+                state_machine(case.height, case.round, case.step.clone());
+
+            let (height, round) = (case.trigger.height, case.trigger.round);
+            let expect = match case.expect {
+                Outcome::Stale => Some(ProcessOutcome::IgnoredStale { height, round }),
+                Outcome::Buffered => Some(ProcessOutcome::BufferedFuture { height, round }),
+                Outcome::Future => Some(ProcessOutcome::IgnoredFuture { height, round }),
+                Outcome::Rejected => None,
+            };
+            let outcome = smr.process_with_outcome(case.trigger).ok();
+            assert_eq!(outcome, expect, "{}", case.desc);
+
+            assert!(
+                rx_state.try_next().is_none(),
+                "{}: state event thrown",
+                case.desc
+            );
+            assert!(
+                rx_timer.try_next().is_none(),
+                "{}: timer event thrown",
+                case.desc
+            );
+            assert_eq!(
+                smr.height,
+                Height(case.height),
+                "{}: height changed",
+                case.desc
+            );
+            assert_eq!(smr.round, Round(case.round), "{}: round changed", case.desc);
+            assert_eq!(smr.step, case.step, "{}: step changed", case.desc);
+            assert!(smr.lock.is_none(), "{}: lock changed", case.desc);
+            assert!(
+                smr.block_hash.is_empty(),
+                "{}: block hash changed",
+                case.desc
+            );
+        }
+    }
+
+    #[test]
+    fn test_strict_commit() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        smr.set_strict_commit(true);
+
+        // There is nothing committed before the first height.
+        smr.process(new_height(1, None)).unwrap();
+        commit_in_round_zero(&mut smr, 1, hash());
+
+        let res = smr.process(new_height(2, None));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+
+        let wrong_hash = CommitProof {
+            height: Height(1),
+            block_hash: Bytes::from(vec![4u8, 5, 6]),
+        };
+        let res = smr.process(new_height(2, Some(wrong_hash)));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+
+        let wrong_height = CommitProof {
+            height: Height(0),
+            block_hash: hash(),
+        };
+        let res = smr.process(new_height(2, Some(wrong_height)));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+        assert_eq!(smr.height, Height(1));
+        assert_eq!(smr.step, Step::Commit);
+
+        let proof = CommitProof {
+            height: Height(1),
+            block_hash: hash(),
+        };
+        // A sync commit does not skip the executor proof of the committed height.
+        let sync = |height: u64, proof: CommitProof| {
+            let trigger_type = TriggerType::SyncCommit {
+                height: Height(height),
+                proof: Box::new(proof),
+            };
+            trigger(trigger_type, TriggerSource::State, Hash::new(), 0, height)
+        };
+        let res = smr.process(sync(2, proof.clone()));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+        assert_eq!((smr.height, smr.step.clone()), (Height(1), Step::Commit));
+
+        smr.process(new_height(2, Some(proof))).unwrap();
+        assert_eq!(smr.height, Height(2));
+        assert_eq!(smr.step, Step::Propose);
+
+        // A lagging SMR still syncs past the heights it has not committed.
+        let proof = CommitProof {
+            height: Height(4),
+            block_hash: hash(),
+        };
+        smr.process(sync(5, proof)).unwrap();
+        assert_eq!(smr.height, Height(5));
+    }
+
+    #[test]
+    fn test_strict_timer() {
+        let timeout = |hash: Hash, round: u64| {
+            trigger(TriggerType::Proposal, TriggerSource::Timer, hash, round, 1)
+        };
+
+        // A malformed timeout is logged, with the hash ignored and a future round dropped.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 1, Step::Propose);
+        smr.process(timeout(Hash::new(), 2)).unwrap();
+        assert_eq!(smr.step, Step::Propose);
+        smr.process(timeout(hash(), 1)).unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::PrevoteVote {
+                height: Height(1),
+                round: Round(1),
+                block_hash: Hash::new(),
+                lock_round: None,
+            })
+        );
+
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 1, Step::Propose);
+        smr.set_strict_timer(true);
+        for malformed in [
+            timeout(hash(), 1),
+            timeout(hash(), 0),
+            timeout(Hash::new(), 2),
+        ] {
+            let res = smr.process(malformed);
+            assert!(matches!(res, Err(ConsensusError::TimerErr(_))));
+        }
+        assert_eq!(smr.step, Step::Propose);
+        // A timeout of a past round is stale rather than malformed.
+        smr.process(timeout(Hash::new(), 0)).unwrap();
+        assert_eq!(smr.ignored_count(IgnoreReason::StaleTimerRound), 1);
+        assert_eq!(smr.step, Step::Propose);
+        // A timeout of another height is stale rather than malformed.
+        let stale = trigger(TriggerType::Proposal, TriggerSource::Timer, hash(), 1, 0);
+        smr.process(stale).unwrap();
+        smr.process(timeout(Hash::new(), 1)).unwrap();
+        assert_eq!(smr.step, Step::Prevote);
+    }
+
+    #[test]
+    fn test_brake() {
+        let precommit_timeout = |round: u64| {
+            trigger(
+                TriggerType::PrecommitQC,
+                TriggerSource::Timer,
+                Hash::new(),
+                round,
+                1,
+            )
+        };
+        let brake_timeout = |round: u64| {
+            trigger(
+                TriggerType::BrakeTimeout,
+                TriggerSource::Timer,
+                Hash::new(),
+                round,
+                1,
+            )
+        };
+        let brake = SMREvent::Brake {
+            height: Height(1),
+            round: Round(1),
+            lock_round: None,
+        };
+
+        // Without the brake, a precommit timeout goes to the next round.
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 1, Step::Precommit);
+        smr.process(precommit_timeout(1)).unwrap();
+        assert_eq!((smr.round, smr.step.clone()), (Round(2), Step::Propose));
+
+        let (mut smr, mut rx_state, mut rx_timer) = state_machine(1, 1, Step::Precommit);
+        smr.set_brake(true);
+        smr.process(precommit_timeout(1)).unwrap();
+        assert_eq!((smr.round, smr.step.clone()), (Round(1), Step::Brake));
+        assert_eq!(rx_state.try_next(), Some(brake.clone()));
+        assert_eq!(rx_timer.try_next(), Some(brake.clone()));
+
+        // Every brake timeout retries the choke message, and a stale one is ignored.
+        for _ in 0..3 {
+            smr.process(brake_timeout(1)).unwrap();
+            assert_eq!(rx_state.try_next(), Some(brake.clone()));
+        }
+        smr.process(brake_timeout(0)).unwrap();
+        smr.process(precommit_timeout(1)).unwrap();
+        assert_eq!(rx_state.try_next(), None);
+        assert_eq!(smr.ignored_count(IgnoreReason::StaleTimerRound), 1);
+
+        // A choke QC continues to the next round, after which the brake timeout is stale.
+        smr.process(SMRTrigger::continue_round(Round(2), Height(1)))
+            .unwrap();
+        assert_eq!((smr.round, smr.step.clone()), (Round(2), Step::Propose));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                from_where: FromWhere::ChokeQC(Round(1)),
+                ..
+            })
+        ));
+        smr.process(brake_timeout(1)).unwrap();
+        assert_eq!(rx_state.try_next(), None);
+    }
+
+    #[test]
+    fn test_loose_commit() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+
+        smr.process(new_height(1, None)).unwrap();
+        commit_in_round_zero(&mut smr, 1, hash());
+        smr.process(new_height(2, None)).unwrap();
+        assert_eq!(smr.height, Height(2));
+    }
+
+    #[test]
+    fn test_sync_commit() {
+        let sync = |height: u64, proof_height: u64, block_hash: Hash| {
+            let sync_commit = TriggerType::SyncCommit {
+                height: Height(height),
+                proof: Box::new(CommitProof {
+                    height: Height(proof_height),
+                    block_hash,
+                }),
+            };
+            trigger(sync_commit, TriggerSource::State, Hash::new(), 0, height)
+        };
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        smr.set_strict_commit(true);
+        smr.process(new_height(1, None)).unwrap();
+        commit_in_round_zero(&mut smr, 1, hash());
+        while rx_state.try_next().is_some() {}
+
+        assert!(smr.process(sync(1, 0, hash())).is_err());
+        let res = smr.process(sync(5, 3, hash()));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+        let res = smr.process(sync(5, 4, Hash::new()));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+        let res = smr.process(sync(2, 1, Bytes::from(vec![2u8])));
+        assert!(matches!(res, Err(ConsensusError::ForkDetected { .. })));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::ForkEvidence { .. })
+        ));
+        assert_eq!(smr.height, Height(1));
+
+        // Jump over the heights never seen, even in strict commit mode.
+        smr.process(sync(5, 4, hash())).unwrap();
+        assert_eq!(smr.height, Height(5));
+        assert_eq!(smr.step, Step::Propose);
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::LockChanged {
+                new: None,
+                cause: LockCause::NewHeight,
+                ..
+            })
+        ));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                height: Height(5),
+                from_where: FromWhere::NewHeight,
+                ..
+            })
+        ));
+        let res = smr.process(sync(5, 4, hash()));
+        assert!(matches!(res, Err(ConsensusError::Other(_))));
+    }
+
+    #[test]
+    fn test_view_change_reason() {
+        let reason = |rx_state: &mut Event| loop {
+            match rx_state.try_next() {
+                Some(SMREvent::NewRoundInfo { reason, .. }) => return reason,
+                Some(_) => (),
+                None => panic!("no new round info event"),
+            }
+        };
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        smr.process(new_height(1, None)).unwrap();
+        assert_eq!(reason(&mut rx_state), ViewChangeReason::Others);
+
+        let qc = trigger(TriggerType::PrevoteQC, TriggerSource::State, hash(), 2, 1);
+        smr.process(qc).unwrap();
+        assert_eq!(
+            reason(&mut rx_state),
+            ViewChangeReason::UpdateFromHigherPrevoteQC(Round(0), Round(2))
+        );
+
+        let nil = trigger(
+            TriggerType::PrecommitQC,
+            TriggerSource::Timer,
+            Hash::new(),
+            3,
+            1,
+        );
+        smr.process(nil).unwrap();
+        assert_eq!(
+            reason(&mut rx_state),
+            ViewChangeReason::NoPrecommitQCFromNetwork
+        );
+
+        let nil = trigger(
+            TriggerType::PrecommitQC,
+            TriggerSource::State,
+            Hash::new(),
+            5,
+            1,
+        );
+        smr.process(nil).unwrap();
+        assert_eq!(
+            reason(&mut rx_state),
+            ViewChangeReason::UpdateFromHigherPrecommitQC(Round(4), Round(5))
+        );
+
+        let choke = trigger(
+            TriggerType::ContinueRound,
+            TriggerSource::State,
+            Hash::new(),
+            8,
+            1,
+        );
+        smr.process(choke).unwrap();
+        assert_eq!(
+            reason(&mut rx_state),
+            ViewChangeReason::UpdateFromHigherChokeQC(Round(6), Round(7))
+        );
+    }
+
+    /// Deliver the triggers of one honest height in random orders, with late timer triggers and a
+    /// duplicate new height. The tolerated behavior is that every trigger is either applied or
+    /// rejected without breaking the self checks, and the block is committed exactly once, since
+    /// the QCs delivered before the new height are buffered.
+    #[test]
+    fn test_next_round_drops_proposal() {
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        smr.process(proposal).unwrap();
+        assert_eq!(smr.block_hash, hash());
+
+        // A nil precommit QC arrives before the prevote QC, and the proposal of round 0 is not
+        // carried over to round 1 without a lock.
+        let nil = trigger(
+            TriggerType::PrecommitQC,
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            1,
+        );
+        smr.process(nil).unwrap();
+        assert_eq!((smr.round, smr.step.clone()), (Round(1), Step::Propose));
+        assert!(smr.block_hash.is_empty());
+    }
+
+    #[test]
+    fn test_misordered_triggers() {
+        use GenericTriggerType::{PrecommitQC, PrevoteQC, Proposal};
+        use TriggerSource::{State, Timer};
+
+        let triggers = vec![
+            new_height(1, None),
+            trigger(Proposal, State, hash(), 0, 1),
+            trigger(PrevoteQC, State, hash(), 0, 1),
+            trigger(PrecommitQC, State, hash(), 0, 1),
+            trigger(Proposal, Timer, Hash::new(), 0, 1),
+            trigger(PrevoteQC, Timer, Hash::new(), 0, 1),
+            trigger(PrecommitQC, Timer, Hash::new(), 0, 1),
+            new_height(1, None),
+        ];
+
+        for seed in 0..256 {
+            let mut rng = Pcg64::seed_from_u64(seed);
+            let mut order = triggers.clone();
+            for i in (1..order.len()).rev() {
+                order.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
+            }
+
+            let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+            smr.set_check_level(CheckLevel::Strict);
+            let mut commits = Vec::new();
+            for trigger in order {
+                if let Ok(events) = smr.process_sync(trigger) {
+                    commits.extend(events.into_iter().filter_map(|event| match event {
+                        SMREvent::Commit { block_hash, .. } => Some(block_hash),
+                        _ => None,
+                    }));
+                }
+            }
+
+            assert_eq!(smr.height, Height(1), "seed {}", seed);
+            assert_eq!(commits, vec![hash()], "seed {}", seed);
+            assert_eq!(smr.step, Step::Commit, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_round_skip() {
+        let skip = |round: u64, senders: u8| {
+            let proof = RoundSkipProof {
+                senders: (0..senders)
+                    .map(|byte| Bytes::from(vec![byte; ADDRESS_LEN]))
+                    .collect(),
+            };
+            let round_skip = TriggerType::RoundSkip {
+                round: Round(round),
+                proof: Box::new(proof),
+            };
+            trigger(round_skip, TriggerSource::State, Hash::new(), round, 1)
+        };
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Prevote);
+        smr.update_polc(QcValue::Block(hash()), Round(1)).unwrap();
+        rx_state.try_next();
+
+        // Without the validator count, no proof is enough.
+        assert!(smr.process(skip(5, 2)).is_err());
+        // Two senders are not enough for seven validators, whatever the peer claims.
+        smr.set_validator_count(7);
+        assert!(smr.process(skip(5, 2)).is_err());
+        assert_eq!(smr.round, Round(2));
+
+        smr.set_validator_count(4);
+        assert!(smr.process(skip(5, 1)).is_err());
+        smr.process(skip(2, 2)).unwrap();
+        assert!(rx_state.try_next().is_none());
+        assert_eq!(smr.round, Round(2));
+
+        // Skip to the round and keep the lock.
+        smr.process(skip(5, 2)).unwrap();
+        assert_eq!((smr.round, smr.step.clone()), (Round(5), Step::Propose));
+        assert_eq!(smr.lock.as_ref().map(|lock| lock.round), Some(Round(1)));
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                height: Height(1),
+                round: Round(5),
+                lock_round: Some(Round(1)),
+                lock_proposal: Some(hash()),
+                new_interval: None,
+                new_config: None,
+                new_timeouts: None,
+                from_where: FromWhere::RoundSkip(Round(5)),
+                reason: ViewChangeReason::RoundSkip(Round(2), Round(5)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_nil_proposal() {
+        let nil = |source: TriggerSource, round: u64| {
+            trigger(TriggerType::NilProposal, source, Hash::new(), round, 1)
+        };
+        let (mut smr, mut rx_state, mut rx_timer) = state_machine(1, 1, Step::Propose);
+        assert!(smr.process(nil(TriggerSource::Timer, 1)).is_err());
+        smr.process(nil(TriggerSource::State, 0)).unwrap();
+        assert_eq!(smr.step, Step::Propose);
+
+        smr.process(nil(TriggerSource::State, 1)).unwrap();
+        assert_eq!(smr.step, Step::Prevote);
+        let prevote = SMREvent::PrevoteVote {
+            height: Height(1),
+            round: Round(1),
+            block_hash: Hash::new(),
+            lock_round: None,
+        };
+        assert_eq!(rx_state.try_next(), Some(prevote.clone()));
+        assert_eq!(rx_timer.try_next(), Some(prevote));
+
+        // A locked validator prevotes the lock.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 1, Step::Propose);
+        smr.adopt_lock(Round(0), hash(), hash()).unwrap();
+        rx_state.try_next();
+        smr.process(nil(TriggerSource::State, 1)).unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::PrevoteVote { block_hash, lock_round: Some(Round(0)), .. })
+                if block_hash == hash()
+        ));
+    }
+
+    #[test]
+    fn test_check_level() {
+        let broken = |level: CheckLevel| {
+            let (mut smr, rx_state, rx_timer) = state_machine(1, 0, Step::Propose);
+            smr.set_check_level(level);
+            smr.lock = Some(Lock {
+                round: Round(0),
+                hash: hash(),
+            });
+            (smr, rx_state, rx_timer)
+        };
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+
+        for level in [CheckLevel::Off, CheckLevel::Warn] {
+            let (mut smr, _rx_state, _rx_timer) = broken(level);
+            smr.process(proposal.clone()).unwrap();
+            assert_eq!(smr.step, Step::Prevote);
+        }
+
+        let (mut smr, _rx_state, _rx_timer) = broken(CheckLevel::Strict);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| smr.process(proposal)));
+        assert!(res.is_err());
+
+        // The initial round is the genesis one.
+        let (mut smr, _rx_state, _rx_timer) =
+            StateMachine::with_genesis(GenesisConfig::new(Height(1), Round(2)));
+        smr.set_check_level(CheckLevel::Strict);
+        smr.height = Height(1);
+        smr.step = Step::Propose;
+        smr.block_hash = hash();
+        smr.lock = Some(Lock {
+            round: Round(2),
+            hash: hash(),
+        });
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| smr.check()));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_commit_certificate() {
+        let other_hash = Bytes::from(vec![2u8]);
+        let certificate = |height: u64, block_hash: Hash| {
+            let proof = CommitProof {
+                height: Height(height),
+                block_hash,
+            };
+            let certificate = TriggerType::CommitCertificate(Box::new(proof));
+            trigger(certificate, TriggerSource::State, Hash::new(), 3, 2)
+        };
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(2, 1, Step::Prevote);
+        smr.set_strict_commit(true);
+
+        let mut timeout = certificate(2, hash());
+        timeout.source = TriggerSource::Timer;
+        assert!(smr.process(timeout).is_err());
+        let res = smr.process(certificate(2, Hash::new()));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+        let res = smr.process(certificate(3, hash()));
+        assert!(matches!(res, Err(ConsensusError::CommitProofErr(_))));
+        smr.process(certificate(1, hash())).unwrap();
+        assert!(rx_state.try_next().is_none());
+        assert_eq!(smr.step, Step::Prevote);
+
+        // The certified block is committed without the QCs of its round.
+        smr.process(certificate(2, hash())).unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::Commit {
+                height: Height(2),
+                round: Round(3),
+                block_hash: hash(),
+                polc_round: None,
+                commit_seq: 1,
+            })
+        );
+        assert_eq!(smr.step, Step::Commit);
+        smr.process(certificate(2, hash())).unwrap();
+        assert!(rx_state.try_next().is_none());
+
+        let res = smr.process(certificate(2, other_hash.clone()));
+        assert!(matches!(res, Err(ConsensusError::ForkDetected { .. })));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::ForkEvidence { .. })
+        ));
+
+        let proof = CommitProof {
+            height: Height(2),
+            block_hash: hash(),
+        };
+        smr.process(new_height(3, Some(proof))).unwrap();
+        assert_eq!(smr.height, Height(3));
+    }
+
+    #[test]
+    fn test_genesis() {
+        let genesis = GenesisConfig::new(Height(10), Round(1));
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::with_genesis(genesis);
+        assert_eq!(smr.height, Height(10));
+        assert_eq!(smr.round, Round(1));
+
+        let res = smr.process(new_height(10, None));
+        assert!(res.is_err());
+
+        smr.process(new_height(11, None)).unwrap();
+        assert_eq!(smr.height, Height(11));
+        assert_eq!(smr.round, Round(1));
+        match rx_state.try_next() {
+            Some(SMREvent::NewRoundInfo {
+                height,
+                round,
+                from_where,
+                ..
+            }) => {
+                assert_eq!(height, Height(11));
+                assert_eq!(round, Round(1));
+                assert_eq!(from_where, FromWhere::NewHeight);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_adopt_lock() {
+        let other_hash = Bytes::from(vec![4u8, 5, 6]);
+        let digest = Bytes::from(vec![7u8]);
+
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 3, Step::Propose);
+        assert!(smr
+            .adopt_lock(Round(1), Hash::new(), digest.clone())
+            .is_err());
+        assert!(smr.adopt_lock(Round(1), hash(), Hash::new()).is_err());
+        assert_eq!(
+            smr.adopt_lock(Round(4), hash(), digest.clone()),
+            Err(ConsensusError::RoundDiff { local: 3, vote: 4 })
+        );
+        // A lock of the current round is only held from the precommit step.
+        let res = smr.adopt_lock(Round(3), hash(), digest.clone());
+        assert!(matches!(res, Err(ConsensusError::StateErr(_))));
+        assert!(smr.lock.is_none());
+
+        smr.adopt_lock(Round(1), hash(), digest.clone()).unwrap();
+        assert_eq!(smr.lock.as_ref().map(|lock| lock.round), Some(Round(1)));
+        assert_eq!(smr.block_hash, hash());
+
+        // A lower lock round is ignored and a conflicting lock of the same round is a fork.
+        smr.adopt_lock(Round(0), other_hash.clone(), digest.clone())
+            .unwrap();
+        assert_eq!(smr.block_hash, hash());
+        assert_eq!(
+            smr.adopt_lock(Round(1), other_hash.clone(), digest.clone()),
+            Err(ConsensusError::ForkDetected {
+                round: 1,
+                local_hash: hash(),
+                remote_hash: other_hash.clone(),
+            })
+        );
+
+        // A higher lock round replaces the lock.
+        smr.adopt_lock(Round(2), other_hash.clone(), digest.clone())
+            .unwrap();
+        assert_eq!(smr.lock.as_ref().map(|lock| lock.round), Some(Round(2)));
+        assert_eq!(smr.block_hash, other_hash);
+
+        smr.step = Step::Prevote;
+        assert!(smr.adopt_lock(Round(2), other_hash, digest).is_err());
+    }
+
+    #[test]
+    fn test_set_lock() {
+        let digest = Bytes::from(vec![7u8]);
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Prevote);
+
+        assert!(smr
+            .set_lock(Height(2), Round(1), hash(), digest.clone())
+            .is_err());
+        assert!(smr
+            .set_lock(Height(1), Round(2), hash(), digest.clone())
+            .is_err());
+        assert!(smr
+            .set_lock(Height(1), Round(1), hash(), Hash::new())
+            .is_err());
+        assert!(smr.lock.is_none());
+
+        // A lock of a previous round is restored in any step of the round but commit.
+        smr.set_lock(Height(1), Round(1), hash(), digest.clone())
+            .unwrap();
+        assert_eq!(smr.lock.as_ref().map(|lock| lock.round), Some(Round(1)));
+        assert_eq!(smr.block_hash, hash());
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::LockChanged {
+                cause: LockCause::Restored,
+                ..
+            })
+        ));
+        smr.revote().unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::PrevoteVote { block_hash, .. }) if block_hash == hash()
+        ));
+
+        // A lock of the current round is restored from the precommit step.
+        smr.step = Step::Precommit;
+        smr.set_lock(Height(1), Round(2), hash(), digest.clone())
+            .unwrap();
+        assert_eq!(smr.lock.as_ref().map(|lock| lock.round), Some(Round(2)));
+
+        smr.step = Step::Commit;
+        assert!(smr.set_lock(Height(1), Round(2), hash(), digest).is_err());
     }
-}
 
-#[cfg(test)]
-mod test {
-    use bytes::Bytes;
-    use std::ops::BitXor;
+    #[test]
+    fn test_payload() {
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+
+        // The payload hashes to the proposal hash.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        smr.process_with_payload(proposal.clone(), &IdentityHasher, &hash())
+            .unwrap();
+        match rx_state.try_next() {
+            Some(SMREvent::PrevoteVote { block_hash, .. }) => assert_eq!(block_hash, hash()),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(smr.step, Step::Prevote);
+
+        // The payload mismatches, prevote an empty hash through the normal processing.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        let mut rx_diagnostic = smr.subscribe_diagnostics();
+        let records = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&records);
+        smr.on_transition(Box::new(move |_| *counter.lock().unwrap() += 1));
+        smr.process_with_payload(proposal.clone(), &IdentityHasher, &[0u8])
+            .unwrap();
+        match rx_state.try_next() {
+            Some(SMREvent::PrevoteVote { block_hash, .. }) => assert!(block_hash.is_empty()),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(smr.step, Step::Prevote);
+        assert!(smr.block_hash.is_empty());
+        assert_eq!(smr.view_handle().load().step, Step::Prevote);
+        assert_eq!(*records.lock().unwrap(), 1);
+        assert_eq!(
+            rx_diagnostic.try_next().unwrap(),
+            Diagnostic::ProposalRejected {
+                hash: hash(),
+                reason: ViewChangeReason::CheckBlockNotPass,
+                height: Height(1),
+                round: Round(0),
+            }
+        );
+
+        // The payload mismatches, prevote the lock.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 1, Step::Propose);
+        let lock_hash = Bytes::from(vec![4u8, 5, 6]);
+        smr.adopt_lock(Round(0), lock_hash.clone(), Bytes::from(vec![7u8]))
+            .unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::LockChanged {
+                cause: LockCause::Adopted,
+                ..
+            })
+        ));
+        let mut proposal = proposal;
+        proposal.round = Round(1);
+        smr.process_with_payload(proposal, &IdentityHasher, &[0u8])
+            .unwrap();
+        match rx_state.try_next() {
+            Some(SMREvent::PrevoteVote {
+                block_hash,
+                lock_round,
+                ..
+            }) => {
+                assert_eq!(block_hash, lock_hash);
+                assert_eq!(lock_round, Some(Round(0)));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Only proposals from state carry a payload.
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        let timeout = trigger(
+            TriggerType::Proposal,
+            TriggerSource::Timer,
+            Hash::new(),
+            0,
+            1,
+        );
+        assert!(smr
+            .process_with_payload(timeout, &IdentityHasher, &[])
+            .is_err());
+    }
+
+    #[test]
+    fn test_error_events() {
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        let mut rx_error = smr.subscribe_errors();
+        let peer = Bytes::from(vec![9u8; ADDRESS_LEN]);
+
+        let empty = trigger(
+            TriggerType::Proposal,
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            1,
+        );
+        assert!(smr.process_from(empty, Some(peer.clone())).is_err());
+        let event = rx_error.try_next().unwrap();
+        assert_eq!(event.error, ConsensusError::ProposalErr(String::new()));
+        assert_eq!(event.trigger_type, TriggerType::Proposal);
+        assert_eq!(event.source, TriggerSource::State);
+        assert_eq!((event.height, event.round), (Height(1), Round(0)));
+        assert_eq!(event.peer, Some(peer));
+
+        // Ignored triggers are not errors.
+        let stale = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 0);
+        smr.process(stale).unwrap();
+        assert!(rx_error.try_next().is_none());
+
+        // Dropping the receiver does not affect processing.
+        drop(rx_error);
+        let empty = trigger(
+            TriggerType::Proposal,
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            1,
+        );
+        assert!(smr.process(empty).is_err());
+        assert!(smr.errors.is_none());
+    }
+
+    #[test]
+    fn test_ignored_triggers() {
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 1, Step::Prevote);
+        let mut rx_diagnostic = smr.subscribe_diagnostics();
+
+        let stale = trigger(
+            TriggerType::PrevoteQC,
+            TriggerSource::Timer,
+            Hash::new(),
+            0,
+            1,
+        );
+        smr.process(stale).unwrap();
+        assert_eq!(smr.step, Step::Prevote);
+        assert_eq!(
+            rx_diagnostic.try_next().unwrap(),
+            Diagnostic::TriggerIgnored {
+                kind: TriggerType::PrevoteQC,
+                reason: IgnoreReason::StaleTimerRound,
+                height: Height(1),
+                round: Round(0),
+            }
+        );
+
+        let stale = trigger(
+            TriggerType::PrecommitQC,
+            TriggerSource::Timer,
+            Hash::new(),
+            0,
+            1,
+        );
+        smr.process(stale).unwrap();
+        assert!(rx_diagnostic.try_next().is_some());
+        assert_eq!(smr.ignored_count(IgnoreReason::StaleTimerRound), 2);
+
+        // A stale QC from state is not a timer misfire.
+        let stale = trigger(TriggerType::PrevoteQC, TriggerSource::State, hash(), 0, 1);
+        smr.process(stale).unwrap();
+        assert!(rx_diagnostic.try_next().is_none());
+        assert_eq!(smr.ignored_count(IgnoreReason::StaleTimerRound), 2);
+    }
+
+    #[test]
+    fn test_param_change() {
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        let change = ParamChange {
+            version: 1,
+            activation_height: Height(3),
+            new_interval: Some(3000),
+            new_config: None,
+            new_timeouts: None,
+            max_round: Some(Round(1)),
+        };
+        let with_change = |height: u64, change: ParamChange| {
+            let mut status = SMRStatus::new(Height(height));
+            status.param_change = Some(change);
+            trigger(
+                TriggerType::NewHeight(Box::new(status)),
+                TriggerSource::State,
+                Hash::new(),
+                0,
+                height - 1,
+            )
+        };
+
+        smr.process(with_change(1, change.clone())).unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::ParamChangePending {
+                version: 1,
+                activation_height: Height(3),
+            })
+        );
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                new_interval: None,
+                ..
+            })
+        ));
+
+        // A change with a stale version is dropped without rejecting the height.
+        smr.process(with_change(2, change)).unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo { .. })
+        ));
+        assert_eq!(smr.params_version(), 0);
+
+        smr.process(new_height(3, None)).unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::ParamChangeActivated {
+                version: 1,
+                height: Height(3),
+                max_round: Some(Round(1)),
+            })
+        );
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                new_interval: Some(3000),
+                ..
+            })
+        ));
+        assert_eq!(smr.params_version(), 1);
+
+        // Triggers beyond the max round are rejected.
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 2, 3);
+        assert!(smr.process(proposal.clone()).is_err());
+
+        // The active parameters survive a restart.
+        let (mut restored, _rx_state, _rx_timer) = StateMachine::new();
+        restored.import_state(smr.export_state()).unwrap();
+        assert_eq!(restored.params_version(), 1);
+        assert!(restored.process(proposal).is_err());
+
+        // An invalid timeout configuration is dropped, and the height is entered with the current
+        // one, since the block carrying it is already committed.
+        let mut status = SMRStatus::new(Height(4));
+        status.new_config = Some(DurationConfig::new(24, 0, 5, 3));
+        let storm = trigger(
+            TriggerType::NewHeight(Box::new(status)),
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            3,
+        );
+        smr.process(storm).unwrap();
+        assert_eq!(smr.height, Height(4));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                new_config: None,
+                ..
+            })
+        ));
+
+        // The absolute timeouts are validated and passed to the timer, and the invalid ones are
+        // dropped likewise.
+        let new_height = |height: u64, timeouts: TimeoutConfig| {
+            let mut status = SMRStatus::new(Height(height));
+            status.new_timeouts = Some(timeouts);
+            trigger(
+                TriggerType::NewHeight(Box::new(status)),
+                TriggerSource::State,
+                Hash::new(),
+                0,
+                height - 1,
+            )
+        };
+        smr.process(new_height(5, TimeoutConfig::default()))
+            .unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                height: Height(5),
+                new_timeouts: None,
+                ..
+            })
+        ));
+        let second = Duration::from_secs(1);
+        let timeouts = TimeoutConfig::new(second * 3, second * 2, second, second);
+        smr.process(new_height(6, timeouts.clone())).unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                height: Height(6),
+                round: Round(0),
+                lock_round: None,
+                lock_proposal: None,
+                from_where: FromWhere::NewHeight,
+                reason: ViewChangeReason::Others,
+                new_interval: None,
+                new_config: None,
+                new_timeouts: Some(Box::new(timeouts)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_transition_latency() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        smr.process(new_height(1, None)).unwrap();
+        let timeout = trigger(
+            TriggerType::Proposal,
+            TriggerSource::Timer,
+            Hash::new(),
+            0,
+            1,
+        );
+        smr.process(timeout).unwrap();
+        // A stale trigger causes no transition.
+        let stale = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        smr.process(stale).unwrap();
+
+        assert_eq!(smr.transition_latency(TriggerSource::State).count(), 1);
+        assert_eq!(smr.transition_latency(TriggerSource::Timer).count(), 1);
+    }
+
+    #[test]
+    fn test_proposal_fork() {
+        let other_hash = Bytes::from(vec![2u8]);
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Propose);
+        smr.update_polc(QcValue::Block(hash()), Round(1)).unwrap();
+        rx_state.try_next();
+
+        let mut proposal = trigger(
+            TriggerType::Proposal,
+            TriggerSource::State,
+            other_hash.clone(),
+            2,
+            1,
+        );
+        proposal.lock_round = Some(Round(1));
+        assert_eq!(
+            smr.process(proposal.clone()),
+            Err(ConsensusError::ForkDetected {
+                round: 1,
+                local_hash: hash(),
+                remote_hash: other_hash.clone(),
+            })
+        );
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::ForkEvidence {
+                height: Height(1),
+                round: Round(1),
+                local_hash: hash(),
+                remote_hash: other_hash.clone(),
+            })
+        );
+        assert_eq!(smr.step, Step::Propose);
+        assert_eq!(smr.block_hash, hash());
+
+        // The same proposal with the lock hash is not a fork.
+        let mut same = proposal.clone();
+        same.hash = hash();
+        smr.process(same).unwrap();
+        assert_eq!(smr.step, Step::Prevote);
+
+        // A proposal with a higher lock round unlocks and prevotes the proposal.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Propose);
+        smr.update_polc(QcValue::Block(hash()), Round(0)).unwrap();
+        rx_state.try_next();
+        smr.process(proposal).unwrap();
+        assert!(smr.lock.is_none());
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::LockChanged {
+                new: None,
+                cause: LockCause::HigherLockRound,
+                ..
+            })
+        ));
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::PrevoteVote {
+                height: Height(1),
+                round: Round(2),
+                block_hash: other_hash,
+                lock_round: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_revote() {
+        for step in [Step::Propose, Step::Commit] {
+            let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, step);
+            smr.update_polc(QcValue::Block(hash()), Round(1)).unwrap();
+            rx_state.try_next();
+            smr.revote().unwrap();
+            assert!(rx_state.try_next().is_none());
+        }
+
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Prevote);
+        smr.update_polc(QcValue::Block(hash()), Round(1)).unwrap();
+        rx_state.try_next();
+        smr.revote().unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::PrevoteVote {
+                height: Height(1),
+                round: Round(2),
+                block_hash: hash(),
+                lock_round: Some(Round(1)),
+            })
+        );
+
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Precommit);
+        smr.update_polc(QcValue::Block(hash()), Round(2)).unwrap();
+        rx_state.try_next();
+        smr.revote().unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::PrecommitVote {
+                height: Height(1),
+                round: Round(2),
+                block_hash: hash(),
+                lock_round: Some(Round(2)),
+            })
+        );
+        assert_eq!(smr.step, Step::Precommit);
+
+        // Without a lock, precommit nil.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 2, Step::Precommit);
+        smr.revote().unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::PrecommitVote {
+                height: Height(1),
+                round: Round(2),
+                block_hash: Hash::new(),
+                lock_round: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let lock = Lock {
+            round: Round(1),
+            hash: hash(),
+        };
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::builder()
+            .genesis(GenesisConfig::new(Height(10), Round(0)))
+            .height(Height(12))
+            .round(Round(2))
+            .step(Step::Precommit)
+            .lock(lock.clone())
+            .build()
+            .unwrap();
+        assert_eq!((smr.height(), smr.round()), (Height(12), Round(2)));
+        assert_eq!(smr.step(), &Step::Precommit);
+        assert_eq!(smr.block_hash(), &hash());
+        assert_eq!(smr.lock(), Some(&lock));
+        assert_eq!(smr.view_handle().load().lock, Some(lock.clone()));
+
+        // The resumed state machine votes for the lock.
+        smr.revote().unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::PrecommitVote {
+                lock_round: Some(Round(1)),
+                ..
+            })
+        ));
+
+        let below_genesis = StateMachine::builder()
+            .genesis(GenesisConfig::new(Height(10), Round(0)))
+            .height(Height(9))
+            .build();
+        assert!(below_genesis.is_err());
+        let future_lock = StateMachine::builder().lock(lock.clone()).build();
+        assert_eq!(
+            future_lock.err(),
+            Some(ConsensusError::RoundDiff { local: 0, vote: 1 })
+        );
+        let mismatch = StateMachine::builder()
+            .round(Round(1))
+            .block_hash(Bytes::from(vec![2u8]))
+            .lock(lock)
+            .build();
+        assert!(mismatch.is_err());
+    }
+
+    #[test]
+    fn test_quorum_unavailable() {
+        let (mut smr, mut rx_state, mut rx_timer) = state_machine(3, 4, Step::Propose);
+        let validators = vec![Bytes::from(vec![9u8; ADDRESS_LEN])];
+        smr.report_quorum_unavailable(1, validators.clone())
+            .unwrap();
+        let event = SMREvent::QuorumUnavailable {
+            height: Height(3),
+            round: Round(4),
+            missing_power: 1,
+            validators,
+        };
+        assert_eq!(rx_state.try_next(), Some(event.clone()));
+        assert_eq!(rx_timer.try_next(), Some(event));
+        assert_eq!(smr.step, Step::Propose);
+    }
+
+    #[test]
+    fn test_process_sync() {
+        let (mut smr, rx_state, rx_timer) = StateMachine::new();
+        // The channels are not used.
+        drop((rx_state, rx_timer));
+
+        let events = smr.process_sync(new_height(1, None)).unwrap();
+        assert!(matches!(events[..], [SMREvent::NewRoundInfo { .. }]));
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        let events = smr.process_sync(proposal).unwrap();
+        assert_eq!(
+            events,
+            vec![SMREvent::PrevoteVote {
+                height: Height(1),
+                round: Round(0),
+                block_hash: hash(),
+                lock_round: None,
+            }]
+        );
+        let stale = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        assert!(smr.process_sync(stale).unwrap().is_empty());
+        let empty = trigger(
+            TriggerType::PrevoteQC,
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            2,
+        );
+        assert!(smr.process_sync(empty).unwrap().is_empty());
+
+        // The asynchronous path still uses the dropped channels.
+        let qc = trigger(TriggerType::PrevoteQC, TriggerSource::State, hash(), 0, 1);
+        assert!(smr.process(qc).is_err());
+    }
+
+    #[test]
+    fn test_state_transition() {
+        let triggers = scenario()
+            .new_height(1)
+            .timer_propose()
+            .qc(VoteType::Prevote, Hash::new())
+            .qc(VoteType::Precommit, Hash::new())
+            .round(1)
+            .proposal(hash())
+            .qc(VoteType::Prevote, hash())
+            .qc(VoteType::Precommit, hash())
+            .build();
+
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        smr.set_transition_events(true);
+        let mut transitions = Vec::new();
+        for trigger in triggers {
+            for event in smr.process_sync(trigger).unwrap() {
+                if let SMREvent::StateTransition {
+                    height,
+                    round,
+                    from_step,
+                    to_step,
+                    reason,
+                } = event
+                {
+                    transitions.push((height.0, round.0, from_step, to_step, reason));
+                }
+            }
+        }
+        assert_eq!(
+            transitions,
+            vec![
+                (
+                    1,
+                    0,
+                    Step::Commit,
+                    Step::Propose,
+                    TransitionReason::NewHeight
+                ),
+                (
+                    1,
+                    0,
+                    Step::Propose,
+                    Step::Prevote,
+                    TransitionReason::Timeout
+                ),
+                (
+                    1,
+                    0,
+                    Step::Prevote,
+                    Step::Precommit,
+                    TransitionReason::PrevoteQC
+                ),
+                (
+                    1,
+                    1,
+                    Step::Precommit,
+                    Step::Propose,
+                    TransitionReason::PrecommitQC
+                ),
+                (
+                    1,
+                    1,
+                    Step::Propose,
+                    Step::Prevote,
+                    TransitionReason::Proposal
+                ),
+                (
+                    1,
+                    1,
+                    Step::Prevote,
+                    Step::Precommit,
+                    TransitionReason::PrevoteQC
+                ),
+                (
+                    1,
+                    1,
+                    Step::Precommit,
+                    Step::Commit,
+                    TransitionReason::PrecommitQC
+                ),
+            ]
+        );
+
+        // No transition event is thrown by default.
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let events = smr.process_sync(new_height(1, None)).unwrap();
+        assert!(matches!(events[..], [SMREvent::NewRoundInfo { .. }]));
+    }
+
+    #[test]
+    fn test_replay() {
+        let wal = scenario()
+            .new_height(1)
+            .proposal(hash())
+            .qc(VoteType::Prevote, hash())
+            .new_height(1)
+            .qc(VoteType::Precommit, hash())
+            .build();
+
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        let report = smr.replay(wal).unwrap();
+        assert_eq!(report.processed, 5);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].0, 3);
+        let indexes = report
+            .events
+            .iter()
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+        assert_eq!(indexes, vec![0, 1, 2, 2, 4]);
+        assert!(matches!(report.events[2].1, SMREvent::LockChanged { .. }));
+        assert!(matches!(report.events[4].1, SMREvent::Commit { .. }));
+        assert_eq!(report.view.step, Step::Commit);
+        assert_eq!(
+            report.view.last_commit.map(|commit| commit.block_hash),
+            Some(hash())
+        );
+        assert!(rx_state.try_next().is_none());
+    }
+
+    #[test]
+    fn test_lock_changed() {
+        let lock = |round| {
+            Some(Lock {
+                round: Round(round),
+                hash: hash(),
+            })
+        };
+        let lock_events = |rx_state: &mut Event| {
+            let mut events = Vec::new();
+            while let Some(event) = rx_state.try_next() {
+                if let SMREvent::LockChanged {
+                    old, new, cause, ..
+                } = event
+                {
+                    events.push((old, new, cause));
+                }
+            }
+            events
+        };
+
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 0, Step::Prevote);
+        let qc =
+            |hash, round| trigger(TriggerType::PrevoteQC, TriggerSource::State, hash, round, 1);
+        smr.process(qc(hash(), 0)).unwrap();
+        assert_eq!(
+            lock_events(&mut rx_state),
+            vec![(None, lock(0), LockCause::PrevoteQC)]
+        );
+
+        // A higher prevote QC replaces the lock, and a nil one removes it.
+        smr.process(qc(hash(), 1)).unwrap();
+        smr.step = Step::Prevote;
+        smr.process(qc(Hash::new(), 2)).unwrap();
+        assert_eq!(
+            lock_events(&mut rx_state),
+            vec![
+                (lock(0), lock(1), LockCause::PrevoteQC),
+                (lock(1), None, LockCause::NilPrevoteQC),
+            ]
+        );
+
+        // A new height removes the lock.
+        smr.update_polc(QcValue::Block(hash()), Round(1)).unwrap();
+        lock_events(&mut rx_state);
+        smr.process(new_height(2, None)).unwrap();
+        assert_eq!(
+            lock_events(&mut rx_state),
+            vec![(lock(1), None, LockCause::NewHeight)]
+        );
+    }
+
+    #[test]
+    fn test_export_import_state() {
+        let (mut smr, _rx_state, _rx_timer) = state_machine(3, 2, Step::Precommit);
+        smr.update_polc(QcValue::Block(hash()), Round(1)).unwrap();
+        let state = smr.export_state();
+        let json = serde_json::to_string(&state).unwrap();
+
+        // The triggers buffered before the import are not replayed into the imported state.
+        let (mut restored, mut rx_state, _rx_timer) = StateMachine::new();
+        restored.process(new_height(1, None)).unwrap();
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 2);
+        restored.process(proposal).unwrap();
+        assert_eq!(restored.future.len(), 1);
+        while rx_state.try_next().is_some() {}
+        restored
+            .import_state(serde_json::from_str(&json).unwrap())
+            .unwrap();
+        assert!(restored.future.is_empty());
+        assert_eq!(restored.export_state(), state);
+        assert_eq!(restored.view_handle().load().height, Height(3));
+        assert!(rx_state.try_next().is_none());
+
+        let mut future_lock = state.clone();
+        future_lock.round = Round(0);
+        assert!(restored.import_state(future_lock).is_err());
+        let mut mismatch = state;
+        mismatch.block_hash = Bytes::from(vec![9u8]);
+        assert!(restored.import_state(mismatch).is_err());
+        assert_eq!(restored.round(), Round(2));
+    }
+
+    #[test]
+    fn test_commit_seq() {
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        for height in 1..=2 {
+            smr.process(new_height(height, None)).unwrap();
+            commit_in_round_zero(&mut smr, height, hash());
+        }
+        let seqs = std::iter::from_fn(|| rx_state.try_next())
+            .filter_map(|event| match event {
+                SMREvent::Commit { commit_seq, .. } => Some(commit_seq),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, vec![1, 2]);
+
+        // The sequence goes on from the imported state after a restart.
+        let (mut restored, mut rx_state, _rx_timer) = StateMachine::new();
+        restored.import_state(smr.export_state()).unwrap();
+        restored.process(new_height(3, None)).unwrap();
+        commit_in_round_zero(&mut restored, 3, hash());
+        assert_eq!(restored.commit_seq(), 3);
+        assert!(std::iter::from_fn(|| rx_state.try_next())
+            .any(|event| matches!(event, SMREvent::Commit { commit_seq: 3, .. })));
+    }
+
+    #[test]
+    fn test_peer_address() {
+        let (mut smr, _rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        let mut rx_error = smr.subscribe_errors();
+
+        let malformed = Bytes::from(vec![9u8; ADDRESS_LEN - 1]);
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        assert_eq!(
+            smr.process_from(proposal.clone(), Some(malformed.clone())),
+            Err(ConsensusError::InvalidAddress)
+        );
+        let event = rx_error.try_next().unwrap();
+        assert_eq!(event.peer, Some(malformed.clone()));
+        assert_eq!(smr.step, Step::Propose);
+
+        // The address length is configurable.
+        smr.set_address_len(ADDRESS_LEN - 1);
+        smr.process_from(proposal, Some(malformed)).unwrap();
+        assert_eq!(smr.step, Step::Prevote);
+    }
+
+    #[test]
+    fn test_lock_expiry() {
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 0, Step::Propose);
+        assert!(smr.set_lock_expiry_rounds(Some(0)).is_err());
+        smr.set_strict_commit(true);
+        assert!(smr.set_lock_expiry_rounds(Some(2)).is_err());
+        smr.set_strict_commit(false);
+        smr.set_lock_expiry_rounds(Some(2)).unwrap();
+
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        let prevote_qc = trigger(TriggerType::PrevoteQC, TriggerSource::State, hash(), 0, 1);
+        smr.process(proposal).unwrap();
+        smr.process(prevote_qc).unwrap();
+        assert_eq!(smr.lock.as_ref().map(|lock| lock.round), Some(Round(0)));
+        while rx_state.try_next().is_some() {}
+
+        // The lock is kept in round 1.
+        let nil = |round| {
+            trigger(
+                TriggerType::PrecommitQC,
+                TriggerSource::State,
+                Hash::new(),
+                round,
+                1,
+            )
+        };
+        smr.process(nil(0)).unwrap();
+        match rx_state.try_next() {
+            Some(SMREvent::NewRoundInfo { lock_round, .. }) => {
+                assert_eq!(lock_round, Some(Round(0)))
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // The lock expires in round 2.
+        smr.process(nil(1)).unwrap();
+        assert_eq!(
+            rx_state.try_next(),
+            Some(SMREvent::LockChanged {
+                height: Height(1),
+                round: Round(1),
+                old: Some(Lock {
+                    round: Round(0),
+                    hash: hash(),
+                }),
+                new: None,
+                cause: LockCause::Expired,
+            })
+        );
+        match rx_state.try_next() {
+            Some(SMREvent::LockExpired {
+                round,
+                lock_round,
+                lock_proposal,
+                ..
+            }) => {
+                assert_eq!(round, Round(2));
+                assert_eq!(lock_round, Round(0));
+                assert_eq!(lock_proposal, hash());
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        match rx_state.try_next() {
+            Some(SMREvent::NewRoundInfo { lock_round, .. }) => assert_eq!(lock_round, None),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(smr.lock.is_none());
+        assert!(smr.block_hash.is_empty());
+
+        // The lock expires on the round changes by choke QCs too.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 1, Step::Propose);
+        smr.set_lock_expiry_rounds(Some(2)).unwrap();
+        smr.adopt_lock(Round(0), hash(), Bytes::from(vec![7u8]))
+            .unwrap();
+        while rx_state.try_next().is_some() {}
+        smr.process(SMRTrigger::continue_round(Round(2), Height(1)))
+            .unwrap();
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::LockChanged {
+                cause: LockCause::Expired,
+                ..
+            })
+        ));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::LockExpired {
+                round: Round(2),
+                ..
+            })
+        ));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo {
+                lock_round: None,
+                ..
+            })
+        ));
+        assert!(smr.lock.is_none());
+
+        // A prevote QC of a higher round expires the old lock and keeps its own lock, even with a
+        // one round expiry.
+        let (mut smr, mut rx_state, _rx_timer) = state_machine(1, 1, Step::Propose);
+        smr.set_lock_expiry_rounds(Some(1)).unwrap();
+        smr.adopt_lock(Round(0), hash(), Bytes::from(vec![7u8]))
+            .unwrap();
+        while rx_state.try_next().is_some() {}
+        let other = Bytes::from(vec![3u8]);
+        smr.process(trigger(
+            TriggerType::PrevoteQC,
+            TriggerSource::State,
+            other.clone(),
+            3,
+            1,
+        ))
+        .unwrap();
+        let mut expired = Vec::new();
+        while let Some(event) = rx_state.try_next() {
+            match event {
+                SMREvent::LockExpired { lock_round, .. } => expired.push(lock_round),
+                SMREvent::PrecommitVote {
+                    round,
+                    block_hash,
+                    lock_round,
+                    ..
+                } => {
+                    assert_eq!((round, block_hash), (Round(4), other.clone()));
+                    assert_eq!(lock_round, Some(Round(3)));
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(expired, vec![Round(0)]);
+        assert_eq!(
+            smr.lock,
+            Some(Lock {
+                round: Round(3),
+                hash: other,
+            })
+        );
+        assert_eq!(smr.step, Step::Precommit);
+    }
+
+    #[test]
+    fn test_commit_budget() {
+        let commit = |smr: &mut StateMachine, height| {
+            scenario()
+                .new_height(height)
+                .proposal(hash())
+                .qc(VoteType::Prevote, hash())
+                .qc(VoteType::Precommit, hash())
+                .run(smr)
+                .unwrap();
+        };
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        commit(&mut smr, 1);
+        assert_eq!(smr.commit_compliance(), None);
+
+        smr.set_commit_budget(Some(Duration::from_secs(3600)));
+        commit(&mut smr, 2);
+        smr.set_commit_budget(Some(Duration::ZERO));
+        commit(&mut smr, 3);
+        commit(&mut smr, 4);
+        assert_eq!(smr.commit_compliance(), Some(0.0));
+
+        let mut breaches = Vec::new();
+        while let Some(event) = rx_state.try_next() {
+            if let SMREvent::SloBreach {
+                height, budget_ms, ..
+            } = event
+            {
+                breaches.push((height, budget_ms));
+            }
+        }
+        assert_eq!(breaches, vec![(Height(3), 0), (Height(4), 0)]);
+
+        // The compliance is computed over the heights since the budget is set.
+        smr.set_commit_budget(Some(Duration::from_secs(3600)));
+        commit(&mut smr, 5);
+        commit(&mut smr, 6);
+        assert_eq!(smr.commit_compliance(), Some(1.0));
+        smr.set_commit_budget(None);
+        assert_eq!(smr.commit_compliance(), None);
+    }
+
+    #[test]
+    fn test_future_buffer() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        smr.process(new_height(1, None)).unwrap();
+
+        // A proposal of a higher round is replayed on reaching the round.
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 1, 1);
+        assert!(smr.process_sync(proposal).unwrap().is_empty());
+        smr.process_sync(trigger(
+            TriggerType::Proposal,
+            TriggerSource::Timer,
+            Hash::new(),
+            0,
+            1,
+        ))
+        .unwrap();
+        smr.process_sync(trigger(
+            TriggerType::PrevoteQC,
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            1,
+        ))
+        .unwrap();
+        let nil = trigger(
+            TriggerType::PrecommitQC,
+            TriggerSource::State,
+            Hash::new(),
+            0,
+            1,
+        );
+        let events = smr.process_sync(nil).unwrap();
+        assert!(matches!(
+            events[..],
+            [
+                SMREvent::NewRoundInfo {
+                    round: Round(1),
+                    ..
+                },
+                SMREvent::PrevoteVote {
+                    round: Round(1),
+                    ..
+                }
+            ]
+        ));
+        assert_eq!(smr.block_hash, hash());
+
+        // The proposal and the QCs of the next height are replayed on entering it.
+        for trigger_type in [
+            TriggerType::Proposal,
+            TriggerType::PrevoteQC,
+            TriggerType::PrecommitQC,
+        ] {
+            smr.process(trigger(trigger_type, TriggerSource::State, hash(), 0, 2))
+                .unwrap();
+        }
+        let events = smr.process_sync(new_height(2, None)).unwrap();
+        assert!(events.contains(&SMREvent::Commit {
+            height: Height(2),
+            round: Round(0),
+            block_hash: hash(),
+            polc_round: Some(Round(0)),
+            commit_seq: 1,
+        }));
+        assert_eq!((smr.height, smr.step.clone()), (Height(2), Step::Commit));
+        assert!(smr.future.is_empty());
+
+        // The rounds too far ahead are not buffered.
+        let proposal = |round: u64, height: u64| {
+            trigger(
+                TriggerType::Proposal,
+                TriggerSource::State,
+                hash(),
+                round,
+                height,
+            )
+        };
+        for round in 1..=FUTURE_TRIGGERS as u64 {
+            smr.process(proposal(round + FUTURE_ROUNDS, 2)).unwrap();
+        }
+        assert!(smr.future.is_empty());
+
+        // The buffer is bounded, and the furthest triggers are evicted for the nearer ones.
+        for _ in 0..FUTURE_TRIGGERS {
+            smr.process(proposal(FUTURE_ROUNDS, 2)).unwrap();
+        }
+        assert_eq!(
+            smr.process_with_outcome(proposal(FUTURE_ROUNDS, 2)),
+            Ok(ProcessOutcome::IgnoredFuture {
+                height: Height(2),
+                round: Round(FUTURE_ROUNDS),
+            })
+        );
+        assert_eq!(
+            smr.process_with_outcome(proposal(0, 3)),
+            Ok(ProcessOutcome::BufferedFuture {
+                height: Height(3),
+                round: Round(0),
+            })
+        );
+        assert_eq!(smr.future.len(), FUTURE_TRIGGERS);
+        // The triggers of the past heights are dropped.
+        smr.process(new_height(3, None)).unwrap();
+        assert!(smr.future.is_empty());
+    }
+
+    #[test]
+    fn test_test_utils() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        smr.process(new_height(1, None)).unwrap();
+        smr.set_round(Round(2)).unwrap();
+        smr.set_step(Step::Precommit).unwrap();
+        smr.set_lock(Height(1), Round(2), hash(), hash()).unwrap();
+        smr.assert_view(Height(1), Round(2), Step::Precommit);
+        smr.assert_state(&SMRState {
+            height: Height(1),
+            round: Round(2),
+            step: Step::Precommit,
+            block_hash: hash(),
+            lock: Some(Lock {
+                round: Round(2),
+                hash: hash(),
+            }),
+            commit_seq: 0,
+            params_version: 0,
+            max_round: None,
+        });
+        assert_eq!(smr.view_handle().load().round, Round(2));
+
+        // The lock stays consistent with the round and the step.
+        assert!(smr.set_step(Step::Prevote).is_err());
+        assert!(smr.set_round(Round(1)).is_err());
+        smr.set_round(Round(3)).unwrap();
+        smr.set_step(Step::Propose).unwrap();
+        smr.assert_view(Height(1), Round(3), Step::Propose);
+    }
+
+    #[test]
+    fn test_process_outcome() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        assert_eq!(
+            smr.process_with_outcome(new_height(1, None)),
+            Ok(ProcessOutcome::Applied)
+        );
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        assert_eq!(
+            smr.process_with_outcome(proposal.clone()),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            smr.process_with_outcome(proposal),
+            Ok(ProcessOutcome::IgnoredStale {
+                height: Height(1),
+                round: Round(0),
+            })
+        );
+
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 2, 1);
+        assert_eq!(
+            smr.process_with_outcome(proposal),
+            Ok(ProcessOutcome::BufferedFuture {
+                height: Height(1),
+                round: Round(2),
+            })
+        );
+        let qc = trigger(TriggerType::PrevoteQC, TriggerSource::State, hash(), 0, 2);
+        assert_eq!(
+            smr.process_with_outcome(qc),
+            Ok(ProcessOutcome::BufferedFuture {
+                height: Height(2),
+                round: Round(0),
+            })
+        );
+        let qc = trigger(TriggerType::PrevoteQC, TriggerSource::State, hash(), 0, 3);
+        assert_eq!(
+            smr.process_with_outcome(qc),
+            Ok(ProcessOutcome::IgnoredFuture {
+                height: Height(3),
+                round: Round(0),
+            })
+        );
+        assert!(smr.process_with_outcome(new_height(1, None)).is_err());
+    }
+
+    #[test]
+    fn test_on_transition() {
+        let records = Arc::new(Mutex::new(Vec::<TransitionRecord>::new()));
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let recorded = Arc::clone(&records);
+        smr.on_transition(Box::new(move |record| {
+            recorded.lock().unwrap().push(record.clone())
+        }));
+
+        smr.process(new_height(1, None)).unwrap();
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 1);
+        smr.process(proposal.clone()).unwrap();
+        // An ignored or a rejected trigger is not a transition.
+        smr.process(proposal.clone()).unwrap();
+        assert!(smr.process(new_height(1, None)).is_err());
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].after.height, Height(1));
+        assert!(matches!(
+            records[0].events[..],
+            [SMREvent::NewRoundInfo { .. }, ..]
+        ));
+        assert_eq!(records[1].trigger, proposal);
+        assert_eq!(records[1].before, records[0].after);
+        assert_eq!(
+            records[1].after,
+            SMRState {
+                height: Height(1),
+                round: Round(0),
+                step: Step::Prevote,
+                block_hash: hash(),
+                lock: None,
+                commit_seq: 0,
+                params_version: 0,
+                max_round: None,
+            }
+        );
+        assert!(matches!(
+            records[1].events[..],
+            [SMREvent::PrevoteVote { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_audit_stream() {
+        let (mut smr, _rx_state, _rx_timer, mut rx_audit) = StateMachine::new_with_audit();
+        smr.process(new_height(1, None)).unwrap();
+        let stale = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 0);
+        smr.process(stale.clone()).unwrap();
+        smr.process(new_height(1, None)).unwrap_err();
+
+        let record = rx_audit.try_next().unwrap();
+        assert_eq!(
+            (record.before.height, record.after.height),
+            (Height(0), Height(1))
+        );
+        assert!(matches!(
+            record.events[..],
+            [SMREvent::NewRoundInfo { .. }, ..]
+        ));
+        assert_eq!(record.rejection, None);
+
+        // The ignored trigger is recorded without a transition.
+        let record = rx_audit.try_next().unwrap();
+        assert_eq!(record.trigger, stale);
+        assert_eq!(record.before, record.after);
+        assert!(record.events.is_empty());
+
+        let record = rx_audit.try_next().unwrap();
+        assert_eq!(record.trigger, new_height(1, None));
+        assert!(record.rejection.unwrap().contains("Delayed status"));
+        assert!(rx_audit.try_next().is_none());
+
+        // Dropping the audit stream does not affect processing.
+        drop(rx_audit);
+        smr.process(trigger(
+            TriggerType::Proposal,
+            TriggerSource::State,
+            hash(),
+            0,
+            1,
+        ))
+        .unwrap();
+        assert_eq!(smr.step, Step::Prevote);
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        assert!(!smr.check_watchdog().unwrap());
+
+        smr.set_watchdog(Some(Duration::from_secs(3600)));
+        smr.process(new_height(1, None)).unwrap();
+        assert!(!smr.check_watchdog().unwrap());
+
+        smr.set_watchdog(Some(Duration::ZERO));
+        smr.process(new_height(2, None)).unwrap();
+        assert!(smr.check_watchdog().unwrap());
+        // Each stall is reported once.
+        assert!(!smr.check_watchdog().unwrap());
+
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo { .. })
+        ));
+        assert!(matches!(
+            rx_state.try_next(),
+            Some(SMREvent::NewRoundInfo { .. })
+        ));
+        match rx_state.try_next() {
+            Some(SMREvent::WatchdogReport {
+                height,
+                step,
+                recent_events,
+                ..
+            }) => {
+                assert_eq!(height, Height(2));
+                assert_eq!(step, Step::Propose);
+                assert_eq!(recent_events.len(), 1);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // A transition rearms the watchdog.
+        let proposal = trigger(TriggerType::Proposal, TriggerSource::State, hash(), 0, 2);
+        smr.process(proposal).unwrap();
+        assert!(smr.check_watchdog().unwrap());
+    }
+
+    #[test]
+    fn test_view() {
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let handle = smr.view_handle();
+        assert_eq!(*handle.load(), SmrView::default());
+
+        smr.process(new_height(1, None)).unwrap();
+        let view = handle.load();
+        assert_eq!((view.height, view.step.clone()), (Height(1), Step::Propose));
+
+        // Readers keep their view while the SMR moves on.
+        commit_in_round_zero(&mut smr, 1, hash());
+        assert_eq!(view.step, Step::Propose);
+        let view = handle.load();
+        assert_eq!(view.step, Step::Commit);
+        assert_eq!(
+            view.last_commit.as_ref().map(|commit| commit.height),
+            Some(Height(1))
+        );
+        assert_eq!(view.lock.as_ref().map(|lock| lock.round), Some(Round(0)));
+
+        // A rejected trigger does not change the view.
+        assert!(smr.process(new_height(1, None)).is_err());
+        assert!(Arc::ptr_eq(&view, &handle.load()));
+    }
 
     #[test]
     fn test_xor() {
@@ -462,4 +4474,39 @@ mod test {
         let right: Option<u64> = None;
         assert!(!left.is_empty().bitxor(&right.is_none()));
     }
+
+    #[test]
+    fn test_fixed_hash() {
+        use crate::smr::smr_types::{
+            GenericQcValue, GenericSMREvent, GenericSMRStatus, GenericSMRTrigger,
+        };
+        use crate::types::ConsensusHash;
+
+        let (mut smr, mut rx_state, _rx_timer) = GenericStateMachine::<[u8; 32]>::new();
+        let hash = [7u8; 32];
+        smr.process(GenericSMRTrigger {
+            trigger_type: GenericTriggerType::NewHeight(Box::new(GenericSMRStatus::new(Height(1)))),
+            source: TriggerSource::State,
+            hash: <[u8; 32]>::empty(),
+            lock_round: None,
+            round: Round(0),
+            height: Height(0),
+        })
+        .unwrap();
+        smr.process(GenericSMRTrigger::proposal(hash, None, Round(0), Height(1)))
+            .unwrap();
+        for vote_type in [VoteType::Prevote, VoteType::Precommit] {
+            let qc = GenericSMRTrigger::qc(vote_type, hash.into(), Round(0), Height(1));
+            smr.process(qc).unwrap();
+        }
+        assert_eq!(smr.block_hash(), &hash);
+        assert_eq!(GenericQcValue::from(hash).into_hash(), hash);
+
+        let commit = std::iter::from_fn(|| rx_state.try_next()).find_map(|event| match event {
+            GenericSMREvent::Commit { block_hash, .. } => Some(block_hash),
+            _ => None,
+        });
+        assert_eq!(commit, Some(hash));
+        assert!(<[u8; 32]>::empty().is_empty() && !hash.is_empty());
+    }
 }
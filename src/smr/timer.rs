@@ -0,0 +1,532 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use tokio::time::Instant;
+
+use crate::error::ConsensusError;
+use crate::smr::smr_types::{SMREvent, SMRTrigger, Step};
+use crate::smr::Event;
+use crate::types::{ConsensusResult, DurationConfig, Height, Round, TimeoutConfig};
+
+/// The denominator of the ratios of `DurationConfig`, so that a ratio of 10 is a timeout of one
+/// height interval.
+pub const RATIO_DENOMINATOR: u64 = 10;
+
+/// The clock of the timer, which is abstracted so that the tests can advance it by hand.
+pub trait Clock: Send + Sync {
+    /// Return the current instant.
+    fn now(&self) -> Instant;
+
+    /// Return a future completing at the deadline.
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+}
+
+/// The default clock of the timer backed by the tokio runtime.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// A timer consuming the timer events of the SMR. Each propose, prevote, precommit and brake event
+/// sets a timer of the step, and a fired timer sends the timeout trigger of the step to the driver,
+/// which feeds it to the SMR. The timeout of a step is the height interval scaled by the ratio of
+/// the step, unless the timeouts are set in absolute durations, and all of them are updated by the
+/// new round info events. With a backoff, the timeouts grow with the round, so that the
+/// validators failing a round repeatedly wait longer each time. The brake timer repeats, since the
+/// SMR throws the brake event again on every brake timeout.
+///
+/// The timers are keyed by the height, round and step. An event of a later step cancels the timers
+/// of the earlier steps, which the SMR has left, so that they do not fire stale timeouts. The
+/// events already queued together are handled as a batch, and a step left by a later event of the
+/// batch is never armed. This is the fast path of round 0: a proposal received before its height is
+/// buffered by the SMR and replayed on entering the height, so the propose timer is skipped.
+#[rustfmt::skip]
+pub struct Timer<C = TokioClock> {
+    clock:    C,
+    interval: u64,
+    config:   DurationConfig,
+    timeouts: Option<TimeoutConfig>,
+    backoff:  u64,
+    rx_timer: Event,
+    triggers: UnboundedSender<SMRTrigger>,
+    pending:  BTreeMap<(Height, Round, Step), (Instant, SMRTrigger)>,
+}
+
+impl Timer {
+    /// Create a timer from the timer event stream, the height interval in milliseconds and the
+    /// timeout configuration. The timeout triggers are sent to the returned receiver.
+    pub fn new(
+        rx_timer: Event,
+        interval: u64,
+        config: DurationConfig,
+    ) -> ConsensusResult<(Self, UnboundedReceiver<SMRTrigger>)> {
+        Timer::with_clock(rx_timer, interval, config, TokioClock)
+    }
+}
+
+impl<C: Clock> Timer<C> {
+    /// Create a timer as `Timer::new` on the given clock.
+    pub fn with_clock(
+        rx_timer: Event,
+        interval: u64,
+        config: DurationConfig,
+        clock: C,
+    ) -> ConsensusResult<(Self, UnboundedReceiver<SMRTrigger>)> {
+        if interval == 0 {
+            return Err(ConsensusError::TimerErr("Zero height interval".to_string()));
+        }
+        config.validate()?;
+
+        let (tx, rx) = unbounded();
+        let timer = Timer {
+            clock,
+            interval,
+            config,
+            timeouts: None,
+            backoff: 0,
+            rx_timer,
+            triggers: tx,
+            pending: BTreeMap::new(),
+        };
+        Ok((timer, rx))
+    }
+
+    /// Set the backoff in percent of the base timeout added per round, so that the timeout of a
+    /// step in round `r` is `timeout * (1 + r * backoff / 100)`. No backoff by default.
+    pub fn set_backoff(&mut self, percent: u64) {
+        self.backoff = percent;
+    }
+
+    /// Set the timeouts in absolute durations, which take precedence over the ratios until a new
+    /// round info event carries a new ratio configuration.
+    pub fn set_timeouts(&mut self, timeouts: TimeoutConfig) -> ConsensusResult<()> {
+        timeouts.validate()?;
+        self.timeouts = Some(timeouts);
+        Ok(())
+    }
+
+    /// Return the timeout of the step in the round, or `None` if the step does not time out.
+    pub fn timeout(&self, step: &Step, round: Round) -> Option<Duration> {
+        let base = match (&self.timeouts, step) {
+            (Some(timeouts), Step::Propose) => timeouts.propose,
+            (Some(timeouts), Step::Prevote) => timeouts.prevote,
+            (Some(timeouts), Step::Precommit) => timeouts.precommit,
+            (Some(timeouts), Step::Brake) => timeouts.brake,
+            (None, Step::Propose) => self.ratio_timeout(self.config.propose_ratio),
+            (None, Step::Prevote) => self.ratio_timeout(self.config.prevote_ratio),
+            (None, Step::Precommit) => self.ratio_timeout(self.config.precommit_ratio),
+            (None, Step::Brake) => self.ratio_timeout(self.config.brake_ratio),
+            _ => return None,
+        };
+        let scale = 100u64.saturating_add(round.0.saturating_mul(self.backoff));
+        let nanos = base.as_nanos().saturating_mul(scale.into()) / 100;
+        Some(Duration::from_nanos(
+            u64::try_from(nanos).unwrap_or(u64::MAX),
+        ))
+    }
+
+    fn ratio_timeout(&self, ratio: u64) -> Duration {
+        Duration::from_millis(self.interval.saturating_mul(ratio) / RATIO_DENOMINATOR)
+    }
+
+    /// Run the timer. This returns after the timer event stream ends or the trigger receiver is
+    /// dropped.
+    pub async fn run(mut self) {
+        loop {
+            let deadline = self.pending.values().map(|(deadline, _)| *deadline).min();
+            tokio::select! {
+                event = self.rx_timer.next() => match event {
+                    Some(event) => if !self.set_timers(event) {
+                        return;
+                    },
+                    None => return,
+                },
+                _ = self.clock.sleep_until(deadline.unwrap_or_else(|| self.clock.now())),
+                    if deadline.is_some() => {
+                    if !self.fire() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Set the timers of the event and the ones queued after it. Return `false` on the stop event.
+    fn set_timers(&mut self, event: SMREvent) -> bool {
+        let mut events = vec![event];
+        while let Some(event) = self.rx_timer.try_next() {
+            events.push(event);
+        }
+
+        let keys = events.iter().map(timer_key).collect::<Vec<_>>();
+        for (index, event) in events.into_iter().enumerate() {
+            if event == SMREvent::Stop {
+                return false;
+            }
+            let left = keys[index]
+                .as_ref()
+                .is_some_and(|key| keys[index + 1..].iter().flatten().any(|later| later > key));
+            self.set_timer(event, !left);
+        }
+        true
+    }
+
+    /// Cancel the timers of the steps before the one entered by the event, and set the timer of
+    /// the entered step if `arm`.
+    fn set_timer(&mut self, event: SMREvent, arm: bool) {
+        let key = match timer_key(&event) {
+            Some(key) => key,
+            None => return,
+        };
+        if let SMREvent::NewRoundInfo {
+            new_interval,
+            new_config,
+            new_timeouts,
+            ..
+        } = event
+        {
+            if let Some(interval) = new_interval {
+                self.interval = interval;
+            }
+            if let Some(config) = new_config {
+                self.config = *config;
+                self.timeouts = None;
+            }
+            if let Some(timeouts) = new_timeouts {
+                self.timeouts = Some(*timeouts);
+            }
+        }
+
+        let (height, round, step) = key.clone();
+        let cancelled = self.pending.len();
+        self.pending = self.pending.split_off(&key);
+        if cancelled > self.pending.len() {
+            smr_debug!(
+                "Tendermint: timer cancel {} timers before {:?} of height {}, round {}",
+                cancelled - self.pending.len(),
+                step,
+                height,
+                round
+            );
+        }
+
+        if !arm {
+            smr_debug!(
+                "Tendermint: timer skip {:?} of height {}, round {}, left already",
+                step,
+                height,
+                round
+            );
+            return;
+        }
+        let timeout = match self.timeout(&step, round) {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let trigger = SMRTrigger::timeout(step, round, height).expect("step times out");
+        smr_debug!(
+            "Tendermint: timer set {:?} of height {}, round {} to {:?}",
+            trigger.trigger_type,
+            height,
+            round,
+            timeout
+        );
+        self.pending
+            .insert(key, (self.clock.now() + timeout, trigger));
+    }
+
+    /// Send the triggers of the expired timers. Return `false` if the trigger receiver is dropped.
+    fn fire(&mut self) -> bool {
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        self.pending.retain(|_, (deadline, trigger)| {
+            if *deadline > now {
+                return true;
+            }
+            expired.push(trigger.clone());
+            false
+        });
+
+        expired
+            .into_iter()
+            .all(|trigger| self.triggers.unbounded_send(trigger).is_ok())
+    }
+}
+
+/// Return the height, round and step entered by a timer event.
+fn timer_key(event: &SMREvent) -> Option<(Height, Round, Step)> {
+    match event {
+        SMREvent::NewRoundInfo { height, round, .. } => Some((*height, *round, Step::Propose)),
+        SMREvent::PrevoteVote { height, round, .. } => Some((*height, *round, Step::Prevote)),
+        SMREvent::PrecommitVote { height, round, .. } => Some((*height, *round, Step::Precommit)),
+        SMREvent::Brake { height, round, .. } => Some((*height, *round, Step::Brake)),
+        SMREvent::Commit { height, round, .. } => Some((*height, *round, Step::Commit)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::StreamExt;
+
+    use crate::smr::smr_types::{SMRStatus, SMRTrigger, Step, TriggerSource};
+    use crate::smr::state_machine::StateMachine;
+    use crate::testing::clock::MockClock;
+    use crate::types::{DurationConfig, Height, Round, TimeoutConfig, VoteType};
+
+    use super::Timer;
+
+    #[tokio::test]
+    async fn test_timer() {
+        let config = DurationConfig::new(3, 2, 1, 1);
+        let (_smr, _rx_state, rx_timer) = StateMachine::new();
+        assert!(Timer::new(rx_timer, 0, config.clone()).is_err());
+        let (_smr, _rx_state, rx_timer) = StateMachine::new();
+        assert!(Timer::new(rx_timer, 100, DurationConfig::default()).is_err());
+
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        let (mut timer, mut rx_trigger) = Timer::new(rx_timer, 100, config).unwrap();
+        let timeout = |timer: &Timer, step, round| timer.timeout(&step, Round(round));
+        assert_eq!(
+            timeout(&timer, Step::Propose, 0),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(
+            timeout(&timer, Step::Propose, 3),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(
+            timeout(&timer, Step::Brake, 0),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(timeout(&timer, Step::Commit, 0), None);
+
+        // The absolute timeouts take precedence over the ratios.
+        let millis = Duration::from_millis;
+        let mut timeouts = TimeoutConfig::new(millis(40), millis(20), millis(20), millis(15));
+        timeouts.prevote = millis(50);
+        assert!(timer.set_timeouts(timeouts.clone()).is_err());
+        timeouts.prevote = millis(30);
+        timer.set_timeouts(timeouts).unwrap();
+        assert_eq!(timeout(&timer, Step::Propose, 0), Some(millis(40)));
+        assert_eq!(timeout(&timer, Step::Prevote, 0), Some(millis(30)));
+        assert_eq!(timeout(&timer, Step::Brake, 0), Some(millis(15)));
+        assert_eq!(timeout(&timer, Step::Commit, 0), None);
+        timer.timeouts = None;
+
+        // The timeouts grow by half of the base timeout per round.
+        timer.set_backoff(50);
+        assert_eq!(
+            timeout(&timer, Step::Propose, 0),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(
+            timeout(&timer, Step::Prevote, 1),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(
+            timeout(&timer, Step::Precommit, 4),
+            Some(Duration::from_millis(30))
+        );
+        assert!(timeout(&timer, Step::Propose, u64::MAX).is_some());
+        let handle = tokio::spawn(timer.run());
+
+        // Nothing but the timer drives the SMR through the timeouts of a round.
+        smr.process(SMRStatus::new(Height(1)).into()).unwrap();
+        let drive = async {
+            while smr.round() == Round(0) {
+                let trigger = rx_trigger.next().await.unwrap();
+                assert_eq!(trigger.source, TriggerSource::Timer);
+                smr.process(trigger).unwrap();
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), drive)
+            .await
+            .unwrap();
+        assert_eq!((smr.height(), smr.round()), (Height(1), Round(1)));
+
+        smr.shutdown().unwrap();
+        handle.await.unwrap();
+    }
+
+    /// Let the timer task handle the sent events and the advanced clock.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let clock = MockClock::new();
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        let config = DurationConfig::new(3, 2, 1, 1);
+        let (timer, mut rx_trigger) =
+            Timer::with_clock(rx_timer, 100, config, clock.clone()).unwrap();
+        let handle = tokio::spawn(timer.run());
+
+        smr.process(SMRStatus::new(Height(1)).into()).unwrap();
+        settle().await;
+        clock.advance(Duration::from_millis(29));
+        settle().await;
+        assert!(rx_trigger.try_next().is_err());
+
+        clock.advance(Duration::from_millis(1));
+        settle().await;
+        let timeout = rx_trigger.try_next().unwrap().unwrap();
+        assert_eq!(
+            timeout,
+            SMRTrigger::timeout(Step::Propose, Round(0), Height(1)).unwrap()
+        );
+
+        // The prevote timer is set from the time of the prevote event.
+        smr.process(timeout).unwrap();
+        settle().await;
+        clock.advance(Duration::from_millis(19));
+        settle().await;
+        assert!(rx_trigger.try_next().is_err());
+        clock.advance(Duration::from_millis(1));
+        settle().await;
+        let timeout = rx_trigger.try_next().unwrap().unwrap();
+        assert_eq!(
+            timeout,
+            SMRTrigger::timeout(Step::Prevote, Round(0), Height(1)).unwrap()
+        );
+
+        smr.shutdown().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_timer() {
+        let clock = MockClock::new();
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        let config = DurationConfig::new(3, 2, 1, 1);
+        let (timer, mut rx_trigger) =
+            Timer::with_clock(rx_timer, 100, config, clock.clone()).unwrap();
+        let handle = tokio::spawn(timer.run());
+
+        // The proposal arriving in time cancels the propose timer.
+        smr.process(SMRStatus::new(Height(1)).into()).unwrap();
+        let hash = Bytes::from(vec![1u8]);
+        smr.process(SMRTrigger::proposal(
+            hash.clone(),
+            None,
+            Round(0),
+            Height(1),
+        ))
+        .unwrap();
+        settle().await;
+        clock.advance(Duration::from_millis(30));
+        settle().await;
+        let timeout = rx_trigger.try_next().unwrap().unwrap();
+        assert_eq!(
+            timeout,
+            SMRTrigger::timeout(Step::Prevote, Round(0), Height(1)).unwrap()
+        );
+        assert!(rx_trigger.try_next().is_err());
+
+        // The commit cancels the precommit timer.
+        let qc = |vote_type, hash: &Bytes| {
+            SMRTrigger::qc(vote_type, hash.clone().into(), Round(0), Height(1))
+        };
+        smr.process(qc(VoteType::Prevote, &hash)).unwrap();
+        smr.process(qc(VoteType::Precommit, &hash)).unwrap();
+        settle().await;
+        clock.advance(Duration::from_millis(100));
+        settle().await;
+        assert!(rx_trigger.try_next().is_err());
+
+        smr.shutdown().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_fast_path() {
+        let config = DurationConfig::new(3, 2, 1, 1);
+        let hash = Bytes::from(vec![1u8]);
+        let steps = |timer: &Timer| {
+            let keys = timer.pending.keys();
+            keys.map(|(_, _, step)| step.clone()).collect::<Vec<_>>()
+        };
+
+        // Without a proposal, the propose timer is armed.
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        let (mut timer, _rx_trigger) = Timer::new(rx_timer, 100, config.clone()).unwrap();
+        smr.process(SMRStatus::new(Height(1)).into()).unwrap();
+        let event = timer.rx_timer.try_next().unwrap();
+        assert!(timer.set_timers(event));
+        assert_eq!(steps(&timer), vec![Step::Propose]);
+
+        // The proposal of round 0 buffered before the height skips the propose timer.
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        let (mut timer, _rx_trigger) = Timer::new(rx_timer, 100, config).unwrap();
+        smr.process(SMRTrigger::proposal(hash, None, Round(0), Height(1)))
+            .unwrap();
+        smr.process(SMRStatus::new(Height(1)).into()).unwrap();
+        let event = timer.rx_timer.try_next().unwrap();
+        assert!(timer.set_timers(event));
+        assert_eq!(steps(&timer), vec![Step::Prevote]);
+
+        smr.shutdown().unwrap();
+        let event = timer.rx_timer.try_next().unwrap();
+        assert!(!timer.set_timers(event));
+    }
+
+    #[tokio::test]
+    async fn test_brake_timer() {
+        let clock = MockClock::new();
+        let (mut smr, _rx_state, rx_timer) = StateMachine::new();
+        smr.set_brake(true);
+        let config = DurationConfig::new(3, 2, 1, 1);
+        let (timer, mut rx_trigger) =
+            Timer::with_clock(rx_timer, 100, config, clock.clone()).unwrap();
+        let handle = tokio::spawn(timer.run());
+
+        // The timeouts of a round without QCs lead to the brake step.
+        smr.process(SMRStatus::new(Height(1)).into()).unwrap();
+        for millis in [30, 20, 10] {
+            settle().await;
+            clock.advance(Duration::from_millis(millis));
+            settle().await;
+            smr.process(rx_trigger.try_next().unwrap().unwrap())
+                .unwrap();
+        }
+        smr.assert_view(Height(1), Round(0), Step::Brake);
+
+        // The brake timer repeats until a choke QC continues to the next round.
+        let brake = SMRTrigger::timeout(Step::Brake, Round(0), Height(1)).unwrap();
+        for _ in 0..3 {
+            settle().await;
+            clock.advance(Duration::from_millis(10));
+            settle().await;
+            let timeout = rx_trigger.try_next().unwrap().unwrap();
+            assert_eq!(timeout, brake);
+            smr.process(timeout).unwrap();
+        }
+        smr.process(SMRTrigger::continue_round(Round(1), Height(1)))
+            .unwrap();
+        settle().await;
+        clock.advance(Duration::from_millis(10));
+        settle().await;
+        assert!(rx_trigger.try_next().is_err());
+        smr.assert_view(Height(1), Round(1), Step::Propose);
+
+        smr.shutdown().unwrap();
+        handle.await.unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::smr::smr_types::SMRTrigger;
+use crate::smr::state_machine::StateMachine;
+use crate::types::{ConsensusResult, Height};
+
+/// The storage a driver appends the triggers to before processing them, so that the entries can
+/// be replayed by the [`WalReplayer`] after a restart.
+pub trait WalBackend {
+    /// Append the trigger to the WAL.
+    fn append(&mut self, trigger: &SMRTrigger) -> ConsensusResult<()>;
+}
+
+/// No WAL, every trigger is dropped.
+impl WalBackend for () {
+    fn append(&mut self, _trigger: &SMRTrigger) -> ConsensusResult<()> {
+        Ok(())
+    }
+}
+
+/// An in memory WAL.
+impl WalBackend for Vec<SMRTrigger> {
+    fn append(&mut self, trigger: &SMRTrigger) -> ConsensusResult<()> {
+        self.push(trigger.clone());
+        Ok(())
+    }
+}
+
+impl<W: WalBackend + ?Sized> WalBackend for Box<W> {
+    fn append(&mut self, trigger: &SMRTrigger) -> ConsensusResult<()> {
+        (**self).append(trigger)
+    }
+}
+
+/// The progress of a WAL replay.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Number of entries processed, including the rejected ones.
+    pub processed: u64,
+    /// Number of entries rejected by the SMR.
+    pub rejected: u64,
+    /// The height of the SMR after the processed entries.
+    pub height: Height,
+    /// Whether the replay is cancelled before all entries are processed.
+    pub cancelled: bool,
+}
+
+/// A handle to cancel a running replay between chunks.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayCancel(Arc<AtomicBool>);
+
+impl ReplayCancel {
+    /// Cancel the replay. The current chunk is finished before the replay stops.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Replay the triggers recorded in the WAL in chunks, reporting the progress after each chunk.
+/// The SMR throws the events as in the live run, so the receivers should be drained concurrently
+/// or afterwards. A trigger rejected by the SMR was rejected in the live run too, so it is counted
+/// and skipped.
+#[rustfmt::skip]
+pub struct WalReplayer<F> {
+    chunk_size: usize,
+    progress:   F,
+    cancel:     ReplayCancel,
+}
+
+impl<F> WalReplayer<F>
+where
+    F: FnMut(&ReplayProgress),
+{
+    /// Create a replayer calling `progress` after every `chunk_size` entries.
+    pub fn new(chunk_size: usize, progress: F) -> Self {
+        WalReplayer {
+            chunk_size: chunk_size.max(1),
+            progress,
+            cancel: ReplayCancel::default(),
+        }
+    }
+
+    /// Use the given handle to cancel the replay.
+    pub fn with_cancel(mut self, cancel: ReplayCancel) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Return a handle to cancel the replay.
+    pub fn cancel_handle(&self) -> ReplayCancel {
+        self.cancel.clone()
+    }
+
+    /// Replay the entries into the SMR and return the final progress.
+    pub fn replay<I>(&mut self, smr: &mut StateMachine, entries: I) -> ReplayProgress
+    where
+        I: IntoIterator<Item = SMRTrigger>,
+    {
+        let mut progress = ReplayProgress {
+            height: smr.height(),
+            ..ReplayProgress::default()
+        };
+        let mut entries = entries.into_iter().peekable();
+
+        while entries.peek().is_some() {
+            if self.cancel.is_cancelled() {
+                log::warn!(
+                    "Tendermint: WAL replay cancelled after {} entries",
+                    progress.processed
+                );
+                progress.cancelled = true;
+                return progress;
+            }
+
+            for trigger in entries.by_ref().take(self.chunk_size) {
+                if let Err(e) = smr.process(trigger) {
+                    smr_debug!("Tendermint: WAL replay skip rejected trigger: {}", e);
+                    progress.rejected += 1;
+                }
+                progress.processed += 1;
+            }
+            progress.height = smr.height();
+            (self.progress)(&progress);
+        }
+        progress
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::smr::state_machine::StateMachine;
+    use crate::testing::scenario::scenario;
+    use crate::types::{Height, VoteType};
+
+    use super::{ReplayCancel, ReplayProgress, WalReplayer};
+
+    #[test]
+    fn test_replay() {
+        let hash = Bytes::from(vec![1u8]);
+        let mut wal = scenario();
+        for height in 1..=5 {
+            wal = wal
+                .new_height(height)
+                .proposal(hash.clone())
+                .qc(VoteType::Prevote, hash.clone())
+                .qc(VoteType::Precommit, hash.clone());
+        }
+        let mut entries = wal.build();
+        // A stale trigger is skipped.
+        entries.insert(5, entries[0].clone());
+
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let mut reports = Vec::new();
+        let mut replayer = WalReplayer::new(8, |progress: &ReplayProgress| {
+            reports.push(progress.clone())
+        });
+        let progress = replayer.replay(&mut smr, entries.clone());
+        drop(replayer);
+
+        assert_eq!(progress.processed, 21);
+        assert_eq!(progress.rejected, 1);
+        assert_eq!(progress.height, Height(5));
+        assert!(!progress.cancelled);
+        let processed = reports
+            .iter()
+            .map(|report| report.processed)
+            .collect::<Vec<_>>();
+        assert_eq!(processed, vec![8, 16, 21]);
+
+        // Cancel after the first chunk.
+        let (mut smr, _rx_state, _rx_timer) = StateMachine::new();
+        let cancel = ReplayCancel::default();
+        let handle = cancel.clone();
+        let mut replayer =
+            WalReplayer::new(8, move |_: &ReplayProgress| handle.cancel()).with_cancel(cancel);
+        let progress = replayer.replay(&mut smr, entries);
+        assert_eq!(progress.processed, 8);
+        assert!(progress.cancelled);
+    }
+}
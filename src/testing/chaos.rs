@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use crate::smr::smr_types::{SMREvent, SMRTrigger, TriggerSource, TriggerType};
+use crate::smr::state_machine::StateMachine;
+use crate::types::{Hash, Height};
+
+/// The faults injected into the triggers received from the network. The timeouts and the new
+/// height triggers are local, so they are always passed through in order.
+#[derive(Clone, Debug, Default)]
+pub struct ChaosPolicy {
+    /// The probability that a trigger is delivered twice.
+    pub duplicate_rate: f64,
+    /// The probability that a trigger is corrupted. The driver fails to verify a corrupted
+    /// message and drops it, so the trigger is retransmitted after another delay.
+    pub corrupt_rate: f64,
+    /// The probability that a trigger is delayed, which reorders it after the later ones.
+    pub delay_rate: f64,
+    /// The maximum number of triggers that a delayed trigger is moved behind.
+    pub max_delay: usize,
+}
+
+/// The statistics of the injected faults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChaosStats {
+    /// Number of duplicated triggers.
+    pub duplicated: u64,
+    /// Number of corrupted and retransmitted triggers.
+    pub corrupted: u64,
+    /// Number of delayed triggers.
+    pub delayed: u64,
+    /// Number of triggers rejected by the SMR.
+    pub rejected: u64,
+}
+
+/// A trigger middleware which reorders, duplicates and corrupts the triggers of a driver
+/// according to a seeded policy, so that the same seed replays the same faults.
+#[rustfmt::skip]
+#[derive(Clone, Debug)]
+pub struct Chaos {
+    policy: ChaosPolicy,
+    seed:   u64,
+    stats:  ChaosStats,
+}
+
+impl Chaos {
+    /// Create a middleware with the given policy and seed.
+    pub fn new(policy: ChaosPolicy, seed: u64) -> Self {
+        Chaos {
+            policy,
+            seed,
+            stats: ChaosStats::default(),
+        }
+    }
+
+    /// Return the statistics of the faults injected so far.
+    pub fn stats(&self) -> &ChaosStats {
+        &self.stats
+    }
+
+    /// Inject the faults into the triggers and return them in the order of delivery.
+    pub fn apply(&mut self, triggers: Vec<SMRTrigger>) -> Vec<SMRTrigger> {
+        let mut slots: Vec<(usize, usize, SMRTrigger)> = Vec::with_capacity(triggers.len());
+        for (index, trigger) in triggers.into_iter().enumerate() {
+            if !is_network(&trigger) {
+                slots.push((index, slots.len(), trigger));
+                continue;
+            }
+
+            let copies = if self.chance(self.policy.duplicate_rate) {
+                self.stats.duplicated += 1;
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                let time = index + self.delay();
+                slots.push((time, slots.len(), trigger.clone()));
+            }
+        }
+
+        slots.sort_by_key(|(time, seq, _)| (*time, *seq));
+        slots.into_iter().map(|(_, _, trigger)| trigger).collect()
+    }
+
+    /// Inject the faults into the triggers and process them in order. A trigger rejected by the
+    /// SMR is counted and skipped, as a driver does for a peer message.
+    pub fn run(&mut self, smr: &mut StateMachine, triggers: Vec<SMRTrigger>) {
+        for trigger in self.apply(triggers) {
+            if smr.process(trigger).is_err() {
+                self.stats.rejected += 1;
+            }
+        }
+    }
+
+    fn delay(&mut self) -> usize {
+        let mut delay = 0;
+        if self.chance(self.policy.delay_rate) {
+            self.stats.delayed += 1;
+            delay += (self.next_u64() % (self.policy.max_delay as u64 + 1)) as usize;
+        }
+        while self.chance(self.policy.corrupt_rate) {
+            self.stats.corrupted += 1;
+            delay += 1 + (self.next_u64() % (self.policy.max_delay as u64 + 1)) as usize;
+        }
+        delay
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 <= rate.min(0.99)
+    }
+
+    /// SplitMix64, which keeps the testing utilities free of a random number dependency.
+    fn next_u64(&mut self) -> u64 {
+        self.seed = self.seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+fn is_network(trigger: &SMRTrigger) -> bool {
+    trigger.source == TriggerSource::State
+        && !matches!(trigger.trigger_type, TriggerType::NewHeight(_))
+}
+
+/// Return the committed blocks in the events of a replica.
+pub fn commits(events: &[SMREvent]) -> Vec<(Height, Hash)> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            SMREvent::Commit {
+                height, block_hash, ..
+            } => Some((*height, block_hash.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Assert the safety of the replicas, given the events of each one. Every replica commits
+/// strictly increasing heights, and no two replicas commit different blocks at the same height.
+pub fn assert_safety(replicas: &[Vec<SMREvent>]) {
+    let mut committed: HashMap<Height, (usize, Hash)> = HashMap::new();
+    for (id, events) in replicas.iter().enumerate() {
+        let mut last = None;
+        for (height, hash) in commits(events) {
+            assert!(
+                last.is_none_or(|last| height > last),
+                "replica {} commits height {} after {:?}",
+                id,
+                height,
+                last
+            );
+            last = Some(height);
+
+            let (first, first_hash) = committed.entry(height).or_insert((id, hash.clone()));
+            assert_eq!(
+                *first_hash, hash,
+                "replicas {} and {} commit different blocks at height {}",
+                first, id, height
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::smr::smr_types::{SMREvent, SMRTrigger, TriggerType};
+    use crate::smr::state_machine::StateMachine;
+    use crate::testing::scenario::scenario;
+    use crate::types::VoteType;
+
+    use super::{assert_safety, commits, is_network, Chaos, ChaosPolicy};
+
+    fn triggers(heights: u64) -> Vec<SMRTrigger> {
+        (1..=heights)
+            .fold(scenario(), |scenario, height| {
+                let hash = Bytes::from(vec![height as u8]);
+                scenario
+                    .new_height(height)
+                    .proposal(hash.clone())
+                    .qc(VoteType::Prevote, hash.clone())
+                    .qc(VoteType::Precommit, hash)
+            })
+            .build()
+    }
+
+    fn run(chaos: Option<&mut Chaos>, triggers: Vec<SMRTrigger>) -> Vec<SMREvent> {
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        match chaos {
+            Some(chaos) => chaos.run(&mut smr, triggers),
+            None => triggers.into_iter().for_each(|t| smr.process(t).unwrap()),
+        }
+        std::iter::from_fn(|| rx_state.try_next()).collect()
+    }
+
+    #[test]
+    fn test_chaos() {
+        let policy = ChaosPolicy {
+            duplicate_rate: 0.3,
+            corrupt_rate: 0.2,
+            delay_rate: 0.3,
+            max_delay: 3,
+        };
+        let honest = run(None, triggers(20));
+        assert_eq!(commits(&honest).len(), 20);
+
+        let mut replicas = vec![honest];
+        for seed in 0..8 {
+            let mut chaos = Chaos::new(policy.clone(), seed);
+            replicas.push(run(Some(&mut chaos), triggers(20)));
+            let stats = chaos.stats();
+            assert!(stats.duplicated > 0 && stats.corrupted > 0 && stats.delayed > 0);
+        }
+        assert_safety(&replicas);
+    }
+
+    #[test]
+    fn test_chaos_seed() {
+        let policy = ChaosPolicy {
+            duplicate_rate: 0.5,
+            corrupt_rate: 0.0,
+            delay_rate: 0.5,
+            max_delay: 2,
+        };
+        let first = Chaos::new(policy.clone(), 7).apply(triggers(5));
+        let second = Chaos::new(policy, 7).apply(triggers(5));
+        assert_eq!(first, second);
+        assert!(first.len() > triggers(5).len());
+        assert!(matches!(first[0].trigger_type, TriggerType::NewHeight(_)));
+
+        let unchanged = Chaos::new(ChaosPolicy::default(), 7).apply(triggers(5));
+        assert_eq!(unchanged, triggers(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "commit different blocks")]
+    fn test_assert_safety() {
+        let fork = triggers(1)
+            .into_iter()
+            .map(|mut trigger| {
+                if is_network(&trigger) {
+                    trigger.hash = Bytes::from(vec![9u8]);
+                }
+                trigger
+            })
+            .collect();
+        assert_safety(&[run(None, triggers(1)), run(None, fork)]);
+    }
+}
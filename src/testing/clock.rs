@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::time::Instant;
+
+use crate::smr::lock;
+use crate::smr::timer::Clock;
+
+/// A mock clock of the timer, which only advances by `advance`, so that the tests drive the
+/// timeouts deterministically without real sleeps. The clones share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<MockTime>>);
+
+#[rustfmt::skip]
+#[derive(Debug)]
+struct MockTime {
+    now:    Instant,
+    sleeps: Vec<Waker>,
+}
+
+impl MockClock {
+    /// Create a mock clock starting from now.
+    pub fn new() -> Self {
+        MockClock(Arc::new(Mutex::new(MockTime {
+            now: Instant::now(),
+            sleeps: Vec::new(),
+        })))
+    }
+
+    /// Advance the clock, which wakes the sleeps to check their deadlines.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = lock(&self.0);
+        time.now += duration;
+        time.sleeps.drain(..).for_each(Waker::wake);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        lock(&self.0).now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(MockSleep {
+            clock: self.clone(),
+            deadline,
+        })
+    }
+}
+
+#[rustfmt::skip]
+struct MockSleep {
+    clock:    MockClock,
+    deadline: Instant,
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut time = lock(&self.clock.0);
+        if time.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        time.sleeps.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+
+use crate::smr::lock;
+use crate::types::{Hash, Hasher};
+
+/// A mock hasher recording every hashed input. By default it returns the input itself as the
+/// hash, unless a response is programmed for the input or a failure is injected.
+#[rustfmt::skip]
+#[derive(Default)]
+pub struct MockHasher {
+    calls:     Mutex<Vec<Bytes>>,
+    responses: Mutex<HashMap<Bytes, Hash>>,
+    failures:  Mutex<usize>,
+}
+
+impl MockHasher {
+    /// Create a mock hasher.
+    pub fn new() -> Self {
+        MockHasher::default()
+    }
+
+    /// Program the hash of the given input.
+    pub fn respond(&self, input: &[u8], hash: Hash) {
+        lock(&self.responses).insert(Bytes::copy_from_slice(input), hash);
+    }
+
+    /// Make the next `times` calls return an empty hash, which matches no proposal.
+    pub fn fail_next(&self, times: usize) {
+        *lock(&self.failures) = times;
+    }
+
+    /// Return the inputs of all calls in order.
+    pub fn calls(&self) -> Vec<Bytes> {
+        lock(&self.calls).clone()
+    }
+}
+
+impl Hasher for MockHasher {
+    fn hash(&self, bytes: &[u8]) -> Hash {
+        let input = Bytes::copy_from_slice(bytes);
+        lock(&self.calls).push(input.clone());
+
+        let mut failures = lock(&self.failures);
+        if *failures > 0 {
+            *failures -= 1;
+            return Hash::new();
+        }
+
+        lock(&self.responses).get(&input).cloned().unwrap_or(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::smr::state_machine::verify_payload;
+    use crate::types::Hasher;
+
+    use super::MockHasher;
+
+    #[test]
+    fn test_mock_hasher() {
+        let hasher = MockHasher::new();
+        assert_eq!(hasher.hash(&[1, 2]), Bytes::from(vec![1u8, 2]));
+
+        hasher.respond(&[3], Bytes::from(vec![4u8]));
+        assert!(verify_payload(&hasher, &Bytes::from(vec![4u8]), &[3]).is_ok());
+
+        hasher.fail_next(1);
+        assert!(verify_payload(&hasher, &Bytes::from(vec![4u8]), &[3]).is_err());
+        assert!(verify_payload(&hasher, &Bytes::from(vec![4u8]), &[3]).is_ok());
+
+        assert_eq!(hasher.calls().len(), 4);
+        assert_eq!(hasher.calls()[0], Bytes::from(vec![1u8, 2]));
+    }
+}
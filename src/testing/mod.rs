@@ -0,0 +1,9 @@
+/// A seeded trigger middleware injecting faults, and the safety assertions under the faults.
+pub mod chaos;
+/// A manually advanced clock for the timer tests. Unstable.
+#[cfg(feature = "timer")]
+pub mod clock;
+/// Mock implementations of the integration traits.
+pub mod mocks;
+/// A builder of trigger sequences for scenario tests.
+pub mod scenario;
@@ -0,0 +1,168 @@
+use crate::smr::smr_types::{QcValue, SMRStatus, SMRTrigger, Step};
+use crate::smr::state_machine::StateMachine;
+use crate::types::{ConsensusResult, Hash, Height, Round, VoteType, INIT_HEIGHT, INIT_ROUND};
+
+/// Start a scenario from the initial height and round.
+pub fn scenario() -> Scenario {
+    Scenario::default()
+}
+
+/// A builder of trigger sequences which fills the heights and rounds in. The round goes to the
+/// next one after a nil precommit QC or a precommit timeout, as the SMR does.
+#[rustfmt::skip]
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    height:   Height,
+    round:    Round,
+    triggers: Vec<SMRTrigger>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            height: INIT_HEIGHT,
+            round: INIT_ROUND,
+            triggers: Vec::new(),
+        }
+    }
+}
+
+impl Scenario {
+    /// Goto the given height.
+    pub fn new_height(self, height: u64) -> Self {
+        self.new_height_with(SMRStatus::new(Height(height)))
+    }
+
+    /// Goto the height of the given status.
+    pub fn new_height_with(mut self, status: SMRStatus) -> Self {
+        self.height = status.height;
+        self.triggers.push(status.into());
+        self.round = INIT_ROUND;
+        self
+    }
+
+    /// Set the round of the following triggers.
+    pub fn round(mut self, round: u64) -> Self {
+        self.round = Round(round);
+        self
+    }
+
+    /// Receive a proposal of the current round.
+    pub fn proposal(mut self, hash: Hash) -> Self {
+        let proposal = SMRTrigger::proposal(hash, None, self.round, self.height);
+        self.triggers.push(proposal);
+        self
+    }
+
+    /// Receive a proposal of the current round with a PoLC of the given round.
+    pub fn proposal_with_lock(mut self, hash: Hash, lock_round: u64) -> Self {
+        let lock_round = Some(Round(lock_round));
+        let proposal = SMRTrigger::proposal(hash, lock_round, self.round, self.height);
+        self.triggers.push(proposal);
+        self
+    }
+
+    /// Receive a QC of the current round. An empty hash is a nil QC.
+    pub fn qc(mut self, vote_type: VoteType, value: impl Into<QcValue>) -> Self {
+        let value = value.into();
+        let next_round = vote_type == VoteType::Precommit && value.is_nil();
+        let qc = SMRTrigger::qc(vote_type, value, self.round, self.height);
+        self.triggers.push(qc);
+        if next_round {
+            self.round = self.round.next();
+        }
+        self
+    }
+
+    /// Time out the propose step.
+    pub fn timer_propose(mut self) -> Self {
+        self.timeout(Step::Propose);
+        self
+    }
+
+    /// Time out the prevote step.
+    pub fn timer_prevote(mut self) -> Self {
+        self.timeout(Step::Prevote);
+        self
+    }
+
+    /// Time out the precommit step, which goes to the next round.
+    pub fn timer_precommit(mut self) -> Self {
+        self.timeout(Step::Precommit);
+        self.round = self.round.next();
+        self
+    }
+
+    /// Return the triggers.
+    pub fn build(self) -> Vec<SMRTrigger> {
+        self.triggers
+    }
+
+    /// Process the triggers in order and stop at the first error.
+    pub fn run(self, smr: &mut StateMachine) -> ConsensusResult<()> {
+        self.triggers
+            .into_iter()
+            .try_for_each(|trigger| smr.process(trigger))
+    }
+
+    fn timeout(&mut self, step: Step) {
+        let timeout = SMRTrigger::timeout(step, self.round, self.height);
+        self.triggers.push(timeout.expect("step with timeout"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::smr::smr_types::{QcValue, SMREvent, TriggerSource, TriggerType};
+    use crate::smr::state_machine::StateMachine;
+    use crate::types::{Height, Round, VoteType};
+
+    use super::scenario;
+
+    #[test]
+    fn test_build() {
+        let hash = Bytes::from(vec![1u8]);
+        let triggers = scenario()
+            .new_height(1)
+            .timer_propose()
+            .timer_prevote()
+            .timer_precommit()
+            .proposal(hash.clone())
+            .qc(VoteType::Prevote, hash.clone())
+            .qc(VoteType::Precommit, QcValue::Nil)
+            .proposal_with_lock(hash, 1)
+            .build();
+
+        let rounds = triggers.iter().map(|t| t.round.0).collect::<Vec<_>>();
+        assert_eq!(rounds, vec![0, 0, 0, 0, 1, 1, 1, 2]);
+        assert_eq!(triggers[0].height, Height(0));
+        assert!(triggers[1..].iter().all(|t| t.height == Height(1)));
+        assert_eq!(triggers[2].source, TriggerSource::Timer);
+        assert_eq!(triggers[5].trigger_type, TriggerType::PrevoteQC);
+        assert_eq!(triggers[7].lock_round, Some(Round(1)));
+    }
+
+    #[test]
+    fn test_run() {
+        let (mut smr, mut rx_state, _rx_timer) = StateMachine::new();
+        let hash = Bytes::from(vec![1u8]);
+        scenario()
+            .new_height(1)
+            .timer_propose()
+            .timer_prevote()
+            .timer_precommit()
+            .proposal(hash.clone())
+            .qc(VoteType::Prevote, hash.clone())
+            .qc(VoteType::Precommit, hash.clone())
+            .run(&mut smr)
+            .unwrap();
+
+        let mut events = std::iter::from_fn(|| rx_state.try_next());
+        assert!(events.any(|event| matches!(
+            event,
+            SMREvent::Commit { height: Height(1), block_hash, .. } if block_hash == hash
+        )));
+    }
+}
@@ -1,21 +1,216 @@
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{Add, Sub};
+use std::time::Duration;
+
 use bytes::Bytes;
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
 use crate::error::ConsensusError;
-use crate::smr::smr_types::{Step, TriggerType};
+use crate::smr::smr_types::{GenericTriggerType, Step};
 
 /// Address type.
 pub type Address = Bytes;
-/// Hash type.
+/// Hash type, the default of the generic hash types.
 pub type Hash = Bytes;
 /// Signature type.
 pub type Signature = Bytes;
 
+/// Block height.
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Display,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(transparent)]
+pub struct Height(pub u64);
+
+/// Round of a height.
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Display,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(transparent)]
+pub struct Round(pub u64);
+
+macro_rules! impl_number {
+    ($name: ident) => {
+        impl $name {
+            /// Return the next one.
+            pub fn next(self) -> Self {
+                $name(self.0 + 1)
+            }
+
+            /// Return the next one, or `None` on overflow.
+            pub fn checked_next(self) -> Option<Self> {
+                self.0.checked_add(1).map($name)
+            }
+
+            /// Add the given number, or return `None` on overflow.
+            pub fn checked_add(self, rhs: u64) -> Option<Self> {
+                self.0.checked_add(rhs).map($name)
+            }
+
+            /// Subtract the given number, or return `None` on underflow.
+            pub fn checked_sub(self, rhs: u64) -> Option<Self> {
+                self.0.checked_sub(rhs).map($name)
+            }
+        }
+
+        impl Add<u64> for $name {
+            type Output = Self;
+
+            fn add(self, rhs: u64) -> Self {
+                $name(self.0 + rhs)
+            }
+        }
+
+        impl Sub<u64> for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: u64) -> Self {
+                $name(self.0 - rhs)
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(n: u64) -> Self {
+                $name(n)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(n: $name) -> u64 {
+                n.0
+            }
+        }
+    };
+}
+
+impl_number!(Height);
+impl_number!(Round);
+
+/// A block hash of the SMR, which is generic so that a chain with fixed size hashes avoids the
+/// heap allocation of `Bytes`. The empty hash stands for nil, such as a nil QC or no proposal, so
+/// a fixed size hash reserves the all zero value for it.
+pub trait ConsensusHash:
+    Clone + Debug + PartialEq + Eq + std::hash::Hash + AsRef<[u8]> + Send + Sync + 'static
+{
+    /// Return the empty hash.
+    fn empty() -> Self;
+
+    /// Return whether it is the empty hash.
+    fn is_empty(&self) -> bool;
+}
+
+impl ConsensusHash for Bytes {
+    fn empty() -> Self {
+        Bytes::new()
+    }
+
+    fn is_empty(&self) -> bool {
+        Bytes::is_empty(self)
+    }
+}
+
+impl<const N: usize> ConsensusHash for [u8; N] {
+    fn empty() -> Self {
+        [0u8; N]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.iter().all(|byte| *byte == 0)
+    }
+}
+
+/// Hash function of the block payload.
+pub trait Hasher<H = Hash> {
+    /// Hash the given bytes.
+    fn hash(&self, bytes: &[u8]) -> H;
+}
+
+/// Format bytes as lower case hex without allocating, for the log and display sites on the hot
+/// path. The debug format is quoted, the same as the debug format of an encoded hex string.
+#[derive(Clone, Copy)]
+pub(crate) struct HexFmt<'a>(pub(crate) &'a [u8]);
+
+impl fmt::Display for HexFmt<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut buf = [0u8; 64];
+        for chunk in self.0.chunks(buf.len() / 2) {
+            for (i, byte) in chunk.iter().enumerate() {
+                buf[2 * i] = DIGITS[(byte >> 4) as usize];
+                buf[2 * i + 1] = DIGITS[(byte & 0xf) as usize];
+            }
+            // The buffer only holds ASCII hex digits.
+            f.write_str(std::str::from_utf8(&buf[..2 * chunk.len()]).map_err(|_| fmt::Error)?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for HexFmt<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
 pub type ConsensusResult<T> = std::result::Result<T, ConsensusError>;
 
-pub const INIT_HEIGHT: u64 = 0;
-pub const INIT_ROUND: u64 = 0;
+pub const INIT_HEIGHT: Height = Height(0);
+pub const INIT_ROUND: Round = Round(0);
+/// The maximum ratio of a step timeout to the height interval.
+pub const MAX_DURATION_RATIO: u64 = 100;
+/// The maximum step timeout of `TimeoutConfig`.
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// The genesis parameters of the SMR.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GenesisConfig {
+    /// The height that the SMR starts from. The first new height trigger must be higher than it.
+    pub init_height: Height,
+    /// The round that each height starts from.
+    pub init_round: Round,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        GenesisConfig {
+            init_height: INIT_HEIGHT,
+            init_round: INIT_ROUND,
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Create a genesis configuration.
+    pub fn new(init_height: Height, init_round: Round) -> Self {
+        GenesisConfig {
+            init_height,
+            init_round,
+        }
+    }
+}
 
 /// Vote or QC types. Prevote and precommit QC will promise the rightness and the final consistency
 /// of overlord consensus protocol.
@@ -38,11 +233,11 @@ impl From<VoteType> for u8 {
     }
 }
 
-impl From<VoteType> for TriggerType {
-    fn from(v: VoteType) -> TriggerType {
+impl<H> From<VoteType> for GenericTriggerType<H> {
+    fn from(v: VoteType) -> GenericTriggerType<H> {
         match v {
-            VoteType::Prevote => TriggerType::PrevoteQC,
-            VoteType::Precommit => TriggerType::PrecommitQC,
+            VoteType::Prevote => GenericTriggerType::PrevoteQC,
+            VoteType::Precommit => GenericTriggerType::PrecommitQC,
         }
     }
 }
@@ -69,42 +264,47 @@ impl TryFrom<u8> for VoteType {
 }
 
 /// The reason of overlord view change.
-#[derive(Serialize, Deserialize, Clone, Debug, Display)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Display, PartialEq, Eq)]
 pub enum ViewChangeReason {
-    ///
+    /// No proposal is received before the propose timeout.
     #[display(fmt = "Do not receive proposal from network")]
     NoProposalFromNetwork,
 
-    ///
+    /// No prevote QC is received before the prevote timeout.
     #[display(fmt = "Do not receive Prevote QC from network")]
     NoPrevoteQCFromNetwork,
 
-    ///
+    /// No precommit QC is received before the precommit timeout.
     #[display(fmt = "Do not receive precommit QC from network")]
     NoPrecommitQCFromNetwork,
 
-    ///
+    /// The proposal does not pass the block check.
     #[display(fmt = "Check the block not pass")]
     CheckBlockNotPass,
 
-    ///
+    /// A prevote QC of a higher round, with the old round and the QC round.
     #[display(fmt = "Update from a higher round prevote QC from {} to {}", _0, _1)]
-    UpdateFromHigherPrevoteQC(u64, u64),
+    UpdateFromHigherPrevoteQC(Round, Round),
 
-    ///
+    /// A precommit QC of a higher round, with the old round and the QC round.
     #[display(fmt = "Update from a higher round precommit QC from {} to {}", _0, _1)]
-    UpdateFromHigherPrecommitQC(u64, u64),
+    UpdateFromHigherPrecommitQC(Round, Round),
 
-    ///
+    /// A choke QC of a higher round, with the old round and the QC round.
     #[display(fmt = "Update from a higher round choke QC from {} to {}", _0, _1)]
-    UpdateFromHigherChokeQC(u64, u64),
+    UpdateFromHigherChokeQC(Round, Round),
 
-    ///
+    /// A round skip, with the old round and the round skipped to.
+    #[display(fmt = "Skip from round {} to {} by f+1 higher round messages", _0, _1)]
+    RoundSkip(Round, Round),
+
+    /// The votes received by the leader are below the threshold.
     #[display(fmt = "{:?} votes count is below threshold", _0)]
     LeaderReceivedVoteBelowThreshold(VoteType),
 
-    ///
+    /// Other reasons, such as a new height.
     #[display(fmt = "other reasons")]
+    #[default]
     Others,
 }
 
@@ -136,4 +336,320 @@ impl DurationConfig {
             brake_ratio,
         }
     }
+
+    /// Check that the ratios are non-zero and not higher than `MAX_DURATION_RATIO`, and that the
+    /// timeout of a step is not shorter than the timeout of the next step, which is
+    /// `propose_ratio >= prevote_ratio >= precommit_ratio`. A zero ratio times out a step at once
+    /// and makes the SMR skip rounds in a storm.
+    pub fn validate(&self) -> ConsensusResult<()> {
+        let ratios = [
+            ("propose", self.propose_ratio),
+            ("prevote", self.prevote_ratio),
+            ("precommit", self.precommit_ratio),
+            ("brake", self.brake_ratio),
+        ];
+        for (step, ratio) in ratios.iter() {
+            if *ratio == 0 || *ratio > MAX_DURATION_RATIO {
+                return Err(ConsensusError::DurationConfigErr(format!(
+                    "{} ratio {} is out of range 1..={}",
+                    step, ratio, MAX_DURATION_RATIO
+                )));
+            }
+        }
+
+        if self.propose_ratio < self.prevote_ratio {
+            return Err(ConsensusError::DurationConfigErr(format!(
+                "propose ratio {} is lower than prevote ratio {}",
+                self.propose_ratio, self.prevote_ratio
+            )));
+        } else if self.prevote_ratio < self.precommit_ratio {
+            return Err(ConsensusError::DurationConfigErr(format!(
+                "prevote ratio {} is lower than precommit ratio {}",
+                self.prevote_ratio, self.precommit_ratio
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The setting of the timeout of each step in absolute durations, as an alternative to the ratios
+/// of the height interval in `DurationConfig`.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    /// Propose timeout.
+    pub propose: Duration,
+    /// Prevote timeout.
+    pub prevote: Duration,
+    /// Precommit timeout.
+    pub precommit: Duration,
+    /// Retry choke message timeout.
+    pub brake: Duration,
+}
+
+impl TimeoutConfig {
+    /// Create a consensus timeout configuration in absolute durations.
+    pub fn new(propose: Duration, prevote: Duration, precommit: Duration, brake: Duration) -> Self {
+        TimeoutConfig {
+            propose,
+            prevote,
+            precommit,
+            brake,
+        }
+    }
+
+    /// Check that the timeouts are non-zero and not longer than `MAX_TIMEOUT`, and ordered as the
+    /// ratios of `DurationConfig::validate`.
+    pub fn validate(&self) -> ConsensusResult<()> {
+        let timeouts = [
+            ("propose", self.propose),
+            ("prevote", self.prevote),
+            ("precommit", self.precommit),
+            ("brake", self.brake),
+        ];
+        for (step, timeout) in timeouts.iter() {
+            if timeout.is_zero() || *timeout > MAX_TIMEOUT {
+                return Err(ConsensusError::DurationConfigErr(format!(
+                    "{} timeout {:?} is out of range (0, {:?}]",
+                    step, timeout, MAX_TIMEOUT
+                )));
+            }
+        }
+
+        if self.propose < self.prevote {
+            return Err(ConsensusError::DurationConfigErr(format!(
+                "propose timeout {:?} is shorter than prevote timeout {:?}",
+                self.propose, self.prevote
+            )));
+        } else if self.prevote < self.precommit {
+            return Err(ConsensusError::DurationConfigErr(format!(
+                "prevote timeout {:?} is shorter than precommit timeout {:?}",
+                self.prevote, self.precommit
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A governance change of the consensus parameters. It is included in a committed status and
+/// applied from the activation height.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ParamChange {
+    /// The version of the parameters, which must increase with every change.
+    pub version: u64,
+    /// The height from which the change is applied.
+    pub activation_height: Height,
+    /// New height interval.
+    pub new_interval: Option<u64>,
+    /// New timeout configuration.
+    pub new_config: Option<DurationConfig>,
+    /// New timeout configuration in absolute durations, which takes precedence over `new_config`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_timeouts: Option<TimeoutConfig>,
+    /// New max round of a height.
+    pub max_round: Option<Round>,
+}
+
+impl ParamChange {
+    /// Validate the change announced at `height` against the current parameters `version`.
+    pub fn validate(&self, height: Height, version: u64) -> ConsensusResult<()> {
+        if self.version <= version {
+            return Err(ConsensusError::ParamChangeErr(format!(
+                "Version {} is not higher than {}",
+                self.version, version
+            )));
+        } else if self.activation_height <= height {
+            return Err(ConsensusError::ParamChangeErr(format!(
+                "Activation height {} is not higher than {}",
+                self.activation_height, height
+            )));
+        } else if self.new_interval == Some(0) {
+            return Err(ConsensusError::ParamChangeErr("Zero interval".to_string()));
+        }
+
+        if let Some(config) = &self.new_config {
+            config
+                .validate()
+                .map_err(|e| ConsensusError::ParamChangeErr(e.to_string()))?;
+        }
+        if let Some(timeouts) = &self.new_timeouts {
+            timeouts
+                .validate()
+                .map_err(|e| ConsensusError::ParamChangeErr(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ConsensusError;
+
+    use std::fmt::Write;
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use hummer::coding::hex_encode;
+
+    use super::{
+        DurationConfig, Height, HexFmt, ParamChange, Round, TimeoutConfig, MAX_DURATION_RATIO,
+        MAX_TIMEOUT,
+    };
+
+    #[test]
+    fn test_number() {
+        assert_eq!(Height(1).next(), Height(2));
+        assert_eq!(Round(1) + 2, Round(3));
+        assert_eq!(Round(3) - 1, Round(2));
+        assert_eq!(u64::from(Height(5)), 5);
+        assert_eq!(Round::from(5), Round(5));
+        assert_eq!(Height(7).to_string(), "7");
+        assert!(Round(1) < Round(2));
+
+        assert_eq!(Round(1).checked_next(), Some(Round(2)));
+        assert_eq!(Round(u64::MAX).checked_next(), None);
+        assert_eq!(Height(u64::MAX - 1).checked_add(1), Some(Height(u64::MAX)));
+        assert_eq!(Height(u64::MAX).checked_add(1), None);
+        assert_eq!(Round(0).checked_sub(1), None);
+    }
+
+    #[test]
+    fn test_hex_fmt() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(HexFmt(&bytes).to_string(), hex_encode(&bytes));
+        assert_eq!(
+            format!("{:?}", HexFmt(&bytes)),
+            format!("{:?}", hex_encode(&bytes))
+        );
+        assert_eq!(HexFmt(&[]).to_string(), "");
+        assert_eq!(format!("{:?}", HexFmt(&[0x0a, 0xff])), r#""0aff""#);
+    }
+
+    /// Check that formatting a hash by `HexFmt` is faster than by `hex_encode`. Run it by
+    /// `cargo test --release -- --ignored bench_hex_fmt`.
+    #[test]
+    #[ignore]
+    fn bench_hex_fmt() {
+        const HASHES: usize = 1_000_000;
+        let hash = Bytes::from(vec![0xabu8; 32]);
+        let mut out = String::with_capacity(128);
+
+        let start = Instant::now();
+        for _ in 0..HASHES {
+            out.clear();
+            write!(out, "{:?}", hex_encode(hash.clone())).unwrap();
+        }
+        let encoded = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..HASHES {
+            out.clear();
+            write!(out, "{:?}", HexFmt(&hash)).unwrap();
+        }
+        let lazy = start.elapsed();
+        assert!(
+            lazy < encoded,
+            "HexFmt {:?}, hex_encode {:?}",
+            lazy,
+            encoded
+        );
+    }
+
+    #[test]
+    fn test_param_change() {
+        let change = ParamChange {
+            version: 2,
+            activation_height: Height(10),
+            new_interval: Some(3000),
+            new_config: None,
+            new_timeouts: None,
+            max_round: None,
+        };
+        assert!(change.validate(Height(5), 1).is_ok());
+        assert!(change.validate(Height(5), 2).is_err());
+        assert!(change.validate(Height(10), 1).is_err());
+
+        let zero = ParamChange {
+            new_interval: Some(0),
+            ..change.clone()
+        };
+        assert!(matches!(
+            zero.validate(Height(5), 1),
+            Err(ConsensusError::ParamChangeErr(_))
+        ));
+
+        let storm = ParamChange {
+            new_config: Some(DurationConfig::new(24, 0, 5, 3)),
+            ..change.clone()
+        };
+        assert!(matches!(
+            storm.validate(Height(5), 1),
+            Err(ConsensusError::ParamChangeErr(_))
+        ));
+        let storm = ParamChange {
+            new_timeouts: Some(TimeoutConfig::default()),
+            ..change
+        };
+        assert!(matches!(
+            storm.validate(Height(5), 1),
+            Err(ConsensusError::ParamChangeErr(_))
+        ));
+    }
+
+    #[test]
+    fn test_timeout_config() {
+        let ms = Duration::from_millis;
+        assert!(TimeoutConfig::new(ms(3000), ms(1000), ms(500), ms(300))
+            .validate()
+            .is_ok());
+        assert!(TimeoutConfig::default().validate().is_err());
+
+        let check = |config: TimeoutConfig, msg: &str| match config.validate() {
+            Err(ConsensusError::DurationConfigErr(e)) => assert_eq!(e, msg),
+            res => panic!("unexpected {:?}", res),
+        };
+        check(
+            TimeoutConfig::new(ms(3000), ms(0), ms(500), ms(300)),
+            "prevote timeout 0ns is out of range (0, 3600s]",
+        );
+        check(
+            TimeoutConfig::new(MAX_TIMEOUT + ms(1), ms(1000), ms(500), ms(300)),
+            "propose timeout 3600.001s is out of range (0, 3600s]",
+        );
+        check(
+            TimeoutConfig::new(ms(500), ms(1000), ms(500), ms(300)),
+            "propose timeout 500ms is shorter than prevote timeout 1s",
+        );
+        check(
+            TimeoutConfig::new(ms(3000), ms(500), ms(1000), ms(300)),
+            "prevote timeout 500ms is shorter than precommit timeout 1s",
+        );
+    }
+
+    #[test]
+    fn test_duration_config() {
+        assert!(DurationConfig::new(24, 10, 5, 3).validate().is_ok());
+        assert!(DurationConfig::new(5, 5, 5, 5).validate().is_ok());
+        assert!(DurationConfig::default().validate().is_err());
+
+        let check = |config: DurationConfig, msg: &str| match config.validate() {
+            Err(ConsensusError::DurationConfigErr(e)) => assert_eq!(e, msg),
+            res => panic!("unexpected {:?}", res),
+        };
+        check(
+            DurationConfig::new(24, 0, 5, 3),
+            "prevote ratio 0 is out of range 1..=100",
+        );
+        check(
+            DurationConfig::new(24, 10, 5, MAX_DURATION_RATIO + 1),
+            "brake ratio 101 is out of range 1..=100",
+        );
+        check(
+            DurationConfig::new(5, 10, 5, 3),
+            "propose ratio 5 is lower than prevote ratio 10",
+        );
+        check(
+            DurationConfig::new(24, 5, 10, 3),
+            "prevote ratio 5 is lower than precommit ratio 10",
+        );
+    }
 }